@@ -12,14 +12,33 @@ include!(concat!(env!("OUT_DIR"), "/_.rs"));
 pub enum ImuCommand {
     /// Get IMU device information
     Info,
-    /// Start IMU data publishing
-    Start,
-    /// Stop IMU data publishing
-    Stop,
-    /// Read IMU data
+    /// Open a streaming session, the IMU publishes while any session is alive
+    Start {
+        /// Seconds the session lives without a keepalive
+        #[arg(long)]
+        ttl: Option<u32>,
+    },
+    /// Keep a streaming session alive
+    Keepalive {
+        /// Session returned by start
+        session: String,
+    },
+    /// Close a streaming session
+    Stop {
+        /// Session returned by start
+        session: Option<String>,
+        /// Close every client session, the IMU stops unless UDP sinks or calibration hold it
+        #[arg(long)]
+        all: bool,
+    },
+    /// Read IMU data, holding a session while reading
     Read,
 }
 
+// Keepalive well within the ttl the read command asks for
+const READ_SESSION_TTL: u32 = 10;
+const READ_KEEPALIVE_INTERVAL: u64 = 3;
+
 /// Handle IMU subcommand
 pub async fn handle_imu_command(
     device_id: Option<String>,
@@ -53,17 +72,31 @@ pub async fn handle_imu_command(
                 std::process::exit(1);
             }
         }
-        Some(ImuCommand::Start) => {
+        Some(ImuCommand::Start { ttl }) => {
             if let Some(device_id) = device_id {
-                start_imu_data_publishing(&device_id, host, port).await?;
+                let session = start_imu_data_publishing(&device_id, ttl, host, port).await?;
+                println!("{}", serde_json::to_string_pretty(&session)?);
             } else {
                 eprintln!("Error: device_id is required for start command");
                 std::process::exit(1);
             }
         }
-        Some(ImuCommand::Stop) => {
+        Some(ImuCommand::Keepalive { session }) => {
+            if let Some(device_id) = device_id {
+                let session = keepalive_imu_session(&device_id, &session, host, port).await?;
+                println!("{}", serde_json::to_string_pretty(&session)?);
+            } else {
+                eprintln!("Error: device_id is required for keepalive command");
+                std::process::exit(1);
+            }
+        }
+        Some(ImuCommand::Stop { session, all }) => {
             if let Some(device_id) = device_id {
-                stop_imu_data_publishing(&device_id, host, port).await?;
+                if session.is_none() && !all {
+                    eprintln!("Error: session or --all is required for stop command");
+                    std::process::exit(1);
+                }
+                stop_imu_data_publishing(&device_id, session.as_deref(), all, host, port).await?;
             } else {
                 eprintln!("Error: device_id is required for stop command");
                 std::process::exit(1);
@@ -74,17 +107,32 @@ pub async fn handle_imu_command(
                 // Create IMU socket for reading data
                 let mut imu_socket = ImuSocket::new(&device_id, host, port).await?;
 
-                // Wait for signal
+                // Hold a session so the IMU keeps publishing while reading
+                let session =
+                    start_imu_data_publishing(&device_id, Some(READ_SESSION_TTL), host, port)
+                        .await?;
+
+                // Wait for signal, SIGTERM too so callers like the Godot plugin can stop it cleanly
                 info!("start to listen imu data");
 
+                let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())?;
                 tokio::select! {
                     _ = signal::ctrl_c() => {
                         info!("received SIGINT signal");
                     }
+                    _ = sigterm.recv() => {
+                        info!("received SIGTERM signal");
+                    }
                     _ = imu_socket.listen() => {
                         error!("imu socket listen error");
                     }
+                    e = keep_session_alive(&device_id, &session.session, host, port) => {
+                        error!("imu session lost: {}", e);
+                    }
                 }
+
+                stop_imu_data_publishing(&device_id, Some(&session.session), false, host, port)
+                    .await?;
             } else {
                 eprintln!("Error: device_id is required for read command");
                 std::process::exit(1);
@@ -146,10 +194,69 @@ pub async fn get_device_info(device_id: &str, host: &str, port: u16) -> io::Resu
     Ok(device_info)
 }
 
-/// Start imu data publishing
-pub async fn start_imu_data_publishing(device_id: &str, host: &str, port: u16) -> io::Result<()> {
+/// Open a streaming session, the IMU starts with the first one
+pub async fn start_imu_data_publishing(
+    device_id: &str,
+    ttl: Option<u32>,
+    host: &str,
+    port: u16,
+) -> io::Result<SessionInfo> {
     let client = reqwest::Client::new();
-    let url = format!("http://{host}:{port}/{device_id}/start");
+    let mut url = format!("http://{host}:{port}/{device_id}/start?client=rebecca-hal");
+    if let Some(ttl) = ttl {
+        url.push_str(&format!("&ttl={ttl}"));
+    }
+
+    let session: SessionInfo = request_json(&client, &url).await?;
+
+    info!("start imu data publishing: {:#?}", session);
+
+    Ok(session)
+}
+
+/// Extend a streaming session by its ttl
+pub async fn keepalive_imu_session(
+    device_id: &str,
+    session: &str,
+    host: &str,
+    port: u16,
+) -> io::Result<SessionInfo> {
+    let client = reqwest::Client::new();
+    let url = format!("http://{host}:{port}/{device_id}/keepalive?session={session}");
+
+    let session: SessionInfo = request_json(&client, &url).await?;
+
+    debug!("keepalive imu session: {:#?}", session);
+
+    Ok(session)
+}
+
+/// Keepalive until it fails
+async fn keep_session_alive(device_id: &str, session: &str, host: &str, port: u16) -> io::Error {
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(READ_KEEPALIVE_INTERVAL));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(e) = keepalive_imu_session(device_id, session, host, port).await {
+            return e;
+        }
+    }
+}
+
+/// Close a streaming session, or all of them, the IMU stops with the last one
+pub async fn stop_imu_data_publishing(
+    device_id: &str,
+    session: Option<&str>,
+    all: bool,
+    host: &str,
+    port: u16,
+) -> io::Result<()> {
+    let client = reqwest::Client::new();
+    let url = match session {
+        Some(session) if !all => format!("http://{host}:{port}/{device_id}/stop?session={session}"),
+        _ => format!("http://{host}:{port}/{device_id}/stop?all=true"),
+    };
 
     let response =
         client.get(&url).send().await.map_err(|e| {
@@ -165,19 +272,18 @@ pub async fn start_imu_data_publishing(device_id: &str, host: &str, port: u16) -
 
     let text = response.text().await.unwrap();
 
-    info!("start imu data publishing: {:#?}", text);
-    println!("{:#?}", text);
+    info!("stop imu data publishing: {:#?}", text);
+    println!("{}", text);
 
     Ok(())
 }
 
-/// Stop imu data publishing
-pub async fn stop_imu_data_publishing(device_id: &str, host: &str, port: u16) -> io::Result<()> {
-    let client = reqwest::Client::new();
-    let url = format!("http://{host}:{port}/{device_id}/stop");
-
+async fn request_json<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+) -> io::Result<T> {
     let response =
-        client.get(&url).send().await.map_err(|e| {
+        client.get(url).send().await.map_err(|e| {
             io::Error::new(io::ErrorKind::Other, format!("HTTP request failed: {}", e))
         })?;
 
@@ -188,12 +294,18 @@ pub async fn stop_imu_data_publishing(device_id: &str, host: &str, port: u16) ->
         ));
     }
 
-    let text = response.text().await.unwrap();
-
-    info!("stop imu data publishing: {:#?}", text);
-    println!("{:#?}", text);
+    response
+        .json()
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to parse JSON: {}", e)))
+}
 
-    Ok(())
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionInfo {
+    session: String,
+    client: String,
+    ttl: Option<f64>,
+    expires_in: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,6 +21,9 @@ public partial class Imu : Node
     [Export]
     public string cliToolName = "rebecca-hal";
 
+    // `read` holds its own streaming session and closes it on SIGTERM
+    private const int StopTimeoutMs = 3000;
+
     private Thread _readerThread;
     private Process _process;
     private bool _running = false;
@@ -35,31 +38,27 @@ public partial class Imu : Node
         }
 
         StartReading();
-        StartWorker();
 
         GD.Print("[IMU] ready");
     }
 
     /// <summary>
-    /// Start reading imu data
+    /// Start reading imu data, the read process keeps its own session alive
     /// </summary>
     /// <returns></returns>
     public bool StartReading()
     {
         GD.Print("[IMU] start reading");
-        var args = $"--host {host} --port {port} imu {deviceId} start";
-        return Common.ExecuteCommand(cliToolName, args);
+        return StartWorker();
     }
 
     /// <summary>
-    /// Stop reading imu data
+    /// Stop reading imu data, the read process closes its session on exit
     /// </summary>
-    /// <returns></returns>
-    public bool StopReading()
+    public void StopReading()
     {
         GD.Print("[IMU] stop reading");
-        var args = $"--host {host} --port {port} imu {deviceId} stop";
-        return Common.ExecuteCommand(cliToolName, args);
+        StopWorker();
     }
 
     private void ReadingWorker()
@@ -85,12 +84,12 @@ public partial class Imu : Node
         EmitSignal(SignalName.ImuDataReceived, data);
     }
 
-    private void StartWorker()
+    private bool StartWorker()
     {
         if (_running)
         {
             GD.PrintErr("imu reading worker already running");
-            return;
+            return false;
         }
 
         _process = new Process();
@@ -101,7 +100,15 @@ public partial class Imu : Node
         _process.StartInfo.RedirectStandardError = true;
         _process.StartInfo.CreateNoWindow = true;
 
-        _process.Start();
+        try
+        {
+            _process.Start();
+        }
+        catch (Exception e)
+        {
+            GD.PrintErr("Start reading process failed: ", e);
+            return false;
+        }
         _running = true;
 
         _readerThread = new Thread(ReadingWorker)
@@ -109,20 +116,28 @@ public partial class Imu : Node
             IsBackground = true
         };
         _readerThread.Start();
+        return true;
     }
 
-    private void StopWroker()
+    private void StopWorker()
     {
+        if (!_running)
+            return;
         _running = false;
 
         try
         {
             if (!_process.HasExited)
-                _process.Kill();
+            {
+                // Let read close its session, kill only if it hangs
+                Common.ExecuteCommand("kill", $"-TERM {_process.Id}");
+                if (!_process.WaitForExit(StopTimeoutMs))
+                    _process.Kill();
+            }
         }
         catch (Exception e)
         {
-            GD.PrintErr("Kill reading process failed: ", e);
+            GD.PrintErr("Stop reading process failed: ", e);
         }
 
         _readerThread?.Join();
@@ -131,7 +146,6 @@ public partial class Imu : Node
     public void Cleanup()
     {
         GD.Print("[IMU] cleanup");
-        StopWroker();
         StopReading();
     }
 
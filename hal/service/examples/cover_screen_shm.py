@@ -0,0 +1,57 @@
+import json
+import mmap
+import socket
+
+import numpy as np
+import requests
+from PIL import Image, ImageDraw
+
+from cover_screen import convert_to_16bit
+
+
+def get_screen_info():
+    """获取screen0的信息"""
+    response = requests.get("http://localhost:12580/screen0/info")
+    response.raise_for_status()
+    return response.json()
+
+
+def connect_shm(shm_socket):
+    """连接共享内存 socket，拿到 memfd 并映射"""
+    sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    # "@" 开头的是抽象 socket，python 里用 "\0" 表示
+    sock.connect("\0" + shm_socket.removeprefix("@"))
+
+    msg, fds, _, _ = socket.recv_fds(sock, 1024, 1)
+    header = json.loads(msg.decode())
+    frame = mmap.mmap(fds[0], header["frame_size"])
+    return sock, frame
+
+
+def main():
+    screen_info = get_screen_info()
+    width, height = screen_info["screen_size"]
+    shm_socket = screen_info["shm_socket"]
+    print(f"屏幕尺寸: {width}x{height}, 共享内存 socket: {shm_socket}")
+
+    sock, frame = connect_shm(shm_socket)
+
+    for i in range(100):
+        image = Image.new("RGB", (width, height), color="black")
+        draw = ImageDraw.Draw(image)
+        draw.text((123, 123), f"Hello shm! {i}")
+
+        # 直接写进共享内存，然后发一个字节通知渲染
+        frame[:] = convert_to_16bit(image)
+        sock.send(b"\x01")
+
+        # 等回复，0 是成功，之后才能复用 buffer
+        if sock.recv(1) != b"\x00":
+            print("❌ 渲染失败")
+            break
+
+    sock.close()
+
+
+if __name__ == "__main__":
+    main()
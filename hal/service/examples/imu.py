@@ -10,6 +10,13 @@ from google.protobuf.message import DecodeError
 
 import imu_data_pb2
 
+# 会话 30 秒不续期就会过期，留足余量每 10 秒续一次
+SESSION_TTL = 30
+KEEPALIVE_INTERVAL = 10
+
+session_id: Optional[str] = None
+last_keepalive = 0.0
+
 
 def format_float(value: float, width: int = 9, precision: int = 2) -> str:
     """格式化浮点数，固定宽度并始终带符号。"""
@@ -47,23 +54,50 @@ def get_imu_info() -> Optional[dict]:
 
 
 def start_publishing() -> bool:
-    """启动 IMU 数据发布。"""
+    """打开一个 IMU 数据发布会话，设备在还有会话时保持发布。"""
+    global session_id, last_keepalive
     try:
-        response = requests.get("http://localhost:12580/imu0/start")
+        response = requests.get(
+            "http://localhost:12580/imu0/start",
+            params={"client": "imu.py", "ttl": SESSION_TTL},
+        )
         response.raise_for_status()
-        print("已发送启动发布指令 (/imu0/start)")
+        session_id = response.json()["session"]
+        last_keepalive = time.time()
+        print(f"已打开发布会话 (/imu0/start): {session_id}")
         return True
     except requests.exceptions.RequestException as error:
         print(f"启动发布失败: {error}")
         return False
 
 
+def keepalive_if_due() -> None:
+    """距离上次续期超过间隔时续期会话。"""
+    global last_keepalive
+    if session_id is None or time.time() - last_keepalive < KEEPALIVE_INTERVAL:
+        return
+    try:
+        response = requests.get(
+            "http://localhost:12580/imu0/keepalive", params={"session": session_id}
+        )
+        response.raise_for_status()
+        last_keepalive = time.time()
+    except requests.exceptions.RequestException as error:
+        print(f"会话续期失败: {error}")
+
+
 def stop_publishing() -> bool:
-    """停止 IMU 数据发布。"""
+    """关闭发布会话，最后一个会话关闭后 IMU 停止发布。"""
+    global session_id
+    if session_id is None:
+        return True
     try:
-        response = requests.get("http://localhost:12580/imu0/stop")
+        response = requests.get(
+            "http://localhost:12580/imu0/stop", params={"session": session_id}
+        )
         response.raise_for_status()
-        print("已发送停止发布指令 (/imu0/stop)")
+        print(f"已关闭发布会话 (/imu0/stop): {session_id}")
+        session_id = None
         return True
     except requests.exceptions.RequestException as error:
         print(f"停止发布失败: {error}")
@@ -92,6 +126,7 @@ def subscribe_and_print_imu_data(
 
     try:
         while received_count < max_messages:
+            keepalive_if_due()
             try:
                 raw_bytes = socket.recv()
             except zmq.Again:
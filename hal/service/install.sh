@@ -14,6 +14,7 @@ NC='\033[0m' # No Color
 SERVICE_NAME="rebecca-hal-service"
 BINARY_NAME="rebecca-hal-service"
 SERVICE_FILE="/etc/systemd/system/${SERVICE_NAME}.service"
+CONFIG_DIR="$HOME/.config/rebecca-hal"
 
 echo -e "${GREEN}开始安装 Rebecca HAL...${NC}"
 
@@ -35,6 +36,8 @@ if [ ! -f "$CARGO_BIN_PATH" ]; then
 fi
 
 echo -e "${YELLOW}2. 创建systemd服务文件...${NC}"
+# 设备设置持久化目录，需要提前创建才能加到 ReadWritePaths
+mkdir -p "$CONFIG_DIR"
 sudo tee "$SERVICE_FILE" > /dev/null << EOF
 [Unit]
 Description=Rebecca HAL Service
@@ -46,7 +49,7 @@ Type=simple
 User=$USER
 Group=$USER
 ExecStartPre=$CARGO_BIN_PATH --version
-ExecStart=$CARGO_BIN_PATH --port 12580
+ExecStart=$CARGO_BIN_PATH --port 12580 --config-dir $CONFIG_DIR
 Restart=always
 RestartSec=5
 StandardOutput=journal
@@ -58,7 +61,7 @@ NoNewPrivileges=yes
 PrivateTmp=yes
 ProtectSystem=strict
 ProtectHome=no
-ReadWritePaths=/tmp
+ReadWritePaths=/tmp $CONFIG_DIR
 
 [Install]
 WantedBy=multi-user.target
@@ -84,6 +87,7 @@ if sudo systemctl is-active --quiet $SERVICE_NAME; then
     echo "- 服务名称: $SERVICE_NAME"
     echo "- 二进制文件: $CARGO_BIN_PATH"
     echo "- 配置文件: $SERVICE_FILE"
+    echo "- 设备设置: $CONFIG_DIR"
     echo ""
     echo "常用命令:"
     echo "  查看状态: sudo systemctl status $SERVICE_NAME"
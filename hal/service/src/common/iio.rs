@@ -0,0 +1,65 @@
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the kernel lists IIO devices and triggers
+pub const IIO_DEVICES_PATH: &str = "/sys/bus/iio/devices";
+/// Where IIO device nodes are, `/dev/iio:deviceN`
+pub const IIO_DEV_PATH: &str = "/dev";
+
+/// List `iio:deviceN` directories under `iio_devices_path` in device number order, triggers are skipped
+pub fn list_iio_devices(iio_devices_path: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(iio_devices_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("read {} failed: {}", iio_devices_path.display(), e);
+            return vec![];
+        }
+    };
+
+    let mut device_paths: Vec<(u32, _)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let number = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("iio:device")?
+                .parse()
+                .ok()?;
+            Some((number, entry.path()))
+        })
+        .collect();
+    device_paths.sort_by_key(|(number, _)| *number);
+
+    device_paths
+        .into_iter()
+        .map(|(_, device_path)| device_path)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_in_device_number_order() {
+        let root = tempfile::tempdir().unwrap();
+        for name in [
+            "iio:device10",
+            "iio:device2",
+            "trigger0",
+            "iio:device0",
+            "iio:devicex",
+        ] {
+            fs::create_dir(root.path().join(name)).unwrap();
+        }
+
+        let names: Vec<_> = list_iio_devices(root.path())
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["iio:device0", "iio:device2", "iio:device10"]);
+
+        assert!(list_iio_devices(&root.path().join("missing")).is_empty());
+    }
+}
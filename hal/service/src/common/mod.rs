@@ -1,3 +1,7 @@
 pub mod emoji;
+pub mod iio;
+pub mod request;
+pub mod storage;
 
 pub use emoji::Emoji;
+pub use request::{query_param, read_body};
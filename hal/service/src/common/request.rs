@@ -0,0 +1,29 @@
+use http_body_util::{BodyExt, Limited};
+use hyper::{Request, Uri, body::Bytes};
+use std::io;
+
+// Max request body size, enough for an encoded image
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// Get a query parameter by key, e.g. `timeout` from `/screen0/idle/set?timeout=30`
+pub fn query_param(uri: &Uri, key: &str) -> Option<String> {
+    uri.query()?
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v.to_string())
+}
+
+/// Read the whole request body
+pub async fn read_body(request: Request<hyper::body::Incoming>) -> io::Result<Bytes> {
+    Limited::new(request.into_body(), MAX_BODY_SIZE)
+        .collect()
+        .await
+        .map(|collected| collected.to_bytes())
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("read body failed: {}", e),
+            )
+        })
+}
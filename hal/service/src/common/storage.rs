@@ -0,0 +1,67 @@
+use log::{debug, warn};
+use once_cell::sync::OnceCell;
+use serde::{Serialize, de::DeserializeOwned};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// 配置目录，启动时设置一次
+static STORAGE_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// Set directory for persisted settings, e.g. `~/.config/rebecca-hal`
+pub fn init_storage(dir: PathBuf) {
+    debug!("storage dir: {}", dir.display());
+    if STORAGE_DIR.set(dir).is_err() {
+        warn!("storage dir already set");
+    }
+}
+
+/// Default storage dir, `$XDG_CONFIG_HOME/rebecca-hal` or `~/.config/rebecca-hal`
+pub fn default_storage_dir() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("rebecca-hal")
+}
+
+/// Directory set by `init_storage`
+pub fn storage_dir() -> io::Result<PathBuf> {
+    STORAGE_DIR
+        .get()
+        .cloned()
+        .ok_or_else(|| io::Error::other("storage dir not set"))
+}
+
+fn storage_path(key: &str) -> io::Result<PathBuf> {
+    Ok(storage_dir()?.join(format!("{}.json", key)))
+}
+
+/// Load a persisted value, key like `screen0/color` maps to `<dir>/screen0/color.json`
+pub fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let path = storage_path(key).ok()?;
+    let content = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(value) => {
+            debug!("load {} from {}", key, path.display());
+            Some(value)
+        }
+        Err(e) => {
+            warn!("failed to parse {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Persist a value as pretty json
+pub fn save<T: Serialize>(key: &str, value: &T) -> io::Result<()> {
+    let path = storage_path(key)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_string_pretty(value)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(&path, content)?;
+    debug!("save {} to {}", key, path.display());
+    Ok(())
+}
@@ -0,0 +1,384 @@
+use crate::common::storage;
+use crate::devices::backlight::{Backlight, FadeCurve, FadingBacklight};
+use crate::devices::light::find_light_sensor;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::time;
+
+// Sensor read and adjust period
+const AUTO_INTERVAL: Duration = Duration::from_millis(500);
+// Auto brightness never turns a backlight off, 0 is left to idle blanking
+const MIN_AUTO_LEVEL: f32 = 0.01;
+
+/// Auto brightness settings, persisted per backlight
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AutoSettings {
+    pub enabled: bool,
+    pub sensor: String,  // light sensor id, like light0
+    pub offset: f32,     // user bias added to the mapped level, -1.0~1.0
+    pub dark_lux: f32,   // at or below this the level is min_level
+    pub bright_lux: f32, // at or above this the level is max_level, log scale in between
+    pub min_level: f32,  // brightness 0.0~1.0
+    pub max_level: f32,  // brightness 0.0~1.0
+    pub hysteresis: f32, // level change needed before adjusting
+    pub smoothing: f32,  // seconds, time constant of lux smoothing, 0 to follow every reading
+    pub fade_ms: u64,    // fade to each new level
+}
+
+impl Default for AutoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sensor: "light0".to_string(),
+            offset: 0.0,
+            dark_lux: 5.0,
+            bright_lux: 2000.0,
+            min_level: 0.1,
+            max_level: 1.0,
+            hysteresis: 0.05,
+            smoothing: 3.0,
+            fade_ms: 800,
+        }
+    }
+}
+
+impl AutoSettings {
+    pub fn validate(&self) -> io::Result<()> {
+        let check = |ok: bool, msg: String| {
+            if ok {
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+            }
+        };
+        check(
+            (-1.0..=1.0).contains(&self.offset),
+            format!("invalid offset {}, must be between -1 and 1", self.offset),
+        )?;
+        check(
+            self.dark_lux > 0.0 && self.dark_lux < self.bright_lux && self.bright_lux.is_finite(),
+            format!(
+                "invalid lux range {}~{}, need 0 < dark_lux < bright_lux",
+                self.dark_lux, self.bright_lux
+            ),
+        )?;
+        check(
+            (0.0..=1.0).contains(&self.min_level)
+                && (0.0..=1.0).contains(&self.max_level)
+                && self.min_level <= self.max_level,
+            format!(
+                "invalid level range {}~{}, need 0 <= min_level <= max_level <= 1",
+                self.min_level, self.max_level
+            ),
+        )?;
+        check(
+            (0.0..=0.5).contains(&self.hysteresis),
+            format!(
+                "invalid hysteresis {}, must be between 0 and 0.5",
+                self.hysteresis
+            ),
+        )?;
+        check(
+            (0.0..=60.0).contains(&self.smoothing),
+            format!(
+                "invalid smoothing {}, must be between 0 and 60 seconds",
+                self.smoothing
+            ),
+        )?;
+        check(
+            self.fade_ms <= 10_000,
+            format!("invalid fade_ms {}, must be up to 10000", self.fade_ms),
+        )
+    }
+
+    /// Brightness for an ambient light level, offset included
+    pub fn level(&self, lux: f32) -> f32 {
+        let (dark, bright) = (self.dark_lux.ln(), self.bright_lux.ln());
+        let t = ((lux.max(self.dark_lux).ln() - dark) / (bright - dark)).clamp(0.0, 1.0);
+        let level = self.min_level + (self.max_level - self.min_level) * t + self.offset;
+        level.clamp(MIN_AUTO_LEVEL, 1.0)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AutoInfo {
+    #[serde(flatten)]
+    pub settings: AutoSettings,
+    pub lux: Option<f32>,          // last reading
+    pub smoothed_lux: Option<f32>, // what the level follows
+    pub level: Option<f32>,        // last level applied
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct AutoState {
+    lux: Option<f32>,
+    smoothed: Option<f32>, // ln(1 + lux)
+    last_read: Option<Instant>,
+    applied: Option<f32>,
+    last_error: Option<String>,
+}
+
+impl AutoState {
+    /// Take a reading, returns the level to fade to, None while within hysteresis of the applied one
+    fn update(&mut self, lux: f32, now: Instant, settings: &AutoSettings) -> Option<f32> {
+        // Smooth in log space, a lamp switching on moves lux by orders of magnitude
+        let log_lux = lux.ln_1p();
+        let smoothed = match (self.smoothed, self.last_read) {
+            (Some(smoothed), Some(last)) if settings.smoothing > 0.0 => {
+                let dt = now.duration_since(last).as_secs_f32();
+                smoothed + (log_lux - smoothed) * (dt / (settings.smoothing + dt))
+            }
+            _ => log_lux,
+        };
+        self.lux = Some(lux);
+        self.smoothed = Some(smoothed);
+        self.last_read = Some(now);
+        self.last_error = None;
+
+        let level = settings.level(smoothed.exp() - 1.0);
+        if self
+            .applied
+            .is_some_and(|applied| (level - applied).abs() < settings.hysteresis)
+        {
+            return None;
+        }
+        Some(level)
+    }
+}
+
+/// Auto brightness of a backlight
+/// 按环境光照度调节背光：照度在对数域平滑，映射到亮度后加上用户偏移，变化超过回差才渐变过去；背光被关掉（比如空闲熄屏）时不去点亮
+pub struct AutoBrightness {
+    id: String,
+    backlight: Arc<FadingBacklight>,
+    settings: RwLock<AutoSettings>,
+    state: Mutex<AutoState>,
+    notify: Notify,
+}
+
+impl AutoBrightness {
+    pub fn new(id: &str, backlight: Arc<FadingBacklight>) -> Self {
+        let settings: AutoSettings = storage::load(&format!("{}/auto", id))
+            .filter(|settings: &AutoSettings| match settings.validate() {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("{} stored auto brightness settings ignored: {}", id, e);
+                    false
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            id: id.to_string(),
+            backlight,
+            settings: RwLock::new(settings),
+            state: Mutex::new(AutoState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn settings(&self) -> AutoSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Change and persist settings, the new level applies right away
+    pub fn set_settings(&self, settings: AutoSettings) -> io::Result<()> {
+        settings.validate()?;
+        *self.settings.write().unwrap() = settings.clone();
+        self.state.lock().unwrap().applied = None;
+        info!("{} auto brightness: {:?}", self.id, settings);
+        self.notify.notify_one();
+        storage::save(&format!("{}/auto", self.id), &settings)
+    }
+
+    pub fn info(&self) -> AutoInfo {
+        let state = self.state.lock().unwrap();
+        AutoInfo {
+            settings: self.settings(),
+            lux: state.lux,
+            smoothed_lux: state.smoothed.map(|smoothed| smoothed.exp() - 1.0),
+            level: state.applied,
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    /// Follow the light sensor while enabled, runs until the task is aborted
+    pub async fn run(&self) {
+        loop {
+            if !self.settings().enabled {
+                *self.state.lock().unwrap() = AutoState::default();
+                self.notify.notified().await;
+                continue;
+            }
+            self.step().await;
+            tokio::select! {
+                _ = time::sleep(AUTO_INTERVAL) => {}
+                _ = self.notify.notified() => {}
+            }
+        }
+    }
+
+    async fn step(&self) {
+        let settings = self.settings();
+        let Some(sensor) = find_light_sensor(&settings.sensor).await else {
+            self.fail(format!("light sensor {} not found", settings.sensor));
+            return;
+        };
+        let lux = match sensor.read_lux() {
+            Ok(lux) => lux.max(0.0),
+            Err(e) => {
+                self.fail(format!("read {} failed: {}", settings.sensor, e));
+                return;
+            }
+        };
+
+        let Some(level) = self
+            .state
+            .lock()
+            .unwrap()
+            .update(lux, Instant::now(), &settings)
+        else {
+            return;
+        };
+
+        // Leave a backlight that was turned off alone, it gets its brightness back on wake
+        match self.backlight.get_brightness() {
+            Ok(brightness) if brightness <= 0.0 => return,
+            Ok(_) => {}
+            Err(e) => {
+                self.fail(format!("get brightness failed: {}", e));
+                return;
+            }
+        }
+        if let Err(e) = self
+            .backlight
+            .fade(level, settings.fade_ms, FadeCurve::Ease)
+        {
+            self.fail(format!("fade failed: {}", e));
+            return;
+        }
+        debug!("{} auto brightness {} at {} lux", self.id, level, lux);
+        self.state.lock().unwrap().applied = Some(level);
+    }
+
+    fn fail(&self, error: String) {
+        let mut state = self.state.lock().unwrap();
+        // Warn once per distinct error, it repeats every interval
+        if state.last_error.as_ref() != Some(&error) {
+            warn!("{} auto brightness: {}", self.id, error);
+        }
+        state.last_error = Some(error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn level_maps_lux_on_log_scale() {
+        let settings = AutoSettings::default();
+        assert!(close(settings.level(0.0), settings.min_level));
+        assert!(close(settings.level(settings.dark_lux), settings.min_level));
+        assert!(close(settings.level(1e6), settings.max_level));
+
+        // Geometric middle of the lux range is the middle level
+        let middle = (settings.dark_lux * settings.bright_lux).sqrt();
+        assert!(close(
+            settings.level(middle),
+            (settings.min_level + settings.max_level) / 2.0
+        ));
+    }
+
+    #[test]
+    fn level_offset_is_clamped() {
+        let settings = AutoSettings {
+            offset: -0.5,
+            ..Default::default()
+        };
+        assert!(close(settings.level(0.0), MIN_AUTO_LEVEL));
+
+        let settings = AutoSettings {
+            offset: 0.5,
+            ..Default::default()
+        };
+        assert!(close(settings.level(1e6), 1.0));
+        assert!(close(settings.level(0.0), 0.6));
+    }
+
+    #[test]
+    fn hysteresis_holds_small_changes() {
+        let settings = AutoSettings {
+            smoothing: 0.0,
+            hysteresis: 0.1,
+            ..Default::default()
+        };
+        let mut state = AutoState::default();
+        let now = Instant::now();
+
+        let level = state.update(100.0, now, &settings).unwrap();
+        state.applied = Some(level);
+
+        // 100 to 120 lux moves the level by about 0.02
+        assert_eq!(state.update(120.0, now, &settings), None);
+        assert!(close(state.lux.unwrap(), 120.0));
+
+        // 100 to 1000 lux moves it by about 0.3
+        let brighter = state.update(1000.0, now, &settings).unwrap();
+        assert!(brighter - level > settings.hysteresis);
+    }
+
+    #[test]
+    fn smoothing_follows_gradually() {
+        let settings = AutoSettings {
+            smoothing: 3.0,
+            hysteresis: 0.0,
+            ..Default::default()
+        };
+        let mut state = AutoState::default();
+        let start = Instant::now();
+
+        // First reading is taken as is
+        state.update(10.0, start, &settings);
+        assert!(close(state.smoothed.unwrap(), 10f32.ln_1p()));
+
+        // One time constant later, half way to the new reading in log space
+        state.update(1000.0, start + Duration::from_secs(3), &settings);
+        let half_way = (10f32.ln_1p() + 1000f32.ln_1p()) / 2.0;
+        assert!(close(state.smoothed.unwrap(), half_way));
+
+        // Keeps approaching without overshooting
+        let target = 1000f32.ln_1p();
+        let mut last = state.smoothed.unwrap();
+        for second in 4..60 {
+            state.update(1000.0, start + Duration::from_secs(second), &settings);
+            let smoothed = state.smoothed.unwrap();
+            assert!(smoothed >= last && smoothed <= target);
+            last = smoothed;
+        }
+        assert!(close(last, target));
+    }
+
+    #[test]
+    fn no_smoothing_follows_every_reading() {
+        let settings = AutoSettings {
+            smoothing: 0.0,
+            hysteresis: 0.0,
+            ..Default::default()
+        };
+        let mut state = AutoState::default();
+        let now = Instant::now();
+        state.update(10.0, now, &settings);
+        let level = state.update(1000.0, now, &settings).unwrap();
+        assert!(close(level, settings.level(1000.0)));
+    }
+}
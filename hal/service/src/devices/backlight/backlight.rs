@@ -1,12 +1,51 @@
-use crate::common::Emoji;
-use crate::devices::backlight::{Backlight, MockBacklight, SysfsBacklight};
+use crate::common::{Emoji, query_param};
+use crate::devices::backlight::{
+    AutoBrightness, AutoSettings, Backlight, BacklightIdle, BrightnessCurve, CurveKind, FadeCurve,
+    FadingBacklight, IdleSettings, MockBacklight, SysfsBacklight,
+};
 use crate::devices::{API_REGISTER, ApiRoute};
-use hyper::{Method, Response, StatusCode, header::CONTENT_TYPE};
+use hyper::{Method, Response, StatusCode, Uri, header::CONTENT_TYPE};
+use indexmap::IndexMap;
 use log::{error, info, warn};
+use once_cell::sync::Lazy;
 use serde_json::json;
 use std::io;
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::{sync::Notify, task};
+use tokio::{
+    sync::{Notify, RwLock},
+    task,
+};
+
+// 已注册的背光设备，给其他服务（比如屏幕空闲策略）按 id 查找
+static BACKLIGHTS: Lazy<RwLock<IndexMap<String, Arc<dyn Backlight + Send + Sync>>>> =
+    Lazy::new(|| RwLock::new(IndexMap::new()));
+
+// 一个背光设备和它上面的曲线渐变、自动亮度、空闲策略
+type BacklightLayers = (
+    String,
+    Arc<FadingBacklight>,
+    Arc<AutoBrightness>,
+    Arc<BacklightIdle>,
+);
+
+// 背光的空闲策略，给 IMU 运动唤醒用
+static IDLE_BACKLIGHTS: Lazy<RwLock<IndexMap<String, Arc<BacklightIdle>>>> =
+    Lazy::new(|| RwLock::new(IndexMap::new()));
+
+/// Find a registered backlight by device id, e.g. `backlight0`
+pub async fn find_backlight(id: &str) -> Option<Arc<dyn Backlight + Send + Sync>> {
+    BACKLIGHTS.read().await.get(id).cloned()
+}
+
+/// Count activity like `source` on every backlight, backlights turned off by their idle policy come back
+pub async fn wake_backlights(source: &str) {
+    for (id, idle) in IDLE_BACKLIGHTS.read().await.iter() {
+        if let Err(e) = idle.on_activity(source) {
+            warn!("{} wake up backlight failed: {}", id, e);
+        }
+    }
+}
 
 // 批量克隆
 macro_rules! arc_clones {
@@ -16,13 +55,23 @@ macro_rules! arc_clones {
 }
 
 // 注册设备
-async fn register_device(id: &str, backlight: &Arc<dyn Backlight + Send + Sync>) {
+async fn register_device(
+    id: &str,
+    backlight: &Arc<FadingBacklight>,
+    auto_brightness: &Arc<AutoBrightness>,
+    idle: &Arc<BacklightIdle>,
+) {
     arc_clones!(
         backlight,
         backlight_clone1,
         backlight_clone2,
-        backlight_clone3
+        backlight_clone3,
+        backlight_clone4,
+        backlight_clone5,
+        backlight_clone6
     );
+    arc_clones!(auto_brightness, auto_clone1, auto_clone2);
+    arc_clones!(idle, idle_clone1, idle_clone2, idle_clone3, idle_clone4);
 
     let success_response = || -> Response<String> {
         Response::builder()
@@ -40,6 +89,14 @@ async fn register_device(id: &str, backlight: &Arc<dyn Backlight + Send + Sync>)
 
     // Add device to device list
     API_REGISTER.add_device(id.to_string()).await;
+    BACKLIGHTS.write().await.insert(
+        id.to_string(),
+        Arc::clone(backlight) as Arc<dyn Backlight + Send + Sync>,
+    );
+    IDLE_BACKLIGHTS
+        .write()
+        .await
+        .insert(id.to_string(), Arc::clone(idle));
 
     // Get info
     if let Err(e) = API_REGISTER
@@ -56,6 +113,7 @@ async fn register_device(id: &str, backlight: &Arc<dyn Backlight + Send + Sync>)
                         "device_type": backlight.device_type(),
                         "max_brightness": backlight.max_brightness(),
                         "current_brightness": backlight.get_brightness().unwrap_or(0.0),
+                        "curve": backlight.curve(),
                         "description": format!("{} Control backlight brightness (0.0~1.0).", Emoji::LIGHT)
                     });
 
@@ -77,7 +135,7 @@ async fn register_device(id: &str, backlight: &Arc<dyn Backlight + Send + Sync>)
             ApiRoute {
                 path: format!("/{}/get", id),
                 method: Method::GET,
-                description: format!("{} Get current brightness (0.0~1.0).", Emoji::LIGHT),
+                description: format!("{} Get current brightness (0.0~1.0), mid-fade too, and the fade in progress if any.", Emoji::LIGHT),
             },
             Box::new(move |_request| {
                 let backlight = Arc::clone(&backlight_clone2);
@@ -85,7 +143,8 @@ async fn register_device(id: &str, backlight: &Arc<dyn Backlight + Send + Sync>)
                     match backlight.get_brightness() {
                         Ok(brightness) => {
                             let response = json!({
-                                "brightness": brightness
+                                "brightness": brightness,
+                                "fade": backlight.fade_info()
                             });
                             Response::builder()
                                 .header(CONTENT_TYPE, "application/json; charset=utf-8")
@@ -112,11 +171,12 @@ async fn register_device(id: &str, backlight: &Arc<dyn Backlight + Send + Sync>)
             },
             Box::new(move |request| {
                 let backlight = Arc::clone(&backlight_clone3);
+                let idle = Arc::clone(&idle_clone1);
                 Box::pin(async move {
                     // Parse brightness from query parameter
                     let uri = request.uri();
                     let query = uri.query().unwrap_or("");
-                    
+
                     let brightness: f32 = if let Some(brightness_str) = query
                         .split('&')
                         .find(|param| param.starts_with("brightness="))
@@ -140,6 +200,7 @@ async fn register_device(id: &str, backlight: &Arc<dyn Backlight + Send + Sync>)
                             .unwrap()
                     };
 
+                    idle.on_set();
                     match backlight.set_brightness(brightness) {
                         Ok(()) => success_response(),
                         Err(e) => error_response(e),
@@ -151,6 +212,299 @@ async fn register_device(id: &str, backlight: &Arc<dyn Backlight + Send + Sync>)
     {
         error!("failed to register backlight set api: {}", e);
     }
+
+    // Fade brightness
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/fade", id),
+                method: Method::GET,
+                description: format!("{} Fade brightness to <to> (0.0~1.0) over <ms> milliseconds with curve linear, ease (default), ease_in or ease_out. A new set or fade cancels the fade in progress. Use query parameter: /{}/fade?to=0.2&ms=500&curve=ease", Emoji::LIGHT, id),
+            },
+            Box::new(move |request| {
+                let backlight = Arc::clone(&backlight_clone4);
+                let idle = Arc::clone(&idle_clone2);
+                Box::pin(async move {
+                    let bad_request = |msg: String| {
+                        Response::builder()
+                            .status(StatusCode::BAD_REQUEST)
+                            .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+                            .body(msg)
+                            .unwrap()
+                    };
+                    let uri = request.uri();
+                    let to = match query_param(uri, "to").map(|to| to.parse::<f32>()) {
+                        Some(Ok(to)) if to.is_finite() => to,
+                        Some(_) => return bad_request("invalid to value, must be a number between 0.0 and 1.0".to_string()),
+                        None => return bad_request("missing to parameter".to_string()),
+                    };
+                    let ms = match query_param(uri, "ms").map(|ms| ms.parse::<u64>()) {
+                        Some(Ok(ms)) => ms,
+                        Some(Err(_)) => return bad_request("invalid ms value, must be milliseconds".to_string()),
+                        None => return bad_request("missing ms parameter".to_string()),
+                    };
+                    let curve = match query_param(uri, "curve").map(|curve| curve.parse::<FadeCurve>()) {
+                        Some(Ok(curve)) => curve,
+                        Some(Err(e)) => return bad_request(e),
+                        None => FadeCurve::Ease,
+                    };
+
+                    idle.on_set();
+                    match backlight.fade(to, ms, curve) {
+                        Ok(()) => success_response(),
+                        Err(e) if e.kind() == io::ErrorKind::InvalidInput => bad_request(e.to_string()),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        error!("failed to register backlight fade api: {}", e);
+    }
+
+    // Get brightness curve
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/curve", id),
+                method: Method::GET,
+                description: format!("{} Get the curve mapping brightness (0.0~1.0) to the raw value: linear, gamma, cie (CIE 1931 lightness) or lut, and the min fraction of max_brightness any non-zero brightness gets.", Emoji::LIGHT),
+            },
+            Box::new(move |_request| {
+                let backlight = Arc::clone(&backlight_clone5);
+                Box::pin(async move {
+                    Response::builder()
+                        .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                        .body(serde_json::to_string(&backlight.curve()).unwrap_or("wtf?🤡".to_string()))
+                        .unwrap()
+                })
+            }),
+        )
+        .await
+    {
+        error!("failed to register backlight curve api: {}", e);
+    }
+
+    // Set brightness curve
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/curve/set", id),
+                method: Method::GET,
+                description: format!("{} Set the brightness curve: linear, gamma (with gamma), cie or lut (with comma separated non-decreasing levels at evenly spaced brightness), and min (0~0.5) so low brightness stays visible. get returns brightness through the inverse curve. Only given parameters change, persisted. Use query parameter: /{}/curve/set?curve=gamma&gamma=2.2&min=0.02 or /{}/curve/set?curve=lut&lut=0,0.02,0.1,0.4,1", Emoji::LIGHT, id, id),
+            },
+            Box::new(move |request| {
+                let backlight = Arc::clone(&backlight_clone6);
+                Box::pin(async move {
+                    let bad_request = |msg: String| {
+                        Response::builder()
+                            .status(StatusCode::BAD_REQUEST)
+                            .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+                            .body(msg)
+                            .unwrap()
+                    };
+                    let curve = match curve_params(request.uri(), backlight.curve()) {
+                        Ok(curve) => curve,
+                        Err(e) => return bad_request(e),
+                    };
+
+                    match backlight.set_curve(curve) {
+                        Ok(()) => Response::builder()
+                            .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                            .body(serde_json::to_string(&backlight.curve()).unwrap_or("wtf?🤡".to_string()))
+                            .unwrap(),
+                        Err(e) if e.kind() == io::ErrorKind::InvalidInput => bad_request(e.to_string()),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        error!("failed to register backlight curve set api: {}", e);
+    }
+
+    // Get auto brightness
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/auto", id),
+                method: Method::GET,
+                description: format!("{} Get auto brightness settings, the last lux reading, smoothed lux and the level applied.", Emoji::LIGHT),
+            },
+            Box::new(move |_request| {
+                let auto_brightness = Arc::clone(&auto_clone1);
+                Box::pin(async move {
+                    Response::builder()
+                        .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                        .body(serde_json::to_string(&auto_brightness.info()).unwrap_or("wtf?🤡".to_string()))
+                        .unwrap()
+                })
+            }),
+        )
+        .await
+    {
+        error!("failed to register backlight auto api: {}", e);
+    }
+
+    // Set auto brightness
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/auto/set", id),
+                method: Method::GET,
+                description: format!("{} Set auto brightness from a light sensor: lux between dark_lux and bright_lux maps on a log scale to min_level~max_level, plus offset (-1~1) as the user bias. Lux is smoothed over smoothing seconds and the level only changes by more than hysteresis, fading over fade_ms. A backlight turned off is left off. Only given parameters change, persisted. Use query parameter: /{}/auto/set?enabled=true&sensor=light0&offset=0.1", Emoji::LIGHT, id),
+            },
+            Box::new(move |request| {
+                let auto_brightness = Arc::clone(&auto_clone2);
+                Box::pin(async move {
+                    let bad_request = |msg: String| {
+                        Response::builder()
+                            .status(StatusCode::BAD_REQUEST)
+                            .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+                            .body(msg)
+                            .unwrap()
+                    };
+                    let settings = match auto_params(request.uri(), auto_brightness.settings()) {
+                        Ok(settings) => settings,
+                        Err(e) => return bad_request(e),
+                    };
+
+                    match auto_brightness.set_settings(settings) {
+                        Ok(()) => Response::builder()
+                            .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                            .body(serde_json::to_string(&auto_brightness.info()).unwrap_or("wtf?🤡".to_string()))
+                            .unwrap(),
+                        Err(e) if e.kind() == io::ErrorKind::InvalidInput => bad_request(e.to_string()),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        error!("failed to register backlight auto set api: {}", e);
+    }
+
+    // Get idle policy
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/idle", id),
+                method: Method::GET,
+                description: format!("{} Get idle policy, whether the backlight is off for idle and the brightness it gets back on wake.", Emoji::INFO),
+            },
+            Box::new(move |_request| {
+                let idle = Arc::clone(&idle_clone3);
+                Box::pin(async move {
+                    Response::builder()
+                        .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                        .body(serde_json::to_string(&idle.info()).unwrap_or("wtf?🤡".to_string()))
+                        .unwrap()
+                })
+            }),
+        )
+        .await
+    {
+        error!("failed to register backlight idle api: {}", e);
+    }
+
+    // Set idle policy
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/idle/set", id),
+                method: Method::GET,
+                description: format!("{} Turn the backlight off after <timeout> seconds without set, fade or IMU motion (see imu wake), fading over fade_ms, and restore it on the next motion. For backlights without a screen, a screen's idle policy handles its own backlight. Only given parameters change, persisted. Use query parameter: /{}/idle/set?timeout=60&fade_ms=500, timeout=0 to disable", Emoji::LIGHT, id),
+            },
+            Box::new(move |request| {
+                let idle = Arc::clone(&idle_clone4);
+                Box::pin(async move {
+                    let bad_request = |msg: String| {
+                        Response::builder()
+                            .status(StatusCode::BAD_REQUEST)
+                            .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+                            .body(msg)
+                            .unwrap()
+                    };
+                    let settings = match idle_params(request.uri(), idle.settings()) {
+                        Ok(settings) => settings,
+                        Err(e) => return bad_request(e),
+                    };
+
+                    match idle.set_settings(settings) {
+                        Ok(()) => Response::builder()
+                            .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                            .body(serde_json::to_string(&idle.info()).unwrap_or("wtf?🤡".to_string()))
+                            .unwrap(),
+                        Err(e) if e.kind() == io::ErrorKind::InvalidInput => bad_request(e.to_string()),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        error!("failed to register backlight idle set api: {}", e);
+    }
+}
+
+// Apply curve/set query parameters on top of the current curve
+fn curve_params(uri: &Uri, mut curve: BrightnessCurve) -> Result<BrightnessCurve, String> {
+    if let Some(kind) = query_param(uri, "curve") {
+        curve.curve = kind.parse::<CurveKind>()?;
+    }
+    if let Some(gamma) = query_param(uri, "gamma") {
+        curve.gamma = gamma
+            .parse()
+            .map_err(|_| format!("invalid gamma '{}', must be a number", gamma))?;
+    }
+    if let Some(min) = query_param(uri, "min") {
+        curve.min = min
+            .parse()
+            .map_err(|_| format!("invalid min '{}', must be a number", min))?;
+    }
+    if let Some(lut) = query_param(uri, "lut") {
+        curve.lut = lut
+            .split(',')
+            .map(|point| point.trim().parse::<f32>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("invalid lut '{}', must be comma separated numbers", lut))?;
+    }
+    Ok(curve)
+}
+
+// Overwrite `value` with query parameter `key` if given
+fn param<T: FromStr>(uri: &Uri, key: &str, value: &mut T) -> Result<(), String> {
+    if let Some(param) = query_param(uri, key) {
+        *value = param
+            .parse()
+            .map_err(|_| format!("invalid {} '{}'", key, param))?;
+    }
+    Ok(())
+}
+
+// Apply auto/set query parameters on top of the current settings
+fn auto_params(uri: &Uri, mut settings: AutoSettings) -> Result<AutoSettings, String> {
+    param(uri, "enabled", &mut settings.enabled)?;
+    param(uri, "sensor", &mut settings.sensor)?;
+    param(uri, "offset", &mut settings.offset)?;
+    param(uri, "dark_lux", &mut settings.dark_lux)?;
+    param(uri, "bright_lux", &mut settings.bright_lux)?;
+    param(uri, "min_level", &mut settings.min_level)?;
+    param(uri, "max_level", &mut settings.max_level)?;
+    param(uri, "hysteresis", &mut settings.hysteresis)?;
+    param(uri, "smoothing", &mut settings.smoothing)?;
+    param(uri, "fade_ms", &mut settings.fade_ms)?;
+    Ok(settings)
+}
+
+// Apply idle/set query parameters on top of the current settings
+fn idle_params(uri: &Uri, mut settings: IdleSettings) -> Result<IdleSettings, String> {
+    param(uri, "timeout", &mut settings.timeout)?;
+    param(uri, "fade_ms", &mut settings.fade_ms)?;
+    Ok(settings)
 }
 
 /// Start backlight service to handle backlight devices
@@ -198,12 +552,41 @@ pub async fn start_backlight_service(
         ));
     }
 
+    // Curves, fades and auto brightness run on top of every device
+    let backlights: Vec<BacklightLayers> = backlights
+        .into_iter()
+        .enumerate()
+        .map(|(i, backlight)| {
+            let id = format!("backlight{}", i);
+            let backlight = Arc::new(FadingBacklight::new(&id, backlight));
+            let auto_brightness = Arc::new(AutoBrightness::new(&id, Arc::clone(&backlight)));
+            let idle = Arc::new(BacklightIdle::new(&id, Arc::clone(&backlight)));
+            (id, backlight, auto_brightness, idle)
+        })
+        .collect();
+
     // Register devices
-    for (i,backlight) in backlights.iter().enumerate() {
-        register_device(format!("backlight{}", i).as_str(), backlight).await;
+    for (id, backlight, auto_brightness, idle) in backlights.iter() {
+        register_device(id, backlight, auto_brightness, idle).await;
     }
 
-    info!("backlight service started with {} devices", backlights.len());
+    // Auto brightness and idle tasks wait until enabled
+    let auto_tasks: Vec<task::JoinHandle<()>> = backlights
+        .iter()
+        .flat_map(|(_, _, auto_brightness, idle)| {
+            let auto_brightness = Arc::clone(auto_brightness);
+            let idle = Arc::clone(idle);
+            [
+                task::spawn(async move { auto_brightness.run().await }),
+                task::spawn(async move { idle.run().await }),
+            ]
+        })
+        .collect();
+
+    info!(
+        "backlight service started with {} devices",
+        backlights.len()
+    );
 
     // Start service task
     Ok(task::spawn(async move {
@@ -211,8 +594,13 @@ pub async fn start_backlight_service(
         shutdown_notify.notified().await;
         info!("backlight service shutdown...");
 
+        // Stop auto brightness and idle before cleanup so they can't fade again
+        for auto_task in auto_tasks {
+            auto_task.abort();
+        }
+
         // Cleanup devices
-        for backlight in backlights {
+        for (_, backlight, _, _) in backlights {
             if let Err(e) = backlight.deinit() {
                 error!("failed to deinit backlight {}: {}", backlight.name(), e);
             }
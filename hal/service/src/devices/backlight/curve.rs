@@ -0,0 +1,242 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+// CIE 1931 lightness constants, L* = 116 * Y^(1/3) - 16 above the linear toe
+const CIE_KAPPA: f32 = 903.3;
+const CIE_EPSILON: f32 = 0.008856;
+const MAX_LUT_POINTS: usize = 256;
+// Brightness reported for a lit backlight whose curve maps it back to 0, like one sitting at min
+const MIN_LIT_BRIGHTNESS: f32 = 0.001;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveKind {
+    Linear, // raw value proportional to brightness
+    Gamma,  // raw = brightness ^ gamma
+    Cie,    // brightness is CIE 1931 lightness L* / 100
+    Lut,    // linear interpolation between table points
+}
+
+impl CurveKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CurveKind::Linear => "linear",
+            CurveKind::Gamma => "gamma",
+            CurveKind::Cie => "cie",
+            CurveKind::Lut => "lut",
+        }
+    }
+}
+
+impl fmt::Display for CurveKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CurveKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(CurveKind::Linear),
+            "gamma" => Ok(CurveKind::Gamma),
+            "cie" => Ok(CurveKind::Cie),
+            "lut" => Ok(CurveKind::Lut),
+            _ => Err(format!(
+                "invalid curve '{}', expected linear, gamma, cie or lut",
+                s
+            )),
+        }
+    }
+}
+
+/// Brightness curve, persisted per backlight
+/// 把 0.0~1.0 的感知亮度映射到 sysfs 的线性值，AMOLED 这类面板用线性映射时低亮度段太敏感
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BrightnessCurve {
+    pub curve: CurveKind,
+    pub gamma: f32,    // exponent of the gamma curve
+    pub lut: Vec<f32>, // output levels 0.0~1.0 at evenly spaced inputs, non-decreasing
+    pub min: f32,      // fraction of max_brightness any non-zero brightness gets at least
+}
+
+impl Default for BrightnessCurve {
+    fn default() -> Self {
+        Self {
+            curve: CurveKind::Linear,
+            gamma: 2.2,
+            lut: Vec::new(),
+            min: 0.0,
+        }
+    }
+}
+
+impl BrightnessCurve {
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid_input = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        if !(0.1..=5.0).contains(&self.gamma) {
+            return invalid_input(format!(
+                "invalid gamma {}, must be between 0.1 and 5",
+                self.gamma
+            ));
+        }
+        if !(0.0..=0.5).contains(&self.min) {
+            return invalid_input(format!(
+                "invalid min {}, must be between 0 and 0.5",
+                self.min
+            ));
+        }
+        if self.curve == CurveKind::Lut {
+            if !(2..=MAX_LUT_POINTS).contains(&self.lut.len()) {
+                return invalid_input(format!(
+                    "lut needs 2 to {} points, got {}",
+                    MAX_LUT_POINTS,
+                    self.lut.len()
+                ));
+            }
+            if self.lut.iter().any(|point| !(0.0..=1.0).contains(point)) {
+                return invalid_input("lut points must be between 0.0 and 1.0".to_string());
+            }
+            if self.lut.windows(2).any(|pair| pair[1] < pair[0]) {
+                return invalid_input("lut points must be non-decreasing".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Perceived brightness to the fraction of max_brightness to write
+    pub fn apply(&self, brightness: f32) -> f32 {
+        let brightness = brightness.clamp(0.0, 1.0);
+        if brightness <= 0.0 {
+            return 0.0;
+        }
+        self.min + (1.0 - self.min) * self.shape(brightness).clamp(0.0, 1.0)
+    }
+
+    /// Fraction of max_brightness read back to perceived brightness, the inverse of `apply`
+    /// Only a backlight that is off reads back as 0, so callers can tell lit from off
+    pub fn invert(&self, linear: f32) -> f32 {
+        let linear = linear.clamp(0.0, 1.0);
+        if linear <= 0.0 {
+            return 0.0;
+        }
+        let x = ((linear - self.min) / (1.0 - self.min)).max(0.0);
+        self.inverse(x).clamp(MIN_LIT_BRIGHTNESS, 1.0)
+    }
+
+    fn shape(&self, x: f32) -> f32 {
+        match self.curve {
+            CurveKind::Linear => x,
+            CurveKind::Gamma => x.powf(self.gamma),
+            CurveKind::Cie => {
+                let lightness = x * 100.0;
+                if lightness > CIE_KAPPA * CIE_EPSILON {
+                    ((lightness + 16.0) / 116.0).powi(3)
+                } else {
+                    lightness / CIE_KAPPA
+                }
+            }
+            CurveKind::Lut => {
+                let Some(last) = self.lut.len().checked_sub(1).filter(|last| *last > 0) else {
+                    return x;
+                };
+                let position = x * last as f32;
+                let i = (position.floor() as usize).min(last - 1);
+                let t = position - i as f32;
+                self.lut[i] + (self.lut[i + 1] - self.lut[i]) * t
+            }
+        }
+    }
+
+    fn inverse(&self, y: f32) -> f32 {
+        match self.curve {
+            CurveKind::Linear => y,
+            CurveKind::Gamma => y.powf(1.0 / self.gamma),
+            CurveKind::Cie => {
+                let lightness = if y > CIE_EPSILON {
+                    116.0 * y.cbrt() - 16.0
+                } else {
+                    CIE_KAPPA * y
+                };
+                lightness / 100.0
+            }
+            CurveKind::Lut => {
+                let Some(last) = self.lut.len().checked_sub(1).filter(|last| *last > 0) else {
+                    return y;
+                };
+                if y <= self.lut[0] {
+                    return 0.0;
+                }
+                // First segment reaching y, flat segments map to their start
+                match (0..last).find(|&i| self.lut[i + 1] >= y) {
+                    Some(i) => {
+                        let (a, b) = (self.lut[i], self.lut[i + 1]);
+                        let t = if b > a { (y - a) / (b - a) } else { 0.0 };
+                        (i as f32 + t) / last as f32
+                    }
+                    None => 1.0,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(curve: CurveKind, lut: Vec<f32>, min: f32) -> BrightnessCurve {
+        BrightnessCurve {
+            curve,
+            lut,
+            min,
+            ..Default::default()
+        }
+    }
+
+    fn assert_round_trip(curve: &BrightnessCurve) {
+        curve.validate().unwrap();
+        assert_eq!(curve.invert(curve.apply(0.0)), 0.0);
+        for step in 1..=100 {
+            let brightness = step as f32 / 100.0;
+            let back = curve.invert(curve.apply(brightness));
+            assert!(
+                (back - brightness).abs() < 1e-3,
+                "{} curve: {} came back as {}",
+                curve.curve,
+                brightness,
+                back
+            );
+        }
+    }
+
+    #[test]
+    fn curves_round_trip() {
+        for min in [0.0, 0.1] {
+            assert_round_trip(&curve(CurveKind::Linear, Vec::new(), min));
+            assert_round_trip(&curve(CurveKind::Gamma, Vec::new(), min));
+            assert_round_trip(&curve(CurveKind::Cie, Vec::new(), min));
+            assert_round_trip(&curve(CurveKind::Lut, vec![0.0, 0.1, 0.4, 1.0], min));
+        }
+    }
+
+    #[test]
+    fn lit_backlight_never_reads_back_as_off() {
+        let lut = vec![0.05, 0.5, 1.0];
+        for kind in [
+            CurveKind::Linear,
+            CurveKind::Gamma,
+            CurveKind::Cie,
+            CurveKind::Lut,
+        ] {
+            let curve = curve(kind, lut.clone(), 0.1);
+            assert_eq!(curve.invert(0.1), MIN_LIT_BRIGHTNESS, "{} curve", kind);
+            assert!(curve.invert(curve.apply(MIN_LIT_BRIGHTNESS)) > 0.0);
+            assert_eq!(curve.invert(0.0), 0.0);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::common::storage;
+use crate::devices::backlight::{Backlight, BrightnessCurve};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::time;
+
+// About 60 steps per second, sysfs writes are cheap
+const FADE_STEP: Duration = Duration::from_millis(16);
+// Longest fade accepted
+const MAX_FADE_MS: u64 = 600_000;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeCurve {
+    Linear,
+    Ease,    // slow start and end (smoothstep)
+    EaseIn,  // slow start
+    EaseOut, // slow end
+}
+
+impl FadeCurve {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FadeCurve::Linear => "linear",
+            FadeCurve::Ease => "ease",
+            FadeCurve::EaseIn => "ease_in",
+            FadeCurve::EaseOut => "ease_out",
+        }
+    }
+
+    /// Map progress 0.0~1.0 to eased progress 0.0~1.0
+    fn apply(&self, t: f32) -> f32 {
+        match self {
+            FadeCurve::Linear => t,
+            FadeCurve::Ease => t * t * (3.0 - 2.0 * t),
+            FadeCurve::EaseIn => t * t,
+            FadeCurve::EaseOut => t * (2.0 - t),
+        }
+    }
+}
+
+impl fmt::Display for FadeCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for FadeCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(FadeCurve::Linear),
+            "ease" => Ok(FadeCurve::Ease),
+            "ease_in" => Ok(FadeCurve::EaseIn),
+            "ease_out" => Ok(FadeCurve::EaseOut),
+            _ => Err(format!(
+                "invalid curve '{}', expected linear, ease, ease_in or ease_out",
+                s
+            )),
+        }
+    }
+}
+
+/// A running fade
+#[derive(Serialize, Debug, Clone)]
+pub struct FadeInfo {
+    pub from: f32,
+    pub to: f32,
+    pub ms: u64,
+    pub curve: FadeCurve,
+}
+
+#[derive(Default)]
+struct FadeState {
+    generation: u64, // bumped by every set or fade, a fade task stops once it's stale
+    fade: Option<FadeInfo>,
+}
+
+/// Backlight with fades and a brightness curve
+/// 包一层背光设备，亮度按感知曲线映射后再写 sysfs；渐变由每个设备自己的任务按时间逐步写入，任何新的 set 或 fade 都会打断正在进行的渐变
+pub struct FadingBacklight {
+    id: String,
+    inner: Arc<dyn Backlight + Send + Sync>,
+    curve: Arc<RwLock<BrightnessCurve>>,
+    state: Arc<Mutex<FadeState>>,
+}
+
+impl FadingBacklight {
+    pub fn new(id: &str, inner: Arc<dyn Backlight + Send + Sync>) -> Self {
+        let curve: BrightnessCurve = storage::load(&format!("{}/curve", id))
+            .filter(|curve: &BrightnessCurve| match curve.validate() {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("{} stored brightness curve ignored: {}", id, e);
+                    false
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            id: id.to_string(),
+            inner,
+            curve: Arc::new(RwLock::new(curve)),
+            state: Arc::new(Mutex::new(FadeState::default())),
+        }
+    }
+
+    pub fn curve(&self) -> BrightnessCurve {
+        self.curve.read().unwrap().clone()
+    }
+
+    /// Change and persist the curve, the raw value stays until the next set or fade
+    pub fn set_curve(&self, curve: BrightnessCurve) -> io::Result<()> {
+        curve.validate()?;
+        *self.curve.write().unwrap() = curve.clone();
+        info!("{} brightness curve: {:?}", self.id, curve);
+        storage::save(&format!("{}/curve", self.id), &curve)
+    }
+
+    /// The fade in progress, if any
+    pub fn fade_info(&self) -> Option<FadeInfo> {
+        self.state.lock().unwrap().fade.clone()
+    }
+
+    /// Fade from the current brightness to `to` (0.0~1.0) over `ms`, replacing any fade in progress
+    pub fn fade(&self, to: f32, ms: u64, curve: FadeCurve) -> io::Result<()> {
+        if ms > MAX_FADE_MS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid ms {}, must be up to {}", ms, MAX_FADE_MS),
+            ));
+        }
+        let to = to.clamp(0.0, 1.0);
+        if ms == 0 {
+            return self.set_brightness(to);
+        }
+        let from = self.get_brightness()?;
+
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            state.generation += 1;
+            state.fade = Some(FadeInfo {
+                from,
+                to,
+                ms,
+                curve,
+            });
+            state.generation
+        };
+        debug!(
+            "backlight {} fade {} -> {} in {} ms, {}",
+            self.inner.name(),
+            from,
+            to,
+            ms,
+            curve
+        );
+
+        let inner = Arc::clone(&self.inner);
+        let curve_map = Arc::clone(&self.curve);
+        let state = Arc::clone(&self.state);
+        let duration = Duration::from_millis(ms);
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let mut interval = time::interval(FADE_STEP);
+            interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+            let max = inner.max_brightness().max(1) as f32;
+            let mut last_raw = None;
+            loop {
+                interval.tick().await;
+                let t = (start.elapsed().as_secs_f32() / duration.as_secs_f32()).min(1.0);
+                let brightness = from + (to - from) * curve.apply(t);
+
+                // Check and write under the lock so a concurrent set always wins
+                let mut state = state.lock().unwrap();
+                if state.generation != generation {
+                    debug!("backlight {} fade cancelled", inner.name());
+                    return;
+                }
+                let linear = curve_map.read().unwrap().apply(brightness);
+                let raw = (linear * max).round() as u32;
+                if last_raw != Some(raw)
+                    && let Err(e) = inner.set_brightness(linear)
+                {
+                    warn!("backlight {} fade failed: {}", inner.name(), e);
+                    state.fade = None;
+                    return;
+                }
+                last_raw = Some(raw);
+                if t >= 1.0 {
+                    state.fade = None;
+                    debug!("backlight {} fade done", inner.name());
+                    return;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    // Stop any fade in progress where it is, its task notices on the next step
+    fn cancel(state: &mut FadeState) {
+        state.generation += 1;
+        state.fade = None;
+    }
+}
+
+impl Backlight for FadingBacklight {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn device_type(&self) -> String {
+        self.inner.device_type()
+    }
+
+    fn max_brightness(&self) -> u32 {
+        self.inner.max_brightness()
+    }
+
+    fn get_brightness(&self) -> io::Result<f32> {
+        let linear = self.inner.get_brightness()?;
+        Ok(self.curve.read().unwrap().invert(linear))
+    }
+
+    fn set_brightness(&self, brightness: f32) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        Self::cancel(&mut state);
+        let linear = self.curve.read().unwrap().apply(brightness);
+        self.inner.set_brightness(linear)
+    }
+
+    fn init(&self) -> io::Result<()> {
+        self.inner.init()
+    }
+
+    fn deinit(&self) -> io::Result<()> {
+        Self::cancel(&mut self.state.lock().unwrap());
+        self.inner.deinit()
+    }
+}
@@ -0,0 +1,290 @@
+use crate::common::storage;
+use crate::devices::backlight::{Backlight, FadeCurve, FadingBacklight};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::time;
+
+// Longest idle timeout, a day
+const MAX_IDLE_TIMEOUT: u64 = 86_400;
+
+/// Idle policy of a backlight, persisted per backlight
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IdleSettings {
+    pub timeout: u64, // seconds without activity before turning off, 0 disables
+    pub fade_ms: u64, // fade out on idle and back in on wake
+}
+
+impl Default for IdleSettings {
+    fn default() -> Self {
+        Self {
+            timeout: 0,
+            fade_ms: 500,
+        }
+    }
+}
+
+impl IdleSettings {
+    pub fn validate(&self) -> io::Result<()> {
+        if self.timeout > MAX_IDLE_TIMEOUT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "invalid timeout {}, must be up to {} seconds",
+                    self.timeout, MAX_IDLE_TIMEOUT
+                ),
+            ));
+        }
+        if self.fade_ms > 10_000 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid fade_ms {}, must be up to 10000", self.fade_ms),
+            ));
+        }
+        Ok(())
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        (self.timeout > 0).then(|| Duration::from_secs(self.timeout))
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IdleInfo {
+    #[serde(flatten)]
+    pub settings: IdleSettings,
+    pub idle: bool,
+    pub saved_brightness: Option<f32>, // restored on wake
+}
+
+struct IdleState {
+    idle: bool,
+    last_activity: Instant,
+    saved_brightness: Option<f32>,
+}
+
+/// Idle policy of a backlight
+/// 给不属于屏幕的背光用：一段时间没有活动（set、fade 或 IMU 运动唤醒）就渐变关掉，记住之前的亮度，唤醒时渐变恢复；屏幕空闲策略关联的背光由屏幕负责
+pub struct BacklightIdle {
+    id: String,
+    backlight: Arc<FadingBacklight>,
+    settings: RwLock<IdleSettings>,
+    state: Mutex<IdleState>,
+    notify: Notify,
+}
+
+impl BacklightIdle {
+    pub fn new(id: &str, backlight: Arc<FadingBacklight>) -> Self {
+        let settings: IdleSettings = storage::load(&format!("{}/idle", id))
+            .filter(|settings: &IdleSettings| match settings.validate() {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("{} stored idle settings ignored: {}", id, e);
+                    false
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            id: id.to_string(),
+            backlight,
+            settings: RwLock::new(settings),
+            state: Mutex::new(IdleState {
+                idle: false,
+                last_activity: Instant::now(),
+                saved_brightness: None,
+            }),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn settings(&self) -> IdleSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Change and persist settings, an idle backlight wakes up first and the timer restarts
+    pub fn set_settings(&self, settings: IdleSettings) -> io::Result<()> {
+        settings.validate()?;
+        self.on_activity("idle settings")?;
+        *self.settings.write().unwrap() = settings.clone();
+        info!("{} idle policy: {:?}", self.id, settings);
+        self.notify.notify_one();
+        storage::save(&format!("{}/idle", self.id), &settings)
+    }
+
+    pub fn info(&self) -> IdleInfo {
+        let state = self.state.lock().unwrap();
+        IdleInfo {
+            settings: self.settings(),
+            idle: state.idle,
+            saved_brightness: state.saved_brightness,
+        }
+    }
+
+    /// Record activity like motion, restarts the idle timer and restores an idle backlight
+    pub fn on_activity(&self, source: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.last_activity = Instant::now();
+        if !state.idle {
+            return Ok(());
+        }
+
+        info!("{} wake up from idle on {}", self.id, source);
+        state.idle = false;
+        self.notify.notify_one();
+        match state.saved_brightness.take() {
+            Some(brightness) => {
+                self.backlight
+                    .fade(brightness, self.settings().fade_ms, FadeCurve::Ease)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Record a brightness set by hand, it replaces the one saved while idle
+    pub fn on_set(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.last_activity = Instant::now();
+        state.idle = false;
+        state.saved_brightness = None;
+        self.notify.notify_one();
+    }
+
+    /// Turn the backlight off when idle for the timeout, runs until the task is aborted
+    pub async fn run(&self) {
+        loop {
+            let deadline = {
+                let state = self.state.lock().unwrap();
+                match self.settings().timeout() {
+                    Some(timeout) if !state.idle => Some(state.last_activity + timeout),
+                    _ => None,
+                }
+            };
+
+            match deadline {
+                Some(deadline) => {
+                    tokio::select! {
+                        _ = time::sleep_until(deadline.into()) => self.check_idle(Instant::now()),
+                        _ = self.notify.notified() => {}
+                    }
+                }
+                None => self.notify.notified().await,
+            }
+        }
+    }
+
+    fn check_idle(&self, now: Instant) {
+        let settings = self.settings();
+        let Some(timeout) = settings.timeout() else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap();
+        if state.idle || now.saturating_duration_since(state.last_activity) < timeout {
+            return;
+        }
+
+        // A backlight already off, by hand or by a screen's idle policy, is left alone
+        let brightness = match self.backlight.get_brightness() {
+            Ok(brightness) if brightness <= 0.0 => {
+                state.last_activity = now;
+                return;
+            }
+            Ok(brightness) => brightness,
+            Err(e) => {
+                warn!("{} get brightness before idle failed: {}", self.id, e);
+                state.last_activity = now;
+                return;
+            }
+        };
+
+        info!(
+            "{} idle for {}s, turn off backlight",
+            self.id,
+            timeout.as_secs()
+        );
+        if let Err(e) = self.backlight.fade(0.0, settings.fade_ms, FadeCurve::Ease) {
+            warn!("{} turn off backlight failed: {}", self.id, e);
+            state.last_activity = now;
+            return;
+        }
+        state.idle = true;
+        state.saved_brightness = Some(brightness);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::backlight::MockBacklight;
+
+    fn idle_backlight(timeout: u64) -> BacklightIdle {
+        let backlight = Arc::new(FadingBacklight::new(
+            "backlight0",
+            Arc::new(MockBacklight::new("mock", 100)),
+        ));
+        let idle = BacklightIdle::new("backlight0", backlight);
+        *idle.settings.write().unwrap() = IdleSettings {
+            timeout,
+            fade_ms: 0,
+        };
+        idle
+    }
+
+    #[test]
+    fn idle_turns_off_and_motion_restores() {
+        let idle = idle_backlight(60);
+        idle.backlight.set_brightness(0.6).unwrap();
+        let start = idle.state.lock().unwrap().last_activity;
+
+        idle.check_idle(start + Duration::from_secs(30));
+        assert!(!idle.info().idle);
+
+        idle.check_idle(start + Duration::from_secs(60));
+        assert!(idle.info().idle);
+        assert_eq!(idle.backlight.get_brightness().unwrap(), 0.0);
+
+        idle.on_activity("motion").unwrap();
+        let info = idle.info();
+        assert!(!info.idle);
+        assert_eq!(info.saved_brightness, None);
+        assert!((idle.backlight.get_brightness().unwrap() - 0.6).abs() < 0.01);
+    }
+
+    #[test]
+    fn backlight_turned_off_is_left_off() {
+        let idle = idle_backlight(60);
+        idle.backlight.set_brightness(0.0).unwrap();
+        let start = idle.state.lock().unwrap().last_activity;
+
+        idle.check_idle(start + Duration::from_secs(60));
+        assert!(!idle.info().idle);
+        idle.on_activity("motion").unwrap();
+        assert_eq!(idle.backlight.get_brightness().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn set_by_hand_replaces_saved_brightness() {
+        let idle = idle_backlight(60);
+        idle.backlight.set_brightness(0.6).unwrap();
+        let start = idle.state.lock().unwrap().last_activity;
+        idle.check_idle(start + Duration::from_secs(60));
+        assert!(idle.info().idle);
+
+        idle.on_set();
+        idle.backlight.set_brightness(0.3).unwrap();
+        idle.on_activity("motion").unwrap();
+        assert!((idle.backlight.get_brightness().unwrap() - 0.3).abs() < 0.01);
+    }
+
+    #[test]
+    fn disabled_policy_never_idles() {
+        let idle = idle_backlight(0);
+        idle.backlight.set_brightness(0.6).unwrap();
+        let start = idle.state.lock().unwrap().last_activity;
+        idle.check_idle(start + Duration::from_secs(MAX_IDLE_TIMEOUT));
+        assert!(!idle.info().idle);
+    }
+}
@@ -1,9 +1,18 @@
+mod auto;
+#[allow(clippy::module_inception)]
 mod backlight;
+mod curve;
+mod fade;
+mod idle;
 mod mock;
 mod sysfs;
 mod types;
 
-pub use backlight::start_backlight_service;
+pub use auto::{AutoBrightness, AutoSettings};
+pub use backlight::{find_backlight, start_backlight_service, wake_backlights};
+pub use curve::{BrightnessCurve, CurveKind};
+pub use fade::{FadeCurve, FadingBacklight};
+pub use idle::{BacklightIdle, IdleSettings};
 pub use mock::MockBacklight;
 pub use sysfs::SysfsBacklight;
 pub use types::Backlight;
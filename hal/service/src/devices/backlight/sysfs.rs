@@ -7,7 +7,6 @@ pub struct SysfsBacklight {
     name: String,
     device_path: PathBuf,
     brightness_path: PathBuf,
-    max_brightness_path: PathBuf,
     max_brightness: u32,
 }
 
@@ -70,7 +69,6 @@ impl SysfsBacklight {
                     name: device_name,
                     device_path,
                     brightness_path,
-                    max_brightness_path,
                     max_brightness,
                 });
             }
@@ -126,7 +124,7 @@ impl Backlight for SysfsBacklight {
     }
 
     fn device_type(&self) -> String {
-        format!("/sys/class/backlight/{}", self.name)
+        self.device_path.display().to_string()
     }
 
     fn max_brightness(&self) -> u32 {
@@ -0,0 +1,351 @@
+use crate::common::storage;
+use crate::devices::imu::ImuData;
+use log::{debug, info};
+use nalgebra::{DMatrix, DVector, Matrix3, Vector3};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use tokio::time;
+
+// Gyro noise above this during a still capture means the device was moved
+const STILL_GYRO_STD_LIMIT: f32 = 0.05; // radians/s
+const STANDARD_GRAVITY: f32 = 9.80665;
+const MIN_MAG_SAMPLES: usize = 50;
+
+/// Calibration capture
+#[derive(Debug, Clone, Copy)]
+pub enum CalibrationKind {
+    Still, // gyro bias and accel offsets, device lies still
+    Mag,   // mag hard and soft iron, device rotates in all directions
+}
+
+/// Calibration values, persisted per IMU
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Calibration {
+    pub gyro_bias: [f32; 3],          // subtracted from gyro
+    pub accel_offset: [f32; 3],       // subtracted from accel
+    pub mag_hard_iron: [f32; 3],      // subtracted from mag
+    pub mag_soft_iron: [[f32; 3]; 3], // row major, applied after hard iron
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            gyro_bias: [0.0; 3],
+            accel_offset: [0.0; 3],
+            mag_hard_iron: [0.0; 3],
+            mag_soft_iron: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+}
+
+impl Calibration {
+    pub fn apply(&self, imu_data: &mut ImuData) {
+        for i in 0..3 {
+            imu_data.gyro[i] -= self.gyro_bias[i];
+            imu_data.accel[i] -= self.accel_offset[i];
+        }
+
+        let mag: [f32; 3] = std::array::from_fn(|i| imu_data.mag[i] - self.mag_hard_iron[i]);
+        imu_data.mag =
+            std::array::from_fn(|i| (0..3).map(|j| self.mag_soft_iron[i][j] * mag[j]).sum());
+    }
+}
+
+/// IMU calibrator
+/// 采集原始数据计算陀螺仪零偏、加速度计偏移和磁力计硬磁/软磁校正，融合和发布之前应用
+pub struct Calibrator {
+    id: String,
+    calibration: RwLock<Calibration>,
+    // Raw samples collected while a capture is going on
+    capture: Mutex<Option<Vec<ImuData>>>,
+}
+
+impl Calibrator {
+    pub fn new(id: &str) -> Self {
+        let calibration: Calibration =
+            storage::load(&format!("{}/calibration", id)).unwrap_or_default();
+
+        Self {
+            id: id.to_string(),
+            calibration: RwLock::new(calibration),
+            capture: Mutex::new(None),
+        }
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration.read().unwrap().clone()
+    }
+
+    /// Apply and persist calibration
+    pub fn set_calibration(&self, calibration: Calibration) -> io::Result<()> {
+        *self.calibration.write().unwrap() = calibration.clone();
+        storage::save(&format!("{}/calibration", self.id), &calibration)
+    }
+
+    /// Feed a raw sample to an ongoing capture, then calibrate it
+    pub fn apply(&self, imu_data: &mut ImuData) {
+        if let Some(samples) = self.capture.lock().unwrap().as_mut() {
+            samples.push(imu_data.clone());
+        }
+        self.calibration.read().unwrap().apply(imu_data);
+    }
+
+    /// Capture while the device lies still, then compute gyro bias and accel offsets
+    pub async fn calibrate_still(&self, duration: Duration) -> io::Result<Calibration> {
+        let samples = self.capture(duration).await?;
+
+        let gyro_mean = mean(samples.iter().map(|s| s.gyro));
+        let gyro_std = std_dev(samples.iter().map(|s| s.gyro), gyro_mean);
+        if gyro_std.iter().any(|std| *std > STILL_GYRO_STD_LIMIT) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "device moved during capture (gyro std {:?}), keep it still",
+                    gyro_std
+                ),
+            ));
+        }
+
+        // Gravity is expected on the dominant axis only, in whatever unit the driver reports
+        let accel_mean = mean(samples.iter().map(|s| s.accel));
+        let norm = accel_mean.iter().map(|a| a * a).sum::<f32>().sqrt();
+        let gravity = if (norm - 1.0).abs() < (norm - STANDARD_GRAVITY).abs() {
+            1.0
+        } else {
+            STANDARD_GRAVITY
+        };
+        let axis = (0..3)
+            .max_by(|a, b| accel_mean[*a].abs().total_cmp(&accel_mean[*b].abs()))
+            .unwrap_or(2);
+        let mut expected = [0.0; 3];
+        expected[axis] = gravity.copysign(accel_mean[axis]);
+
+        let mut calibration = self.calibration();
+        calibration.gyro_bias = gyro_mean;
+        calibration.accel_offset = std::array::from_fn(|i| accel_mean[i] - expected[i]);
+        self.set_calibration(calibration.clone())?;
+
+        info!(
+            "{} still calibration done: gyro bias {:?}, accel offset {:?}",
+            self.id, calibration.gyro_bias, calibration.accel_offset
+        );
+        Ok(calibration)
+    }
+
+    /// Capture while the device is rotated in all directions, then fit an ellipsoid to mag samples
+    pub async fn calibrate_mag(&self, duration: Duration) -> io::Result<Calibration> {
+        let samples = self.capture(duration).await?;
+        let (hard_iron, soft_iron) = fit_ellipsoid(&samples)?;
+
+        let mut calibration = self.calibration();
+        calibration.mag_hard_iron = hard_iron;
+        calibration.mag_soft_iron = soft_iron;
+        self.set_calibration(calibration.clone())?;
+
+        info!(
+            "{} mag calibration done: hard iron {:?}, soft iron {:?}",
+            self.id, calibration.mag_hard_iron, calibration.mag_soft_iron
+        );
+        Ok(calibration)
+    }
+
+    /// Collect raw samples for `duration`, only one capture at a time
+    async fn capture(&self, duration: Duration) -> io::Result<Vec<ImuData>> {
+        {
+            let mut capture = self.capture.lock().unwrap();
+            if capture.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::ResourceBusy,
+                    format!("{} is already capturing", self.id),
+                ));
+            }
+            *capture = Some(Vec::new());
+        }
+
+        debug!("{} capture raw samples for {:?}", self.id, duration);
+        time::sleep(duration).await;
+
+        let samples = self.capture.lock().unwrap().take().unwrap_or_default();
+        if samples.is_empty() {
+            return Err(io::Error::other(format!(
+                "{} got no samples during capture",
+                self.id
+            )));
+        }
+        Ok(samples)
+    }
+}
+
+fn mean(values: impl Iterator<Item = [f32; 3]>) -> [f32; 3] {
+    let (sum, count) = values.fold(([0.0; 3], 0), |(sum, count), value| {
+        (std::array::from_fn(|i| sum[i] + value[i]), count + 1)
+    });
+    sum.map(|s| s / count.max(1) as f32)
+}
+
+fn std_dev(values: impl Iterator<Item = [f32; 3]>, mean: [f32; 3]) -> [f32; 3] {
+    let (sum, count) = values.fold(([0.0; 3], 0), |(sum, count), value| {
+        (
+            std::array::from_fn(|i| sum[i] + (value[i] - mean[i]).powi(2)),
+            count + 1,
+        )
+    });
+    sum.map(|s| (s / count.max(1) as f32).sqrt())
+}
+
+/// Least squares fit of `ax² + by² + cz² + 2dxy + 2exz + 2fyz + 2gx + 2hy + 2iz = 1`,
+/// returns hard iron center and the soft iron matrix mapping the ellipsoid to a sphere of the mean radius
+fn fit_ellipsoid(samples: &[ImuData]) -> io::Result<([f32; 3], [[f32; 3]; 3])> {
+    let fit_failed = |reason: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "mag ellipsoid fit failed: {}, rotate the device slowly in all directions",
+                reason
+            ),
+        )
+    };
+    if samples.len() < MIN_MAG_SAMPLES {
+        return Err(fit_failed(&format!(
+            "only {} samples, need at least {}",
+            samples.len(),
+            MIN_MAG_SAMPLES
+        )));
+    }
+
+    let rows = samples.len();
+    let mut design = DMatrix::<f64>::zeros(rows, 9);
+    for (row, sample) in samples.iter().enumerate() {
+        let [x, y, z] = sample.mag.map(|m| m as f64);
+        let terms = [
+            x * x,
+            y * y,
+            z * z,
+            2.0 * x * y,
+            2.0 * x * z,
+            2.0 * y * z,
+            2.0 * x,
+            2.0 * y,
+            2.0 * z,
+        ];
+        for (col, term) in terms.into_iter().enumerate() {
+            design[(row, col)] = term;
+        }
+    }
+    let params = design
+        .svd(true, true)
+        .solve(&DVector::from_element(rows, 1.0), 1e-12)
+        .map_err(fit_failed)?;
+
+    let shape = Matrix3::new(
+        params[0], params[3], params[4], //
+        params[3], params[1], params[5], //
+        params[4], params[5], params[2],
+    );
+    let linear = Vector3::new(params[6], params[7], params[8]);
+    let center = -shape
+        .try_inverse()
+        .ok_or_else(|| fit_failed("singular shape matrix"))?
+        * linear;
+
+    // (m - c)ᵀ S (m - c) = 1 + cᵀ S c
+    let shape = shape / (1.0 + center.dot(&(shape * center)));
+    let eigen = shape.symmetric_eigen();
+    if eigen.eigenvalues.iter().any(|value| *value <= 0.0) {
+        return Err(fit_failed("samples are not on an ellipsoid"));
+    }
+
+    // sqrt(S) maps the ellipsoid to a unit sphere, scale back to the geometric mean radius
+    let radius = eigen
+        .eigenvalues
+        .iter()
+        .map(|value| 1.0 / value.sqrt())
+        .product::<f64>()
+        .cbrt();
+    let soft_iron = eigen.eigenvectors
+        * Matrix3::from_diagonal(&eigen.eigenvalues.map(|value| value.sqrt() * radius))
+        * eigen.eigenvectors.transpose();
+
+    Ok((
+        [center.x as f32, center.y as f32, center.z as f32],
+        std::array::from_fn(|i| std::array::from_fn(|j| soft_iron[(i, j)] as f32)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Points on an ellipsoid with `radii` along rotated axes around `center`
+    fn ellipsoid(center: [f32; 3], radii: [f64; 3], rotation: Matrix3<f64>) -> Vec<ImuData> {
+        let mut samples = Vec::new();
+        for i in 0..20 {
+            for j in 0..20 {
+                let theta = std::f64::consts::PI * (i as f64 + 0.5) / 20.0;
+                let phi = 2.0 * std::f64::consts::PI * j as f64 / 20.0;
+                let unit = Vector3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                let point = rotation * unit.component_mul(&Vector3::from(radii));
+                samples.push(ImuData {
+                    mag: std::array::from_fn(|k| center[k] + point[k] as f32),
+                    ..Default::default()
+                });
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn fit_axis_aligned_ellipsoid() {
+        let center = [0.2, -0.1, 0.35];
+        let samples = ellipsoid(center, [0.6, 0.4, 0.3], Matrix3::identity());
+        let (hard_iron, soft_iron) = fit_ellipsoid(&samples).unwrap();
+
+        for i in 0..3 {
+            assert!((hard_iron[i] - center[i]).abs() < 1e-4, "{:?}", hard_iron);
+        }
+        // Each axis scaled to the geometric mean radius
+        let radius = (0.6f32 * 0.4 * 0.3).cbrt();
+        let expected = [radius / 0.6, radius / 0.4, radius / 0.3];
+        for i in 0..3 {
+            for j in 0..3 {
+                let value = if i == j { expected[i] } else { 0.0 };
+                assert!((soft_iron[i][j] - value).abs() < 1e-3, "{:?}", soft_iron);
+            }
+        }
+    }
+
+    #[test]
+    fn fit_rotated_ellipsoid_maps_to_sphere() {
+        let center = [-0.15, 0.25, 0.05];
+        let rotation = *nalgebra::Rotation3::from_euler_angles(0.3, -0.5, 1.1).matrix();
+        let samples = ellipsoid(center, [0.5, 0.45, 0.3], rotation);
+        let (hard_iron, soft_iron) = fit_ellipsoid(&samples).unwrap();
+
+        let radius = (0.5f32 * 0.45 * 0.3).cbrt();
+        for sample in &samples {
+            let mut imu_data = sample.clone();
+            Calibration {
+                mag_hard_iron: hard_iron,
+                mag_soft_iron: soft_iron,
+                ..Default::default()
+            }
+            .apply(&mut imu_data);
+            let norm = imu_data.mag.iter().map(|m| m * m).sum::<f32>().sqrt();
+            assert!((norm - radius).abs() < 1e-3, "{} vs {}", norm, radius);
+        }
+    }
+
+    #[test]
+    fn fit_rejects_too_few_samples() {
+        let samples = ellipsoid([0.0; 3], [0.5; 3], Matrix3::identity());
+        let err = fit_ellipsoid(&samples[..MIN_MAG_SAMPLES - 1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
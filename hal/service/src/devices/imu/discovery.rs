@@ -0,0 +1,87 @@
+use crate::common::iio::list_iio_devices;
+use crate::common::storage;
+use crate::devices::imu::{IioImu, Imu};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// IIO discovery settings, `<config-dir>/imu/discovery.json`, read at startup
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct DiscoverySettings {
+    pub include: Vec<String>,         // device names to use, empty for all
+    pub exclude: Vec<String>,         // device names to skip
+    pub order: Vec<String>, // listed names come first in this order, the rest by device number
+    pub mag: HashMap<String, String>, // imu name to magnetometer-only device name
+}
+
+/// Find every IIO device under `iio_devices_path` with accel or gyro channels, in the configured order
+/// Device nodes are looked up in `dev_dir`
+/// 枚举所有带加速度计或陀螺仪通道的 IIO 设备，按配置过滤、排序，并把单独的磁力计配对给对应的 IMU
+pub fn discover_iio_imus(iio_devices_path: &Path, dev_dir: &Path) -> Vec<IioImu> {
+    let settings: DiscoverySettings = storage::load("imu/discovery").unwrap_or_default();
+    debug!("iio discovery settings: {:?}", settings);
+
+    let mut imus = Vec::new();
+    let mut mags = Vec::new();
+    for device_path in list_iio_devices(iio_devices_path) {
+        let dev_path = dev_dir.join(device_path.file_name().unwrap_or_default());
+        let device = match IioImu::new(&device_path, &dev_path) {
+            Ok(device) => device,
+            Err(e) => {
+                debug!("skip iio device {}: {}", device_path.display(), e);
+                continue;
+            }
+        };
+        let name = device.name();
+        if !settings.include.is_empty() && !settings.include.contains(&name) {
+            debug!("skip iio device {}, not included", name);
+        } else if settings.exclude.contains(&name) {
+            debug!("skip iio device {}, excluded", name);
+        } else if device.is_imu() {
+            imus.push(device);
+        } else if device.has_mag() {
+            mags.push(device);
+        } else {
+            debug!("skip iio device {}, no accel, gyro or mag", name);
+        }
+    }
+
+    // Stable sort keeps device number order among names with the same rank
+    imus.sort_by_key(|imu| {
+        settings
+            .order
+            .iter()
+            .position(|name| *name == imu.name())
+            .unwrap_or(settings.order.len())
+    });
+
+    for imu in imus.iter_mut() {
+        let Some(mag_name) = settings.mag.get(&imu.name()) else {
+            continue;
+        };
+        match mags.iter().position(|mag| mag.name() == *mag_name) {
+            Some(index) => {
+                let mag = mags.remove(index);
+                info!(
+                    "pair iio magnetometer {} at {} with {}",
+                    mag_name,
+                    mag.device_path().display(),
+                    imu.name()
+                );
+                imu.pair_mag(mag);
+            }
+            None => warn!("iio magnetometer {} for {} not found", mag_name, imu.name()),
+        }
+    }
+
+    for imu in &imus {
+        info!(
+            "found iio imu {} at {}",
+            imu.name(),
+            imu.device_path().display()
+        );
+    }
+    imus
+}
@@ -0,0 +1,283 @@
+use crate::common::storage;
+use ahrs::{Ahrs, Madgwick, Mahony};
+use log::debug;
+use nalgebra::{UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
+
+/// Fusion settings, persisted per IMU
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct FusionSettings {
+    pub algorithm: FusionAlgorithm,
+    pub mag: bool,  // 9-DoF with magnetometer, otherwise 6-DoF and yaw drifts
+    pub beta: f64,  // Madgwick gain
+    pub kp: f64,    // Mahony proportional gain
+    pub ki: f64,    // Mahony integral gain
+    pub alpha: f64, // complementary filter gyro weight, 0 to 1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FusionAlgorithm {
+    Madgwick,
+    Mahony,
+    Complementary,
+}
+
+impl Default for FusionSettings {
+    fn default() -> Self {
+        Self {
+            algorithm: FusionAlgorithm::Madgwick,
+            mag: false,
+            beta: 0.1,
+            kp: 0.5,
+            ki: 0.0,
+            alpha: 0.98,
+        }
+    }
+}
+
+impl FromStr for FusionAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "madgwick" => Ok(FusionAlgorithm::Madgwick),
+            "mahony" => Ok(FusionAlgorithm::Mahony),
+            "complementary" => Ok(FusionAlgorithm::Complementary),
+            _ => Err(format!(
+                "invalid fusion algorithm '{}', expected madgwick, mahony or complementary",
+                s
+            )),
+        }
+    }
+}
+
+/// Sensor fusion
+/// 按配置选择 Madgwick / Mahony / 互补滤波，6 轴或带磁力计的 9 轴，按真实采样间隔更新
+pub struct Fusion {
+    id: String,
+    settings: RwLock<FusionSettings>,
+    filter: Mutex<Filter>,
+    sample_period: RwLock<f64>, // nominal at the last rebuild, used until real intervals are known
+    tare: RwLock<Option<UnitQuaternion<f64>>>, // reference orientation, kept until cleared
+}
+
+enum Filter {
+    Madgwick(Madgwick<f64>),
+    Mahony(Mahony<f64>),
+    Complementary {
+        quat: UnitQuaternion<f64>,
+        alpha: f64,
+        initialized: bool,
+    },
+}
+
+impl Fusion {
+    pub fn new(id: &str, sample_rate: f64) -> Self {
+        let settings: FusionSettings = storage::load(&format!("{}/fusion", id)).unwrap_or_default();
+        let sample_period = Self::period(sample_rate);
+
+        Self {
+            id: id.to_string(),
+            filter: Mutex::new(Filter::new(&settings, sample_period)),
+            settings: RwLock::new(settings),
+            sample_period: RwLock::new(sample_period),
+            tare: RwLock::new(None),
+        }
+    }
+
+    pub fn settings(&self) -> FusionSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Apply and persist fusion settings, orientation starts over at the current `sample_rate`
+    pub fn set_settings(&self, settings: FusionSettings, sample_rate: f64) -> io::Result<()> {
+        if !(0.0..=1.0).contains(&settings.alpha)
+            || settings.beta < 0.0
+            || settings.kp < 0.0
+            || settings.ki < 0.0
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "gains must not be negative and alpha must be 0 to 1",
+            ));
+        }
+
+        self.rebuild(&settings, sample_rate);
+        *self.settings.write().unwrap() = settings.clone();
+        debug!("{} fusion settings: {:?}", self.id, settings);
+        storage::save(&format!("{}/fusion", self.id), &settings)
+    }
+
+    /// Start over from identity orientation, at the current `sample_rate` which may have changed
+    pub fn reset(&self, sample_rate: f64) {
+        let settings = self.settings();
+        self.rebuild(&settings, sample_rate);
+    }
+
+    fn rebuild(&self, settings: &FusionSettings, sample_rate: f64) {
+        let sample_period = Self::period(sample_rate);
+        *self.sample_period.write().unwrap() = sample_period;
+        *self.filter.lock().unwrap() = Filter::new(settings, sample_period);
+    }
+
+    fn period(sample_rate: f64) -> f64 {
+        if sample_rate > 0.0 {
+            1.0 / sample_rate
+        } else {
+            1.0
+        }
+    }
+
+    /// Current orientation becomes the reference of `relative`
+    pub fn tare(&self) {
+        let quat = self.filter.lock().unwrap().quat();
+        debug!("{} tare at {:?}", self.id, quat.euler_angles());
+        *self.tare.write().unwrap() = Some(quat);
+    }
+
+    pub fn clear_tare(&self) {
+        *self.tare.write().unwrap() = None;
+    }
+
+    /// `quat` from `update` relative to the tare reference
+    pub fn relative(&self, quat: &UnitQuaternion<f64>) -> UnitQuaternion<f64> {
+        match *self.tare.read().unwrap() {
+            Some(reference) => reference.inverse() * quat,
+            None => *quat,
+        }
+    }
+
+    /// Update with one sample, `dt` is the real interval in seconds when known
+    pub fn update(
+        &self,
+        gyro: Vector3<f64>,
+        accel: Vector3<f64>,
+        mag: Vector3<f64>,
+        dt: Option<f64>,
+    ) -> UnitQuaternion<f64> {
+        let use_mag = self.settings.read().unwrap().mag;
+        let dt = dt.unwrap_or_else(|| *self.sample_period.read().unwrap());
+        let mut filter = self.filter.lock().unwrap();
+
+        let result = match &mut *filter {
+            Filter::Madgwick(madgwick) => {
+                *madgwick.sample_period_mut() = dt;
+                Self::update_ahrs(madgwick, &gyro, &accel, use_mag.then_some(&mag))
+            }
+            Filter::Mahony(mahony) => {
+                *mahony.sample_period_mut() = dt;
+                Self::update_ahrs(mahony, &gyro, &accel, use_mag.then_some(&mag))
+            }
+            Filter::Complementary {
+                quat,
+                alpha,
+                initialized,
+            } => {
+                Self::update_complementary(
+                    quat,
+                    *alpha,
+                    initialized,
+                    &gyro,
+                    &accel,
+                    use_mag.then_some(&mag),
+                    dt,
+                );
+                Ok(*quat)
+            }
+        };
+
+        match result {
+            Ok(quat) => quat,
+            Err(e) => {
+                // Zero accel or mag can't be normalized, keep last orientation
+                debug!("{} fusion update failed: {:?}", self.id, e);
+                filter.quat()
+            }
+        }
+    }
+
+    fn update_ahrs(
+        ahrs: &mut impl Ahrs<f64>,
+        gyro: &Vector3<f64>,
+        accel: &Vector3<f64>,
+        mag: Option<&Vector3<f64>>,
+    ) -> Result<UnitQuaternion<f64>, ahrs::AhrsError> {
+        match mag {
+            // Fall back to 6-DoF when mag reads zero, e.g. not present
+            Some(mag) if mag.norm() > 0.0 => ahrs.update(gyro, accel, mag).copied(),
+            _ => ahrs.update_imu(gyro, accel).copied(),
+        }
+    }
+
+    /// Integrate gyro, then pull roll and pitch towards accel and yaw towards tilt compensated mag heading
+    fn update_complementary(
+        quat: &mut UnitQuaternion<f64>,
+        alpha: f64,
+        initialized: &mut bool,
+        gyro: &Vector3<f64>,
+        accel: &Vector3<f64>,
+        mag: Option<&Vector3<f64>>,
+        dt: f64,
+    ) {
+        let predicted = *quat * UnitQuaternion::from_scaled_axis(gyro * dt);
+        if accel.norm() == 0.0 {
+            *quat = predicted;
+            return;
+        }
+
+        let roll = accel.y.atan2(accel.z);
+        let pitch = (-accel.x).atan2((accel.y * accel.y + accel.z * accel.z).sqrt());
+        let yaw = match mag {
+            Some(mag) if mag.norm() > 0.0 => {
+                let x = mag.x * pitch.cos()
+                    + mag.y * roll.sin() * pitch.sin()
+                    + mag.z * roll.cos() * pitch.sin();
+                let y = mag.y * roll.cos() - mag.z * roll.sin();
+                (-y).atan2(x)
+            }
+            _ => predicted.euler_angles().2,
+        };
+        let measured = UnitQuaternion::from_euler_angles(roll, pitch, yaw);
+
+        // Snap to the first measurement, the filter would take seconds to get there
+        *quat = if *initialized {
+            predicted
+                .try_slerp(&measured, 1.0 - alpha, 1e-9)
+                .unwrap_or(predicted)
+        } else {
+            *initialized = true;
+            measured
+        };
+    }
+}
+
+impl Filter {
+    fn new(settings: &FusionSettings, sample_period: f64) -> Self {
+        match settings.algorithm {
+            FusionAlgorithm::Madgwick => {
+                Filter::Madgwick(Madgwick::new(sample_period, settings.beta))
+            }
+            FusionAlgorithm::Mahony => {
+                Filter::Mahony(Mahony::new(sample_period, settings.kp, settings.ki))
+            }
+            FusionAlgorithm::Complementary => Filter::Complementary {
+                quat: UnitQuaternion::identity(),
+                alpha: settings.alpha,
+                initialized: false,
+            },
+        }
+    }
+
+    fn quat(&self) -> UnitQuaternion<f64> {
+        match self {
+            Filter::Madgwick(madgwick) => madgwick.quat(),
+            Filter::Mahony(mahony) => mahony.quat(),
+            Filter::Complementary { quat, .. } => *quat,
+        }
+    }
+}
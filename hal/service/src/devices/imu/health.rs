@@ -0,0 +1,325 @@
+use crate::common::storage;
+use crate::devices::imu::{IMU_CHANNELS, ImuData, MotionEventKind, timestamp_now};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::io;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+// Exactly the same value this long is stuck, real sensors always have some noise
+const STUCK_DURATION: u64 = 2_000_000; // microseconds
+const STUCK_SAMPLES: u64 = 10;
+// Within this share of full scale counts as saturated
+const SATURATION_MARGIN: f32 = 0.999;
+// Saturated for more than this share of a check window is a fault
+const SATURATED_SHARE: f64 = 0.5;
+// Measured rate below this share of nominal is a fault
+const LOW_RATE_SHARE: f64 = 0.5;
+// Reopen after being unhealthy this long, retries back off up to the max
+const REOPEN_AFTER: Duration = Duration::from_secs(5);
+const REOPEN_BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// Health settings, persisted per IMU
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct HealthSettings {
+    pub auto_reopen: bool, // stop and start the device when it stays unhealthy
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthFault {
+    Stuck,         // value hasn't changed at all
+    ReadErrors,    // reads kept failing during the last check
+    Saturated,     // mostly at full scale during the last check
+    LowSampleRate, // measured rate below half of nominal, channel "imu"
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FaultInfo {
+    pub channel: &'static str, // like IMU_CHANNELS, "buffer" or "imu"
+    pub fault: HealthFault,
+    pub since: u64, // microseconds since UNIX_EPOCH
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ChannelHealth {
+    pub read_errors: u64,       // failed reads since start
+    pub saturated: u64,         // samples at full scale since start
+    pub unchanged_seconds: f64, // how long the value has been exactly the same
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ImuHealth {
+    pub healthy: bool,
+    pub faults: Vec<FaultInfo>,
+    pub sample_rate: f64, // measured over the last check
+    pub nominal_sample_rate: f64,
+    pub samples: u64,
+    pub buffer_errors: u64,
+    pub reopens: u64,
+    pub auto_reopen: bool,
+    pub channels: BTreeMap<&'static str, ChannelHealth>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct ChannelState {
+    last: f32,
+    unchanged_since: u64,
+    unchanged_samples: u64,
+    active: bool, // reported a nonzero value, channels the device doesn't have stay zero
+    read_errors: u64,
+    read_errors_checked: u64,
+    saturated: u64,
+    saturated_window: u64,
+}
+
+#[derive(Default)]
+struct HealthState {
+    channels: [ChannelState; 10],
+    samples: u64,
+    last_timestamp: u64,
+    window_start: Option<Instant>,
+    window_samples: u64,
+    sample_rate: f64,
+    buffer_errors: u64,
+    buffer_errors_checked: u64,
+    faults: BTreeMap<(&'static str, HealthFault), u64>,
+    unhealthy_since: Option<Instant>,
+}
+
+struct ReopenState {
+    reopens: u64,
+    last: Option<Instant>,
+    backoff: Duration,
+}
+
+/// IMU health monitor
+/// 统计每个通道的读取失败、数值卡死、满量程饱和和实际采样率，传感器出问题时产生事件，可选自动重新打开设备
+pub struct HealthMonitor {
+    id: String,
+    settings: RwLock<HealthSettings>,
+    state: Mutex<HealthState>,
+    reopen: Mutex<ReopenState>,
+}
+
+impl HealthMonitor {
+    pub fn new(id: &str) -> Self {
+        let settings: HealthSettings = storage::load(&format!("{}/health", id)).unwrap_or_default();
+        Self {
+            id: id.to_string(),
+            settings: RwLock::new(settings),
+            state: Mutex::new(HealthState::default()),
+            reopen: Mutex::new(ReopenState {
+                reopens: 0,
+                last: None,
+                backoff: REOPEN_AFTER,
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> HealthSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: HealthSettings) -> io::Result<()> {
+        *self.settings.write().unwrap() = settings.clone();
+        debug!("{} health settings: {:?}", self.id, settings);
+        storage::save(&format!("{}/health", self.id), &settings)
+    }
+
+    /// Start over when the device starts, reopens are kept
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = HealthState::default();
+    }
+
+    /// Track one sample as read, before calibration
+    pub fn update(&self, imu_data: &ImuData, full_scale: Option<[f32; 2]>) {
+        let mut state = self.state.lock().unwrap();
+        let first = state.samples == 0;
+        state.samples += 1;
+        state.window_samples += 1;
+        state.last_timestamp = imu_data.timestamp;
+
+        let values = imu_data
+            .accel
+            .iter()
+            .chain(&imu_data.gyro)
+            .chain(&imu_data.mag)
+            .chain([&imu_data.temp]);
+        for (i, value) in values.enumerate() {
+            let channel = &mut state.channels[i];
+            if first || *value != channel.last {
+                channel.last = *value;
+                channel.unchanged_since = imu_data.timestamp;
+                channel.unchanged_samples = 0;
+            } else {
+                channel.unchanged_samples += 1;
+            }
+            channel.active |= *value != 0.0;
+
+            // Accel and gyro only
+            if let Some(full_scale) = full_scale.filter(|_| i < 6)
+                && value.abs() >= full_scale[i / 3] * SATURATION_MARGIN
+            {
+                channel.saturated += 1;
+                channel.saturated_window += 1;
+            }
+        }
+    }
+
+    pub fn record_buffer_error(&self) {
+        self.state.lock().unwrap().buffer_errors += 1;
+    }
+
+    /// Run about once a second, returns fault and recovery events
+    pub fn check(
+        &self,
+        read_errors: &[(&'static str, u64)],
+        nominal_sample_rate: f64,
+    ) -> Vec<MotionEventKind> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let now = Instant::now();
+
+        // First call only opens the rate window
+        let Some(window_start) = state.window_start.replace(now) else {
+            state.window_samples = 0;
+            return vec![];
+        };
+        let elapsed = now.duration_since(window_start).as_secs_f64();
+        let window_samples = std::mem::take(&mut state.window_samples);
+        state.sample_rate = window_samples as f64 / elapsed.max(f64::EPSILON);
+
+        let mut faults = Vec::new();
+        for (i, channel) in state.channels.iter_mut().enumerate() {
+            let name = IMU_CHANNELS[i];
+            if let Some((_, errors)) = read_errors.iter().find(|(n, _)| *n == name) {
+                channel.read_errors = *errors;
+            }
+            if channel.read_errors > channel.read_errors_checked {
+                faults.push((name, HealthFault::ReadErrors));
+            }
+            channel.read_errors_checked = channel.read_errors;
+
+            // Temperature may well sit still
+            if name != "temp"
+                && channel.active
+                && channel.unchanged_samples >= STUCK_SAMPLES
+                && state.last_timestamp.saturating_sub(channel.unchanged_since) >= STUCK_DURATION
+            {
+                faults.push((name, HealthFault::Stuck));
+            }
+
+            let saturated_window = std::mem::take(&mut channel.saturated_window);
+            if window_samples > 0
+                && saturated_window as f64 / window_samples as f64 > SATURATED_SHARE
+            {
+                faults.push((name, HealthFault::Saturated));
+            }
+        }
+        if state.buffer_errors > state.buffer_errors_checked {
+            faults.push(("buffer", HealthFault::ReadErrors));
+        }
+        state.buffer_errors_checked = state.buffer_errors;
+        if state.sample_rate < nominal_sample_rate * LOW_RATE_SHARE {
+            faults.push(("imu", HealthFault::LowSampleRate));
+        }
+
+        // Events for changes only
+        let mut events = Vec::new();
+        for (channel, fault) in &faults {
+            if let Entry::Vacant(entry) = state.faults.entry((channel, *fault)) {
+                warn!("{} {} {:?}", self.id, channel, fault);
+                entry.insert(timestamp_now());
+                events.push(MotionEventKind::SensorFault {
+                    channel,
+                    fault: *fault,
+                });
+            }
+        }
+        state.faults.retain(|(channel, fault), _| {
+            let active = faults.contains(&(*channel, *fault));
+            if !active {
+                info!("{} {} recovered from {:?}", self.id, channel, fault);
+                events.push(MotionEventKind::SensorRecovered {
+                    channel,
+                    fault: *fault,
+                });
+            }
+            active
+        });
+
+        if state.faults.is_empty() {
+            state.unhealthy_since = None;
+            self.reopen.lock().unwrap().backoff = REOPEN_AFTER;
+        } else {
+            state.unhealthy_since.get_or_insert(now);
+        }
+        events
+    }
+
+    /// Auto reopen is on and the device has been unhealthy long enough since the last try
+    pub fn reopen_due(&self) -> bool {
+        if !self.settings.read().unwrap().auto_reopen {
+            return false;
+        }
+        let Some(unhealthy_since) = self.state.lock().unwrap().unhealthy_since else {
+            return false;
+        };
+        let reopen = self.reopen.lock().unwrap();
+        unhealthy_since.elapsed() >= REOPEN_AFTER
+            && reopen
+                .last
+                .is_none_or(|last| last.elapsed() >= reopen.backoff)
+    }
+
+    /// Count a reopen, the next one waits twice as long while still unhealthy
+    pub fn reopened(&self) {
+        let mut reopen = self.reopen.lock().unwrap();
+        reopen.reopens += 1;
+        reopen.last = Some(Instant::now());
+        reopen.backoff = (reopen.backoff * 2).min(REOPEN_BACKOFF_MAX);
+    }
+
+    pub fn health(&self, nominal_sample_rate: f64) -> ImuHealth {
+        let state = self.state.lock().unwrap();
+        let channels = IMU_CHANNELS
+            .into_iter()
+            .zip(&state.channels)
+            .map(|(name, channel)| {
+                let unchanged = state.last_timestamp.saturating_sub(channel.unchanged_since);
+                (
+                    name,
+                    ChannelHealth {
+                        read_errors: channel.read_errors,
+                        saturated: channel.saturated,
+                        unchanged_seconds: unchanged as f64 / 1e6,
+                    },
+                )
+            })
+            .collect();
+
+        ImuHealth {
+            healthy: state.faults.is_empty(),
+            faults: state
+                .faults
+                .iter()
+                .map(|((channel, fault), since)| FaultInfo {
+                    channel,
+                    fault: *fault,
+                    since: *since,
+                })
+                .collect(),
+            sample_rate: state.sample_rate,
+            nominal_sample_rate,
+            samples: state.samples,
+            buffer_errors: state.buffer_errors,
+            reopens: self.reopen.lock().unwrap().reopens,
+            auto_reopen: self.settings.read().unwrap().auto_reopen,
+            channels,
+        }
+    }
+}
@@ -1,8 +1,37 @@
-use crate::devices::imu::{Imu, ImuData};
+use crate::devices::imu::{
+    IMU_CHANNELS, IioBuffer, IioMotionEvent, IioSample, Imu, ImuConfig, ImuConfigInfo, ImuData,
+    MountMatrix, timestamp_now,
+};
 use log::{debug, warn};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+// Channel order of buffered samples
+const BUFFER_CHANNELS: [&str; 10] = [
+    "accel_x",
+    "accel_y",
+    "accel_z",
+    "anglvel_x",
+    "anglvel_y",
+    "anglvel_z",
+    "magn_x",
+    "magn_y",
+    "magn_z",
+    "temp",
+];
+
+const STANDARD_GRAVITY: f64 = 9.80665;
+
+// Settings changed by `set_config`
+struct IioSettings {
+    sample_rate: f64,
+    accel_scale: f64,
+    gyro_scale: f64,
+}
 
 pub struct IioImu {
     name: String,
@@ -18,103 +47,146 @@ pub struct IioImu {
     mag_y_path: Option<PathBuf>,
     mag_z_path: Option<PathBuf>,
     temp_path: Option<PathBuf>,
+    // Sample rate and accel/gyro scales, the scales set the full-scale range
+    settings: RwLock<IioSettings>,
     // Scale factors for converting raw values
-    accel_scale: f32,
-    gyro_scale: f32,
     mag_scale: f32,
     temp_scale: f32,
     // Optional offset for temperature when using raw channel
     temp_offset: f32,
     // Whether temperature path points to an already-processed input value
     temp_is_input: bool,
-    // Sample rate for the IMU device
-    sample_rate: u32,
+    // sampling_frequency or in_accel_sampling_frequency
+    sample_rate_path: Option<PathBuf>,
+    // Mount matrix from device tree
+    mount_matrix: Option<MountMatrix>,
+    // Separate magnetometer device, mag channels point into it
+    mag_device: Option<PathBuf>,
+    // Paired mag frame to this sensor's frame
+    mag_rotation: Option<MountMatrix>,
+    // Hardware buffer, used instead of polling sysfs when it can be enabled
+    buffer: Option<IioBuffer>,
+    buffered: AtomicBool,
+    // Accel rising event used for wake-on-motion
+    motion_event: Option<IioMotionEvent>,
+    // Failed sysfs reads since init, in IMU_CHANNELS order
+    read_errors: [AtomicU64; 10],
 }
 
 impl IioImu {
-    pub fn new(name: &str) -> Option<Self> {
-        debug!("searching for iio device: {}", name);
-
-        // Scan /sys/bus/iio/devices for matching device
-        let iio_devices_path = Path::new("/sys/bus/iio/devices");
-        if !iio_devices_path.exists() {
-            warn!(
-                "iio devices path does not exist: {}",
-                iio_devices_path.display()
-            );
-            return None;
-        }
+    /// Open an IIO device directory (`/sys/bus/iio/devices/iio:deviceN`) with its device node (`/dev/iio:deviceN`)
+    pub fn new(device_path: &Path, dev_path: &Path) -> io::Result<Self> {
+        let name = fs::read_to_string(device_path.join("name"))?
+            .trim()
+            .to_string();
+        debug!("open iio device {} at: {}", name, device_path.display());
+
+        let mut imu = IioImu {
+            name: name.clone(),
+            device_path: device_path.to_path_buf(),
+            accel_x_path: None,
+            accel_y_path: None,
+            accel_z_path: None,
+            gyro_x_path: None,
+            gyro_y_path: None,
+            gyro_z_path: None,
+            mag_x_path: None,
+            mag_y_path: None,
+            mag_z_path: None,
+            temp_path: None,
+            settings: RwLock::new(IioSettings {
+                sample_rate: 30.0,
+                accel_scale: 1.0,
+                gyro_scale: 1.0,
+            }),
+            mag_scale: 1.0,
+            temp_scale: 1.0,
+            temp_offset: 0.0,
+            temp_is_input: false,
+            sample_rate_path: None,
+            mount_matrix: None,
+            mag_device: None,
+            mag_rotation: None,
+            buffer: None,
+            buffered: AtomicBool::new(false),
+            motion_event: None,
+            read_errors: Default::default(),
+        };
+
+        // Scan for available channels and scales
+        imu.scan_channels()?;
 
-        let entries = match fs::read_dir(iio_devices_path) {
-            Ok(entries) => entries,
+        imu.buffer = match IioBuffer::new(device_path, dev_path, &BUFFER_CHANNELS) {
+            Ok(buffer) => Some(buffer),
             Err(e) => {
-                warn!("failed to read IIO devices directory: {}", e);
-                return None;
+                debug!("iio buffer of {} unavailable: {}, use polling", name, e);
+                None
             }
         };
 
-        for entry in entries {
-            let entry = match entry {
-                Ok(entry) => entry,
-                Err(_) => continue,
-            };
+        imu.motion_event = match IioMotionEvent::new(device_path, dev_path) {
+            Ok(motion_event) => Some(motion_event),
+            Err(e) => {
+                debug!("wake-on-motion of {} unavailable: {}", name, e);
+                None
+            }
+        };
 
-            let device_path = entry.path();
+        Ok(imu)
+    }
 
-            // Check if this is an IIO device directory (iio:deviceN)
-            let dir_name = device_path.file_name()?.to_str()?;
-            if !dir_name.starts_with("iio:device") {
-                continue;
-            }
+    pub fn device_path(&self) -> &Path {
+        &self.device_path
+    }
 
-            // Read device name
-            let name_path = device_path.join("name");
-            let device_name = match fs::read_to_string(&name_path) {
-                Ok(content) => content.trim().to_string(),
-                Err(_) => continue,
-            };
+    /// Whether accel or gyro channels were found, magnetometer-only devices have neither
+    pub fn is_imu(&self) -> bool {
+        self.accel_x_path.is_some() || self.gyro_x_path.is_some()
+    }
 
-            // Check if this matches our target device name
-            if device_name != name {
-                continue;
-            }
+    pub fn has_mag(&self) -> bool {
+        self.mag_x_path.is_some()
+    }
 
-            debug!("found matching iio device at: {}", device_path.display());
-
-            // Initialize the ImuFromIio instance
-            let mut imu = IioImu {
-                name: name.to_string(),
-                device_path: device_path.clone(),
-                accel_x_path: None,
-                accel_y_path: None,
-                accel_z_path: None,
-                gyro_x_path: None,
-                gyro_y_path: None,
-                gyro_z_path: None,
-                mag_x_path: None,
-                mag_y_path: None,
-                mag_z_path: None,
-                temp_path: None,
-                accel_scale: 1.0,
-                gyro_scale: 1.0,
-                mag_scale: 1.0,
-                temp_scale: 1.0,
-                temp_offset: 0.0,
-                temp_is_input: false,
-                sample_rate: 30,
-            };
-
-            // Scan for available channels and scales
-            if let Err(e) = imu.scan_channels() {
-                warn!("failed to scan iio channels for {}: {}", name, e);
-                return None;
+    /// Read mag from a separate magnetometer device, e.g. the AK8963 inside an MPU9250
+    pub fn pair_mag(&mut self, mag: IioImu) {
+        // Mag is rotated into this sensor's frame, the mount matrix is applied to all later
+        self.mag_rotation = match (self.mount_matrix, mag.mount_matrix) {
+            (_, None) => None,
+            (imu_matrix, Some(mag_matrix)) => {
+                let imu_matrix = imu_matrix.unwrap_or(MountMatrix::IDENTITY).0;
+                Some(MountMatrix(std::array::from_fn(|i| {
+                    std::array::from_fn(|j| {
+                        (0..3).map(|k| imu_matrix[k][i] * mag_matrix.0[k][j]).sum()
+                    })
+                })))
             }
+        };
+        self.mag_x_path = mag.mag_x_path;
+        self.mag_y_path = mag.mag_y_path;
+        self.mag_z_path = mag.mag_z_path;
+        self.mag_scale = mag.mag_scale;
+        self.mag_device = Some(mag.device_path);
+    }
 
-            return Some(imu);
-        }
+    fn scales(&self) -> (f32, f32) {
+        let settings = self.settings.read().unwrap();
+        (settings.accel_scale as f32, settings.gyro_scale as f32)
+    }
 
-        warn!("iio device '{}' not found", name);
-        None
+    /// Mag from sysfs, rotated into this sensor's frame when it comes from a paired device
+    fn read_mag(&self) -> [f32; 3] {
+        let mag = [
+            self.read_raw_value(6, &self.mag_x_path) * self.mag_scale,
+            self.read_raw_value(7, &self.mag_y_path) * self.mag_scale,
+            self.read_raw_value(8, &self.mag_z_path) * self.mag_scale,
+        ];
+        match &self.mag_rotation {
+            Some(rotation) => {
+                std::array::from_fn(|i| (0..3).map(|j| rotation.0[i][j] * mag[j]).sum())
+            }
+            None => mag,
+        }
     }
 
     fn scan_channels(&mut self) -> io::Result<()> {
@@ -130,7 +202,8 @@ impl IioImu {
                 // Accelerometer channels
                 "in_accel_x_raw" => {
                     self.accel_x_path = Some(entry.path());
-                    self.accel_scale = self.read_scale("in_accel_scale").unwrap_or(1.0);
+                    self.settings.get_mut().unwrap().accel_scale =
+                        Self::read_f64(&self.device_path.join("in_accel_scale")).unwrap_or(1.0);
                 }
                 "in_accel_y_raw" => {
                     self.accel_y_path = Some(entry.path());
@@ -142,7 +215,8 @@ impl IioImu {
                 // Gyroscope channels
                 "in_anglvel_x_raw" => {
                     self.gyro_x_path = Some(entry.path());
-                    self.gyro_scale = self.read_scale("in_anglvel_scale").unwrap_or(1.0);
+                    self.settings.get_mut().unwrap().gyro_scale =
+                        Self::read_f64(&self.device_path.join("in_anglvel_scale")).unwrap_or(1.0);
                 }
                 "in_anglvel_y_raw" => {
                     self.gyro_y_path = Some(entry.path());
@@ -181,15 +255,28 @@ impl IioImu {
                     self.temp_offset = self.read_offset("in_temp_offset").unwrap_or(0.0);
                 }
 
-                // Sample rate files
-                "sampling_frequency" => {
-                    if let Some(rate) = self.read_sample_rate(&entry.path()) {
-                        self.sample_rate = rate;
+                // Sample rate files, device-wide one wins
+                "sampling_frequency" | "in_accel_sampling_frequency" => {
+                    if self.sample_rate_path.is_some() && file_name_str != "sampling_frequency" {
+                        continue;
+                    }
+                    if let Some(rate) = Self::read_f64(&entry.path()).filter(|rate| *rate > 0.0) {
+                        self.settings.get_mut().unwrap().sample_rate = rate;
+                        self.sample_rate_path = Some(entry.path());
                     }
                 }
-                "in_accel_sampling_frequency" => {
-                    if let Some(rate) = self.read_sample_rate(&entry.path()) {
-                        self.sample_rate = rate;
+
+                // Mount matrix, drivers report identity when device tree has none
+                "mount_matrix" | "in_mount_matrix" | "in_accel_mount_matrix" => {
+                    match fs::read_to_string(entry.path())
+                        .map_err(|e| e.to_string())
+                        .and_then(|content| content.trim().parse::<MountMatrix>())
+                    {
+                        Ok(mount_matrix) if mount_matrix != MountMatrix::IDENTITY => {
+                            self.mount_matrix = Some(mount_matrix);
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("read mount matrix of {} failed: {}", self.name, e),
                     }
                 }
 
@@ -211,21 +298,151 @@ impl IioImu {
         fs::read_to_string(offset_path).ok()?.trim().parse().ok()
     }
 
-    fn read_sample_rate(&self, path: &PathBuf) -> Option<u32> {
+    /// Rates like "12.500000" are fractional
+    fn read_f64(path: &Path) -> Option<f64> {
         fs::read_to_string(path).ok()?.trim().parse().ok()
     }
 
-    fn read_raw_value(&self, path: &Option<PathBuf>) -> f32 {
+    /// Values listed in `<attribute>_available`, empty without a list or for a "[min step max]" range
+    fn read_available(path: &Path) -> Vec<f64> {
+        let mut available_path = path.as_os_str().to_owned();
+        available_path.push("_available");
+        fs::read_to_string(available_path)
+            .ok()
+            .and_then(|content| {
+                content
+                    .split_whitespace()
+                    .map(|value| value.parse().ok())
+                    .collect::<Option<Vec<f64>>>()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Attribute path and the listed value matching `value`, or `value` when the driver lists none
+    fn check_available<'a>(
+        &self,
+        path: Option<&'a Path>,
+        value: f64,
+        what: &str,
+    ) -> io::Result<(&'a Path, f64)> {
+        let invalid_input = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        let Some(path) = path.filter(|path| path.exists()) else {
+            return Err(invalid_input(format!(
+                "{} has no configurable {}",
+                self.name, what
+            )));
+        };
+        if !(value > 0.0 && value.is_finite()) {
+            return Err(invalid_input(format!(
+                "invalid {} {}, must be above 0",
+                what, value
+            )));
+        }
+
+        let available = Self::read_available(path);
+        if available.is_empty() {
+            return Ok((path, value));
+        }
+        available
+            .iter()
+            .copied()
+            .find(|available| (available - value).abs() <= available.abs() * 1e-6)
+            .map(|value| (path, value))
+            .ok_or_else(|| {
+                let available: Vec<String> = available.iter().map(|v| v.to_string()).collect();
+                invalid_input(format!(
+                    "invalid {} {} for {}, available: {}",
+                    what,
+                    value,
+                    self.name,
+                    available.join(" ")
+                ))
+            })
+    }
+
+    /// Write an attribute and read back what the driver actually set
+    fn write_attribute(path: &Path, value: f64) -> io::Result<f64> {
+        fs::write(path, value.to_string()).map_err(|e| {
+            io::Error::new(e.kind(), format!("write {} failed: {}", path.display(), e))
+        })?;
+        Ok(Self::read_f64(path).unwrap_or(value))
+    }
+
+    /// Raw value of a channel in IMU_CHANNELS order, 0 and counted as a read error when it fails
+    fn read_raw_value(&self, channel: usize, path: &Option<PathBuf>) -> f32 {
         match path {
             Some(p) => fs::read_to_string(p)
                 .ok()
                 .and_then(|s| s.trim().parse::<i32>().ok())
-                .unwrap_or(0) as f32,
+                .unwrap_or_else(|| {
+                    self.read_errors[channel].fetch_add(1, Ordering::Relaxed);
+                    0
+                }) as f32,
             None => 0.0,
         }
     }
 
-    fn read_value_as_f32(&self, path: &Option<PathBuf>) -> f32 {
+    fn read_temp(&self) -> f32 {
+        if self.temp_is_input {
+            // Already processed by driver; use as-is
+            self.read_value_as_f32(9, &self.temp_path)
+        } else {
+            // Apply offset and scale for raw channel
+            (self.read_raw_value(9, &self.temp_path) + self.temp_offset) * self.temp_scale
+        }
+    }
+
+    fn buffered_imu_data(
+        &self,
+        sample: &IioSample,
+        temp: &mut Option<f32>,
+        paired_mag: &mut Option<[f32; 3]>,
+    ) -> ImuData {
+        let value = |i: usize, scale: f32| sample.values[i].unwrap_or(0) as f32 * scale;
+        let (accel_scale, gyro_scale) = self.scales();
+
+        // Temperature and paired mag are read from sysfs once per batch when not buffered
+        let temp = match sample.values[9] {
+            Some(raw) if !self.temp_is_input => (raw as f32 + self.temp_offset) * self.temp_scale,
+            _ => *temp.get_or_insert_with(|| self.read_temp()),
+        };
+        let mag = match self.mag_device {
+            Some(_) => *paired_mag.get_or_insert_with(|| self.read_mag()),
+            None => [
+                value(6, self.mag_scale),
+                value(7, self.mag_scale),
+                value(8, self.mag_scale),
+            ],
+        };
+
+        ImuData {
+            // Timestamp clock is set to realtime on init
+            timestamp: sample
+                .timestamp
+                .map(|ns| (ns / 1000) as u64)
+                .unwrap_or_else(timestamp_now),
+            accel: [
+                value(0, accel_scale),
+                value(1, accel_scale),
+                value(2, accel_scale),
+            ],
+            gyro: [
+                value(3, gyro_scale),
+                value(4, gyro_scale),
+                value(5, gyro_scale),
+            ],
+            mag,
+            temp,
+            quaternion: [0.0, 0.0, 0.0, 0.0],
+            euler_angles: [0.0, 0.0, 0.0],
+        }
+    }
+
+    fn read_value_as_f32(&self, channel: usize, path: &Option<PathBuf>) -> f32 {
+        let failed = || {
+            self.read_errors[channel].fetch_add(1, Ordering::Relaxed);
+            0.0
+        };
         match path {
             Some(p) => match fs::read_to_string(p) {
                 Ok(content) => {
@@ -237,10 +454,10 @@ impl IioImu {
                     } else if let Ok(v) = trimmed.parse::<i32>() {
                         v as f32
                     } else {
-                        0.0
+                        failed()
                     }
                 }
-                Err(_) => 0.0,
+                Err(_) => failed(),
             },
             None => 0.0,
         }
@@ -254,27 +471,21 @@ impl Imu for IioImu {
 
     fn imu_data(&self) -> ImuData {
         // Read raw values and apply scaling
-        let accel_x = self.read_raw_value(&self.accel_x_path) * self.accel_scale;
-        let accel_y = self.read_raw_value(&self.accel_y_path) * self.accel_scale;
-        let accel_z = self.read_raw_value(&self.accel_z_path) * self.accel_scale;
+        let (accel_scale, gyro_scale) = self.scales();
+        let accel_x = self.read_raw_value(0, &self.accel_x_path) * accel_scale;
+        let accel_y = self.read_raw_value(1, &self.accel_y_path) * accel_scale;
+        let accel_z = self.read_raw_value(2, &self.accel_z_path) * accel_scale;
 
-        let gyro_x = self.read_raw_value(&self.gyro_x_path) * self.gyro_scale;
-        let gyro_y = self.read_raw_value(&self.gyro_y_path) * self.gyro_scale;
-        let gyro_z = self.read_raw_value(&self.gyro_z_path) * self.gyro_scale;
+        let gyro_x = self.read_raw_value(3, &self.gyro_x_path) * gyro_scale;
+        let gyro_y = self.read_raw_value(4, &self.gyro_y_path) * gyro_scale;
+        let gyro_z = self.read_raw_value(5, &self.gyro_z_path) * gyro_scale;
 
-        let mag_x = self.read_raw_value(&self.mag_x_path) * self.mag_scale;
-        let mag_y = self.read_raw_value(&self.mag_y_path) * self.mag_scale;
-        let mag_z = self.read_raw_value(&self.mag_z_path) * self.mag_scale;
+        let [mag_x, mag_y, mag_z] = self.read_mag();
 
-        let temp = if self.temp_is_input {
-            // Already processed by driver; use as-is
-            self.read_value_as_f32(&self.temp_path)
-        } else {
-            // Apply offset and scale for raw channel
-            (self.read_raw_value(&self.temp_path) + self.temp_offset) * self.temp_scale
-        };
+        let temp = self.read_temp();
 
         ImuData {
+            timestamp: timestamp_now(),
             accel: [accel_x, accel_y, accel_z],
             gyro: [gyro_x, gyro_y, gyro_z],
             mag: [mag_x, mag_y, mag_z],
@@ -286,15 +497,179 @@ impl Imu for IioImu {
 
     fn init(&self) -> io::Result<()> {
         debug!("init iio imu device: {}", self.name);
+        for read_errors in &self.read_errors {
+            read_errors.store(0, Ordering::Relaxed);
+        }
+
+        if let Some(buffer) = &self.buffer {
+            // Hardware timestamps are published as wall clock time
+            let clock_path = self.device_path.join("current_timestamp_clock");
+            if let Err(e) = fs::write(&clock_path, "realtime") {
+                warn!("set {} failed: {}", clock_path.display(), e);
+            }
+
+            match buffer.enable() {
+                Ok(()) => self.buffered.store(true, Ordering::Release),
+                Err(e) => warn!(
+                    "enable iio buffer of {} failed: {}, fall back to polling",
+                    self.name, e
+                ),
+            }
+        }
         Ok(())
     }
 
     fn deinit(&self) -> io::Result<()> {
         debug!("deinit iio imu device: {}", self.name);
+
+        if let Some(buffer) = &self.buffer
+            && self.buffered.swap(false, Ordering::AcqRel)
+        {
+            buffer.disable()?;
+        }
         Ok(())
     }
 
-    fn sample_rate(&self) -> u32 {
-        self.sample_rate
+    fn sample_rate(&self) -> f64 {
+        self.settings.read().unwrap().sample_rate
+    }
+
+    fn read_errors(&self) -> Vec<(&'static str, u64)> {
+        IMU_CHANNELS
+            .into_iter()
+            .zip(&self.read_errors)
+            .map(|(channel, read_errors)| (channel, read_errors.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Raw values are signed, drivers without scan elements are assumed 16 bit
+    fn full_scale(&self) -> Option<[f32; 2]> {
+        let (accel_scale, gyro_scale) = self.scales();
+        let max_raw = |channel: &str| {
+            let bits = IioBuffer::channel_bits(&self.device_path, channel).unwrap_or(16);
+            (1u64 << (bits.clamp(2, 63) - 1)) as f32
+        };
+        Some([
+            accel_scale * max_raw("accel_x"),
+            gyro_scale * max_raw("anglvel_x"),
+        ])
+    }
+
+    fn config(&self) -> ImuConfigInfo {
+        let settings = self.settings.read().unwrap();
+        let accel_scale_path = self.device_path.join("in_accel_scale");
+        let gyro_scale_path = self.device_path.join("in_anglvel_scale");
+        ImuConfigInfo {
+            sample_rate: settings.sample_rate,
+            sample_rate_available: self
+                .sample_rate_path
+                .as_deref()
+                .map(Self::read_available)
+                .unwrap_or_default(),
+            accel_scale: accel_scale_path.exists().then_some(settings.accel_scale),
+            accel_scale_available: Self::read_available(&accel_scale_path),
+            gyro_scale: gyro_scale_path.exists().then_some(settings.gyro_scale),
+            gyro_scale_available: Self::read_available(&gyro_scale_path),
+        }
+    }
+
+    fn set_config(&self, config: &ImuConfig) -> io::Result<()> {
+        let accel_scale_path = self.device_path.join("in_accel_scale");
+        let gyro_scale_path = self.device_path.join("in_anglvel_scale");
+        type Field = fn(&mut IioSettings) -> &mut f64;
+        let fields: [(Option<f64>, Option<&Path>, &str, Field); 3] = [
+            (
+                config.sample_rate,
+                self.sample_rate_path.as_deref(),
+                "sample rate",
+                |settings| &mut settings.sample_rate,
+            ),
+            (
+                config.accel_scale,
+                Some(&accel_scale_path),
+                "accel scale",
+                |settings| &mut settings.accel_scale,
+            ),
+            (
+                config.gyro_scale,
+                Some(&gyro_scale_path),
+                "gyro scale",
+                |settings| &mut settings.gyro_scale,
+            ),
+        ];
+
+        // Check everything before writing anything
+        let mut writes = Vec::new();
+        for (value, path, what, field) in fields {
+            if let Some(value) = value {
+                let (path, value) = self.check_available(path, value, what)?;
+                writes.push((path, value, field));
+            }
+        }
+
+        let mut settings = self.settings.write().unwrap();
+        for (path, value, field) in writes {
+            let actual = Self::write_attribute(path, value)?;
+            *field(&mut settings) = actual;
+            debug!("{} set {} to {}", self.name, path.display(), actual);
+        }
+        Ok(())
+    }
+
+    fn mount_matrix(&self) -> Option<MountMatrix> {
+        self.mount_matrix
+    }
+
+    fn is_buffered(&self) -> bool {
+        self.buffered.load(Ordering::Acquire)
+    }
+
+    fn read_buffer(&self, timeout: Duration) -> io::Result<Vec<ImuData>> {
+        let Some(buffer) = self.buffer.as_ref().filter(|_| self.is_buffered()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "iio buffer is not enabled",
+            ));
+        };
+
+        let (mut temp, mut paired_mag) = (None, None);
+        Ok(buffer
+            .read(timeout)?
+            .iter()
+            .map(|sample| self.buffered_imu_data(sample, &mut temp, &mut paired_mag))
+            .collect())
+    }
+
+    fn has_wake_on_motion(&self) -> bool {
+        self.motion_event.is_some()
+    }
+
+    fn enable_wake_on_motion(&self, threshold: f32) -> io::Result<f32> {
+        let Some(motion_event) = &self.motion_event else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} has no wake-on-motion", self.name),
+            ));
+        };
+        // IIO event values for accel are in m/s²
+        let value = motion_event.enable(threshold as f64 * STANDARD_GRAVITY)?;
+        Ok((value / STANDARD_GRAVITY) as f32)
+    }
+
+    fn disable_wake_on_motion(&self) -> io::Result<()> {
+        match &self.motion_event {
+            Some(motion_event) => motion_event.disable(),
+            None => Ok(()),
+        }
+    }
+
+    fn wait_wake_on_motion(&self, timeout: Duration) -> io::Result<bool> {
+        match &self.motion_event {
+            Some(motion_event) => motion_event.wait(timeout),
+            None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} has no wake-on-motion", self.name),
+            )),
+        }
     }
 }
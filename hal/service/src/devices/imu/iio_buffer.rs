@@ -0,0 +1,521 @@
+use log::debug;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::os::fd::AsFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Samples kept by the kernel between reads
+const BUFFER_LENGTH: usize = 256;
+
+/// IIO buffer
+/// 通过 /dev/iio:deviceN 读取触发器采集的打包数据，每个采样带硬件时间戳
+pub struct IioBuffer {
+    device_path: PathBuf,
+    dev_path: PathBuf,
+    // Enabled channels in scan order, the timestamp channel has no slot
+    channels: Vec<ScanChannel>,
+    slots: usize,
+    scan_size: usize,
+    trigger: Option<String>,
+    file: Mutex<Option<File>>,
+}
+
+/// One decoded scan
+pub struct IioSample {
+    pub values: Vec<Option<i64>>, // raw values in the order of requested channels
+    pub timestamp: Option<i64>,   // nanoseconds in current_timestamp_clock
+}
+
+struct ScanChannel {
+    name: String,
+    slot: Option<usize>,
+    offset: usize,
+    format: ScanFormat,
+}
+
+/// Channel format from scan_elements/in_<channel>_type, e.g. "le:s16/16>>0"
+#[derive(Debug, Clone, Copy)]
+struct ScanFormat {
+    big_endian: bool,
+    signed: bool,
+    bits: u32,
+    storage_bytes: usize,
+    shift: u32,
+}
+
+impl IioBuffer {
+    /// Prepare a buffer for the given channels (e.g. `accel_x`), channels without scan elements are skipped
+    /// `device_path` is the sysfs directory, `dev_path` its device node (`/dev/iio:deviceN`)
+    pub fn new(device_path: &Path, dev_path: &Path, channels: &[&str]) -> io::Result<Self> {
+        let scan_elements_path = device_path.join("scan_elements");
+        if !scan_elements_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no scan_elements, device has no buffer support",
+            ));
+        }
+
+        if !dev_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found", dev_path.display()),
+            ));
+        }
+
+        // Read index and type of every wanted channel, plus timestamp
+        let slots = channels.len();
+        let mut scan_channels = Vec::new();
+        let wanted = channels
+            .iter()
+            .enumerate()
+            .map(|(slot, name)| (Some(slot), *name))
+            .chain([(None, "timestamp")]);
+        for (slot, name) in wanted {
+            let index = fs::read_to_string(scan_elements_path.join(format!("in_{}_index", name)));
+            let format = fs::read_to_string(scan_elements_path.join(format!("in_{}_type", name)));
+            let (Ok(index), Ok(format)) = (index, format) else {
+                continue;
+            };
+            let index: u32 = index.trim().parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid scan index of {}: {}", name, index.trim()),
+                )
+            })?;
+            let format: ScanFormat = format.trim().parse().map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid scan type of {}: {}", name, e),
+                )
+            })?;
+            scan_channels.push((index, name.to_string(), slot, format));
+        }
+        if scan_channels.iter().all(|(_, _, slot, _)| slot.is_none()) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "none of the channels can be buffered",
+            ));
+        }
+
+        // Scans are packed in index order, each channel aligned to its own storage size,
+        // and the whole scan padded to the largest one
+        scan_channels.sort_by_key(|(index, ..)| *index);
+        let mut offset: usize = 0;
+        let mut max_storage_bytes = 1;
+        let channels: Vec<ScanChannel> = scan_channels
+            .into_iter()
+            .map(|(_, name, slot, format)| {
+                offset = offset.next_multiple_of(format.storage_bytes);
+                let channel = ScanChannel {
+                    name,
+                    slot,
+                    offset,
+                    format,
+                };
+                offset += format.storage_bytes;
+                max_storage_bytes = max_storage_bytes.max(format.storage_bytes);
+                channel
+            })
+            .collect();
+        let scan_size = offset.next_multiple_of(max_storage_bytes);
+
+        // Drivers with a hardware fifo have no trigger
+        let current_trigger_path = device_path.join("trigger/current_trigger");
+        let trigger = if current_trigger_path.exists() {
+            let current_trigger = fs::read_to_string(&current_trigger_path)?;
+            let current_trigger = current_trigger.trim();
+            if current_trigger.is_empty() {
+                // Triggers sit next to the device in the same sysfs directory
+                let iio_devices_path = device_path.parent().unwrap_or(Path::new("/"));
+                Some(Self::find_trigger(iio_devices_path, device_path)?)
+            } else {
+                Some(current_trigger.to_string())
+            }
+        } else {
+            None
+        };
+
+        debug!(
+            "iio buffer {}: {} bytes per scan, channels: {:?}, trigger: {:?}",
+            dev_path.display(),
+            scan_size,
+            channels.iter().map(|c| &c.name).collect::<Vec<_>>(),
+            trigger
+        );
+
+        Ok(Self {
+            device_path: device_path.to_path_buf(),
+            dev_path: dev_path.to_path_buf(),
+            channels,
+            slots,
+            scan_size,
+            trigger,
+            file: Mutex::new(None),
+        })
+    }
+
+    /// Value bits of a channel from its scan type, None without scan elements
+    pub fn channel_bits(device_path: &Path, channel: &str) -> Option<u32> {
+        let path = device_path.join(format!("scan_elements/in_{}_type", channel));
+        let format: ScanFormat = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+        Some(format.bits)
+    }
+
+    /// Find the trigger a driver registers for its own device, named like `mpu6500-dev0`
+    fn find_trigger(iio_devices_path: &Path, device_path: &Path) -> io::Result<String> {
+        let name = fs::read_to_string(device_path.join("name"))?;
+        let name = name.trim();
+        let dev_number = device_path
+            .file_name()
+            .and_then(|dir_name| dir_name.to_str())
+            .and_then(|dir_name| dir_name.strip_prefix("iio:device"))
+            .unwrap_or_default();
+
+        let triggers: Vec<String> = fs::read_dir(iio_devices_path)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("trigger"))
+            .filter_map(|entry| fs::read_to_string(entry.path().join("name")).ok())
+            .map(|trigger| trigger.trim().to_string())
+            .collect();
+
+        let own_trigger = format!("{}-dev{}", name, dev_number);
+        triggers
+            .iter()
+            .find(|trigger| **trigger == own_trigger)
+            .or(triggers
+                .iter()
+                .find(|trigger| trigger.starts_with(&format!("{}-", name))))
+            .cloned()
+            .ok_or(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no trigger found for {}", name),
+            ))
+    }
+
+    /// Set trigger, enable scan elements and buffer, then open the device node
+    pub fn enable(&self) -> io::Result<()> {
+        let buffer_path = self.buffer_path();
+
+        // Buffer may be left enabled by a previous run, channels can't change while it is
+        write_attr(&buffer_path.join("enable"), "0")?;
+
+        if let Some(trigger) = &self.trigger {
+            write_attr(&self.device_path.join("trigger/current_trigger"), trigger)?;
+        }
+
+        let scan_elements_path = self.device_path.join("scan_elements");
+        for entry in fs::read_dir(&scan_elements_path)? {
+            let file_name = entry?.file_name().to_string_lossy().to_string();
+            let Some(name) = file_name
+                .strip_prefix("in_")
+                .and_then(|name| name.strip_suffix("_en"))
+            else {
+                continue;
+            };
+            let enabled = self.channels.iter().any(|channel| channel.name == name);
+            write_attr(
+                &scan_elements_path.join(&file_name),
+                if enabled { "1" } else { "0" },
+            )?;
+        }
+
+        write_attr(&buffer_path.join("length"), &BUFFER_LENGTH.to_string())?;
+        write_attr(&buffer_path.join("enable"), "1")?;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(nix::libc::O_NONBLOCK)
+            .open(&self.dev_path)?;
+        *self.file.lock().unwrap() = Some(file);
+
+        debug!("iio buffer {} enabled", self.dev_path.display());
+        Ok(())
+    }
+
+    /// Close the device node and disable buffer
+    pub fn disable(&self) -> io::Result<()> {
+        self.file.lock().unwrap().take();
+        write_attr(&self.buffer_path().join("enable"), "0")?;
+
+        debug!("iio buffer {} disabled", self.dev_path.display());
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for samples, returns all complete scans available
+    pub fn read(&self, timeout: Duration) -> io::Result<Vec<IioSample>> {
+        let mut file = self.file.lock().unwrap();
+        let Some(file) = file.as_mut() else {
+            return Err(io::Error::other("iio buffer is not enabled"));
+        };
+
+        let timeout = PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX);
+        let ready = poll(&mut [PollFd::new(file.as_fd(), PollFlags::POLLIN)], timeout)?;
+        if ready == 0 {
+            return Ok(vec![]);
+        }
+
+        let mut data = vec![0u8; self.scan_size * BUFFER_LENGTH];
+        let len = match file.read(&mut data) {
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        Ok(data[..len]
+            .chunks_exact(self.scan_size)
+            .map(|scan| self.decode(scan))
+            .collect())
+    }
+
+    fn decode(&self, scan: &[u8]) -> IioSample {
+        let mut sample = IioSample {
+            values: vec![None; self.slots],
+            timestamp: None,
+        };
+        for channel in &self.channels {
+            let value = channel
+                .format
+                .decode(&scan[channel.offset..channel.offset + channel.format.storage_bytes]);
+            match channel.slot {
+                Some(slot) => sample.values[slot] = Some(value),
+                None => sample.timestamp = Some(value),
+            }
+        }
+        sample
+    }
+
+    fn buffer_path(&self) -> PathBuf {
+        // Newer kernels also expose buffer0, buffer is kept as an alias of it
+        let buffer_path = self.device_path.join("buffer");
+        if buffer_path.exists() {
+            buffer_path
+        } else {
+            self.device_path.join("buffer0")
+        }
+    }
+}
+
+impl ScanFormat {
+    fn decode(&self, bytes: &[u8]) -> i64 {
+        let raw = bytes.iter().enumerate().fold(0u64, |raw, (i, byte)| {
+            let shift = if self.big_endian {
+                (bytes.len() - 1 - i) * 8
+            } else {
+                i * 8
+            };
+            raw | (*byte as u64) << shift
+        });
+
+        let raw = raw >> self.shift;
+        if self.bits >= 64 {
+            return raw as i64;
+        }
+        let raw = raw & ((1u64 << self.bits) - 1);
+        if self.signed {
+            // Sign extend
+            let unused_bits = 64 - self.bits;
+            ((raw << unused_bits) as i64) >> unused_bits
+        } else {
+            raw as i64
+        }
+    }
+}
+
+impl FromStr for ScanFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // [be|le]:[s|u]bits/storagebits[Xrepeat]>>shift
+        let invalid = || format!("'{}', expected like le:s16/16>>0", s);
+        let (endian, rest) = s.split_once(':').ok_or_else(invalid)?;
+        let (sign, rest) = rest.split_at_checked(1).ok_or_else(invalid)?;
+        let (bits, rest) = rest.split_once('/').ok_or_else(invalid)?;
+        let (storage_bits, shift) = rest.split_once(">>").ok_or_else(invalid)?;
+        if storage_bits.contains('X') {
+            return Err(format!("'{}', repeated channels are not supported", s));
+        }
+
+        let bits: u32 = bits.parse().map_err(|_| invalid())?;
+        let storage_bits: usize = storage_bits.parse().map_err(|_| invalid())?;
+        let shift: u32 = shift.parse().map_err(|_| invalid())?;
+        if !matches!(storage_bits, 8 | 16 | 32 | 64) || bits == 0 || bits > 64 {
+            return Err(invalid());
+        }
+
+        Ok(ScanFormat {
+            big_endian: match endian {
+                "be" => true,
+                "le" => false,
+                _ => return Err(invalid()),
+            },
+            signed: match sign {
+                "s" => true,
+                "u" => false,
+                _ => return Err(invalid()),
+            },
+            bits,
+            storage_bytes: storage_bits / 8,
+            shift,
+        })
+    }
+}
+
+fn write_attr(path: &Path, value: &str) -> io::Result<()> {
+    fs::write(path, value)
+        .map_err(|e| io::Error::new(e.kind(), format!("write {} failed: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(s: &str) -> ScanFormat {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_scan_format() {
+        let le = format("le:s16/16>>0");
+        assert!(!le.big_endian);
+        assert!(le.signed);
+        assert_eq!((le.bits, le.storage_bytes, le.shift), (16, 2, 0));
+
+        let be = format("be:u12/16>>4");
+        assert!(be.big_endian);
+        assert!(!be.signed);
+        assert_eq!((be.bits, be.storage_bytes, be.shift), (12, 2, 4));
+
+        for invalid in [
+            "le:s16/16",
+            "xx:s16/16>>0",
+            "le:x16/16>>0",
+            "le:s16/24>>0",
+            "le:s0/16>>0",
+            "le:s16/16X2>>0",
+        ] {
+            assert!(invalid.parse::<ScanFormat>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn decode_sign_shift_and_endianness() {
+        assert_eq!(format("le:s16/16>>0").decode(&[0xfe, 0xff]), -2);
+        assert_eq!(format("le:u16/16>>0").decode(&[0xfe, 0xff]), 0xfffe);
+        assert_eq!(format("be:s16/16>>0").decode(&[0xff, 0xfe]), -2);
+
+        // 12 bit -3 stored in the high bits
+        assert_eq!(format("be:s12/16>>4").decode(&[0xff, 0xd0]), -3);
+        assert_eq!(format("le:s12/16>>4").decode(&[0xd0, 0xff]), -3);
+        assert_eq!(format("le:u12/16>>4").decode(&[0xd0, 0xff]), 0xffd);
+
+        // Bits above the value are ignored
+        assert_eq!(format("le:s12/16>>0").decode(&[0x05, 0xf0]), 5);
+
+        let timestamp = -5i64;
+        assert_eq!(
+            format("le:s64/64>>0").decode(&timestamp.to_le_bytes()),
+            timestamp
+        );
+        assert_eq!(format("be:u32/32>>0").decode(&[0, 1, 0, 2]), 0x10002);
+    }
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    // iio:device0 with accel_x (s16), accel_y (s16), accel_z (s32) and timestamp (s64),
+    // gyro_x has no scan element
+    fn fake_device(root: &Path) -> (PathBuf, PathBuf) {
+        let device_path = root.join("iio:device0");
+        write(&device_path.join("name"), "mpu6500\n");
+        for (name, index, format) in [
+            ("accel_x", 0, "le:s16/16>>0"),
+            ("accel_y", 1, "le:s16/16>>0"),
+            ("accel_z", 2, "be:s32/32>>0"),
+            ("timestamp", 3, "le:s64/64>>0"),
+        ] {
+            let scan_elements_path = device_path.join("scan_elements");
+            write(
+                &scan_elements_path.join(format!("in_{}_index", name)),
+                &format!("{}\n", index),
+            );
+            write(
+                &scan_elements_path.join(format!("in_{}_type", name)),
+                &format!("{}\n", format),
+            );
+            write(&scan_elements_path.join(format!("in_{}_en", name)), "0\n");
+        }
+        write(&device_path.join("trigger/current_trigger"), "\n");
+        write(&root.join("trigger0/name"), "other-dev1\n");
+        write(&root.join("trigger1/name"), "mpu6500-dev0\n");
+
+        let dev_path = root.join("dev/iio:device0");
+        write(&dev_path, "");
+        (device_path, dev_path)
+    }
+
+    #[test]
+    fn packed_scan_layout() {
+        let root = tempfile::tempdir().unwrap();
+        let (device_path, dev_path) = fake_device(root.path());
+
+        let buffer =
+            IioBuffer::new(&device_path, &dev_path, &["accel_z", "gyro_x", "accel_x"]).unwrap();
+
+        // accel_x at 0, accel_z aligned to 4, timestamp aligned to 8, accel_y not enabled
+        let layout: Vec<_> = buffer
+            .channels
+            .iter()
+            .map(|channel| (channel.name.as_str(), channel.slot, channel.offset))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                ("accel_x", Some(2), 0),
+                ("accel_z", Some(0), 4),
+                ("timestamp", None, 8)
+            ]
+        );
+        assert_eq!(buffer.scan_size, 16);
+        assert_eq!(buffer.trigger.as_deref(), Some("mpu6500-dev0"));
+
+        let mut scan = vec![0u8; 16];
+        scan[0..2].copy_from_slice(&(-100i16).to_le_bytes());
+        scan[4..8].copy_from_slice(&70000i32.to_be_bytes());
+        scan[8..16].copy_from_slice(&123456789i64.to_le_bytes());
+        let sample = buffer.decode(&scan);
+        assert_eq!(sample.values, [Some(70000), None, Some(-100)]);
+        assert_eq!(sample.timestamp, Some(123456789));
+    }
+
+    #[test]
+    fn unsupported_devices() {
+        let root = tempfile::tempdir().unwrap();
+        let (device_path, dev_path) = fake_device(root.path());
+
+        // Nothing wanted can be buffered
+        let e = IioBuffer::new(&device_path, &dev_path, &["gyro_x"])
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+
+        // No device node
+        let e = IioBuffer::new(&device_path, &root.path().join("missing"), &["accel_x"])
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+
+        // No scan elements
+        fs::remove_dir_all(device_path.join("scan_elements")).unwrap();
+        let e = IioBuffer::new(&device_path, &dev_path, &["accel_x"])
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+    }
+}
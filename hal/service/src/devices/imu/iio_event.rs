@@ -0,0 +1,139 @@
+use log::debug;
+use nix::fcntl::{FcntlArg, OFlag, fcntl};
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::fd::{AsFd, AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// IIO_GET_EVENT_FD_IOCTL from linux/iio/events.h
+nix::ioctl_read!(iio_get_event_fd, b'i', 0x90, nix::libc::c_int);
+
+// struct iio_event_data, u64 id and s64 timestamp
+const EVENT_SIZE: usize = 16;
+
+// Rate of change is what wake-on-motion drivers like inv_mpu6050 expose, then magnitude and threshold
+const MOTION_EVENT_TYPES: [&str; 3] = ["roc", "mag", "thresh"];
+
+/// IIO motion event
+/// 通过 events/ 下的加速度上升沿事件（比如 MPU6500 的 wake-on-motion）在低功耗下等运动，事件从 /dev/iio:deviceN 的 event fd 读取
+pub struct IioMotionEvent {
+    dev_path: PathBuf,
+    enable_path: PathBuf,
+    value_path: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl IioMotionEvent {
+    /// Find an accel rising event like `events/in_accel_x|y|z_roc_rising_en`
+    /// `dev_path` is the device node events are read from (`/dev/iio:deviceN`)
+    pub fn new(device_path: &Path, dev_path: &Path) -> io::Result<Self> {
+        let unsupported = || {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no accel rising event, device has no wake-on-motion",
+            )
+        };
+        let events_path = device_path.join("events");
+        let names: Vec<String> = fs::read_dir(&events_path)
+            .map_err(|_| unsupported())?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+
+        let (enable, value) = MOTION_EVENT_TYPES
+            .iter()
+            .flat_map(|kind| {
+                let suffix = format!("_{}_rising_en", kind);
+                names
+                    .iter()
+                    .filter(move |name| name.starts_with("in_accel") && name.ends_with(&suffix))
+            })
+            .find_map(|enable| {
+                let value = format!("{}_value", enable.strip_suffix("_en")?);
+                names.contains(&value).then(|| (enable.clone(), value))
+            })
+            .ok_or_else(unsupported)?;
+
+        if !dev_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found", dev_path.display()),
+            ));
+        }
+
+        debug!("iio motion event of {}: {}", dev_path.display(), enable);
+        Ok(Self {
+            dev_path: dev_path.to_path_buf(),
+            enable_path: events_path.join(enable),
+            value_path: events_path.join(value),
+            file: Mutex::new(None),
+        })
+    }
+
+    /// Arm the event at `value` in the driver's unit, returns the value the driver set
+    pub fn enable(&self, value: f64) -> io::Result<f64> {
+        write_attr(&self.value_path, &value.to_string())?;
+        let value = fs::read_to_string(&self.value_path)
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(value);
+
+        let dev = File::open(&self.dev_path)?;
+        let mut fd: nix::libc::c_int = -1;
+        unsafe { iio_get_event_fd(dev.as_raw_fd(), &mut fd) }?;
+        let file = unsafe { File::from_raw_fd(fd) };
+        fcntl(&file, FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+
+        write_attr(&self.enable_path, "1")?;
+        *self.file.lock().unwrap() = Some(file);
+
+        debug!(
+            "iio motion event {} enabled at {}",
+            self.dev_path.display(),
+            value
+        );
+        Ok(value)
+    }
+
+    pub fn disable(&self) -> io::Result<()> {
+        self.file.lock().unwrap().take();
+        write_attr(&self.enable_path, "0")?;
+
+        debug!("iio motion event {} disabled", self.dev_path.display());
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for motion, pending events are drained
+    pub fn wait(&self, timeout: Duration) -> io::Result<bool> {
+        let mut file = self.file.lock().unwrap();
+        let Some(file) = file.as_mut() else {
+            return Err(io::Error::other("iio motion event is not enabled"));
+        };
+
+        let timeout = PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX);
+        let ready = poll(&mut [PollFd::new(file.as_fd(), PollFlags::POLLIN)], timeout)?;
+        if ready == 0 {
+            return Ok(false);
+        }
+
+        let mut events = 0;
+        let mut data = [0u8; EVENT_SIZE * 16];
+        loop {
+            match file.read(&mut data) {
+                Ok(0) => break,
+                Ok(len) => events += len / EVENT_SIZE,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(events > 0)
+    }
+}
+
+fn write_attr(path: &Path, value: &str) -> io::Result<()> {
+    fs::write(path, value)
+        .map_err(|e| io::Error::new(e.kind(), format!("write {} failed: {}", path.display(), e)))
+}
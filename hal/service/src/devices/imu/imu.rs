@@ -1,12 +1,23 @@
-use crate::common::Emoji;
-use crate::devices::imu::{IioImu, Imu, MockImu, socket::ImuSocket};
+use crate::common::iio::{IIO_DEV_PATH, IIO_DEVICES_PATH};
+use crate::common::{Emoji, query_param, read_body, storage};
+use crate::devices::imu::{
+    Calibration, CalibrationKind, FusionAlgorithm, Imu, ImuConfig, ImuTopic, MockImu, MountMatrix,
+    ReplayImu, ReplayOptions, SinkFormat, WakeSettings, discover_iio_imus, recording_path,
+    socket::ImuSocket, timestamp_now,
+};
 use crate::devices::{API_REGISTER, ApiRoute};
-use hyper::{Method, Response, StatusCode, header::CONTENT_TYPE};
+use hyper::{Method, Response, StatusCode, Uri, header::CONTENT_TYPE};
 use log::{error, info, warn};
 use std::io;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::{sync::Notify, task};
 
+// Session keepalive timeout in seconds
+const SESSION_TTL_DEFAULT: u64 = 30;
+const SESSION_TTL_MAX: u64 = 3600;
+
 // 批量克隆
 macro_rules! arc_clones {
     ($arc_var:ident, $($name:ident),*) => {
@@ -21,9 +32,45 @@ async fn register_device(imu_socket: &Arc<ImuSocket>) {
         imu_socket_clone1,
         imu_socket_clone2,
         imu_socket_clone3,
-        imu_socket_clone4
+        imu_socket_clone4,
+        imu_socket_clone5,
+        imu_socket_clone6,
+        imu_socket_clone7,
+        imu_socket_clone8,
+        imu_socket_clone9,
+        imu_socket_clone10,
+        imu_socket_clone11,
+        imu_socket_clone12,
+        imu_socket_clone13,
+        imu_socket_clone14,
+        imu_socket_clone15,
+        imu_socket_clone16,
+        imu_socket_clone17,
+        imu_socket_clone18,
+        imu_socket_clone19,
+        imu_socket_clone20,
+        imu_socket_clone21,
+        imu_socket_clone22,
+        imu_socket_clone23,
+        imu_socket_clone24,
+        imu_socket_clone25,
+        imu_socket_clone26,
+        imu_socket_clone27,
+        imu_socket_clone28,
+        imu_socket_clone29,
+        imu_socket_clone30,
+        imu_socket_clone31,
+        imu_socket_clone32,
+        imu_socket_clone33
     );
 
+    let json_response = |body: String| -> Response<String> {
+        Response::builder()
+            .header(CONTENT_TYPE, "application/json; charset=utf-8")
+            .body(body)
+            .unwrap()
+    };
+
     let success_response = || -> Response<String> {
         Response::builder()
             .header(CONTENT_TYPE, "text/plain; charset=utf-8")
@@ -31,8 +78,14 @@ async fn register_device(imu_socket: &Arc<ImuSocket>) {
             .unwrap()
     };
     let error_response = |e: io::Error| {
+        let status = match e.kind() {
+            io::ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+            io::ErrorKind::ResourceBusy => StatusCode::CONFLICT,
+            io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
         Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .status(status)
             .header(CONTENT_TYPE, "text/plain; charset=utf-8")
             .body(e.to_string())
             .unwrap()
@@ -87,19 +140,84 @@ async fn register_device(imu_socket: &Arc<ImuSocket>) {
         warn!("add api failed: {}", e);
     }
 
-    // Start publishing data
+    // Start a session
     if let Err(e) = API_REGISTER
         .add_api(
             ApiRoute {
                 path: format!("/{}/start", imu_socket.id),
                 method: Method::GET,
-                description: format!("{} Start publishing data.", Emoji::START),
+                description: format!(
+                    "{} Open a session and start publishing data if not yet. Returns a session id to keep alive before its ttl runs out (seconds, default {}, up to {}), data keeps publishing while any session is alive. Use query parameter: /{}/start?client=godot&ttl=30",
+                    Emoji::START,
+                    SESSION_TTL_DEFAULT,
+                    SESSION_TTL_MAX,
+                    imu_socket.id
+                ),
             },
-            Box::new(move |_request| {
+            Box::new(move |request| {
                 let imu_socket = Arc::clone(&imu_socket_clone2);
                 Box::pin(async move {
-                    match imu_socket.start().await {
-                        Ok(_) => success_response(),
+                    let client =
+                        query_param(request.uri(), "client").unwrap_or("unknown".to_string());
+                    let ttl = match query_param(request.uri(), "ttl") {
+                        Some(ttl) => match ttl.parse::<u64>() {
+                            Ok(ttl) if (1..=SESSION_TTL_MAX).contains(&ttl) => ttl,
+                            _ => {
+                                return error_response(io::Error::new(
+                                    io::ErrorKind::InvalidInput,
+                                    format!(
+                                        "invalid ttl value, must be 1 to {} seconds",
+                                        SESSION_TTL_MAX
+                                    ),
+                                ));
+                            }
+                        },
+                        None => SESSION_TTL_DEFAULT,
+                    };
+
+                    match imu_socket
+                        .open_session(&client, Some(Duration::from_secs(ttl)))
+                        .await
+                    {
+                        Ok(session) => json_response(
+                            serde_json::to_string_pretty(&session).unwrap_or("wtf?🤡".to_string()),
+                        ),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Keep a session alive
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/keepalive", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Extend a session by its ttl. Use query parameter: /{}/keepalive?session=<session>",
+                    Emoji::START,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone27);
+                Box::pin(async move {
+                    let Some(session) = query_param(request.uri(), "session") else {
+                        return error_response(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "missing session parameter",
+                        ));
+                    };
+
+                    match imu_socket.keepalive_session(&session) {
+                        Ok(session) => json_response(
+                            serde_json::to_string_pretty(&session).unwrap_or("wtf?🤡".to_string()),
+                        ),
                         Err(e) => error_response(e),
                     }
                 })
@@ -110,18 +228,844 @@ async fn register_device(imu_socket: &Arc<ImuSocket>) {
         warn!("add api failed: {}", e);
     }
 
-    // Stop publishing data
+    // End a session
     if let Err(e) = API_REGISTER
         .add_api(
             ApiRoute {
                 path: format!("/{}/stop", imu_socket.id),
                 method: Method::GET,
-                description: format!("{} Stop publishing data.", Emoji::STOP),
+                description: format!(
+                    "{} Close a session, publishing stops when no session is left. all=true closes every client session, internal ones held by UDP sinks or a running calibration stay. Use query parameter: /{}/stop?session=<session>",
+                    Emoji::STOP,
+                    imu_socket.id
+                ),
             },
-            Box::new(move |_request| {
+            Box::new(move |request| {
                 let imu_socket = Arc::clone(&imu_socket_clone3);
                 Box::pin(async move {
-                    match imu_socket.stop().await {
+                    if query_param(request.uri(), "all").as_deref() == Some("true") {
+                        return match imu_socket.close_all_sessions().await {
+                            Ok(_) => success_response(),
+                            Err(e) => error_response(e),
+                        };
+                    }
+                    let Some(session) = query_param(request.uri(), "session") else {
+                        return error_response(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "missing session parameter, use the one from start, or all=true",
+                        ));
+                    };
+
+                    match imu_socket.close_session(&session).await {
+                        Ok(_) => success_response(),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // List sessions
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/sessions", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} List open sessions with their client and seconds until they expire.",
+                    Emoji::INFO
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone28);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&imu_socket.sessions.list())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+    // Get calibration
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/calibration", imu_socket.id),
+                method: Method::GET,
+                description: format!("{} Get calibration.", Emoji::INFO),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone5);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&imu_socket.calibrator.calibration())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Set calibration
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/calibration", imu_socket.id),
+                method: Method::PUT,
+                description: format!(
+                    "{} Set calibration from a JSON body like GET returns, missing fields are reset. Calibration is persisted.",
+                    Emoji::STORAGE
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone6);
+                Box::pin(async move {
+                    let body = match read_body(request).await {
+                        Ok(body) => body,
+                        Err(e) => return error_response(e),
+                    };
+                    let calibration: Calibration = match serde_json::from_slice(&body) {
+                        Ok(calibration) => calibration,
+                        Err(e) => {
+                            return error_response(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("invalid calibration: {}", e),
+                            ));
+                        }
+                    };
+
+                    match imu_socket.calibrator.set_calibration(calibration) {
+                        Ok(_) => success_response(),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Calibrate gyro bias and accel offsets
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/calibrate/still", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Capture gyro bias and accel offsets while the device lies still and level, returns the new calibration. Use query parameter: /{}/calibrate/still?seconds=5",
+                    Emoji::SEARCH,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone7);
+                Box::pin(async move {
+                    let duration = match capture_duration(request.uri(), 5) {
+                        Ok(duration) => duration,
+                        Err(e) => return error_response(e),
+                    };
+                    match calibrate(imu_socket, CalibrationKind::Still, duration).await {
+                        Ok(calibration) => json_response(calibration),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Calibrate magnetometer
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/calibrate/mag", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Capture mag hard and soft iron while the device is rotated slowly in all directions, returns the new calibration. Use query parameter: /{}/calibrate/mag?seconds=30",
+                    Emoji::SEARCH,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone8);
+                Box::pin(async move {
+                    let duration = match capture_duration(request.uri(), 30) {
+                        Ok(duration) => duration,
+                        Err(e) => return error_response(e),
+                    };
+                    match calibrate(imu_socket, CalibrationKind::Mag, duration).await {
+                        Ok(calibration) => json_response(calibration),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+    // Get fusion settings
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/fusion", imu_socket.id),
+                method: Method::GET,
+                description: format!("{} Get sensor fusion settings.", Emoji::INFO),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone9);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&imu_socket.fusion.settings())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Set fusion settings
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/fusion/set", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Set sensor fusion, only given parameters change, settings are persisted and orientation starts over. Use query parameter: /{}/fusion/set?algorithm=mahony&mag=true&kp=0.5&ki=0.0, algorithm can be madgwick (gain beta), mahony (gains kp, ki) or complementary (gyro weight alpha)",
+                    Emoji::STORAGE,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone10);
+                Box::pin(async move {
+                    let mut settings = imu_socket.fusion.settings();
+                    let invalid_input =
+                        |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+                    if let Some(algorithm) = query_param(request.uri(), "algorithm") {
+                        settings.algorithm = match algorithm.parse::<FusionAlgorithm>() {
+                            Ok(algorithm) => algorithm,
+                            Err(e) => return error_response(invalid_input(e)),
+                        };
+                    }
+                    if let Some(mag) = query_param(request.uri(), "mag") {
+                        settings.mag = match mag.parse::<bool>() {
+                            Ok(mag) => mag,
+                            Err(_) => {
+                                return error_response(invalid_input(
+                                    "invalid mag value, must be true or false".to_string(),
+                                ));
+                            }
+                        };
+                    }
+                    for (key, value) in [
+                        ("beta", &mut settings.beta),
+                        ("kp", &mut settings.kp),
+                        ("ki", &mut settings.ki),
+                        ("alpha", &mut settings.alpha),
+                    ] {
+                        if let Some(param) = query_param(request.uri(), key) {
+                            match param.parse::<f64>() {
+                                Ok(param) => *value = param,
+                                Err(_) => {
+                                    return error_response(invalid_input(format!(
+                                        "invalid {} value, must be a number",
+                                        key
+                                    )));
+                                }
+                            }
+                        }
+                    }
+
+                    match imu_socket
+                        .fusion
+                        .set_settings(settings, imu_socket.sample_rate())
+                    {
+                        Ok(_) => success_response(),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Tare orientation
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/tare", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Capture the current orientation as reference, published quaternion and euler angles become relative to it. The imu must be running, the stream is not interrupted.",
+                    Emoji::START
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone22);
+                Box::pin(async move {
+                    match imu_socket.tare() {
+                        Ok(_) => success_response(),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Clear tare
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/tare/clear", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Clear the tare reference, orientation is published as fused again.",
+                    Emoji::STOP
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone23);
+                Box::pin(async move {
+                    imu_socket.fusion.clear_tare();
+                    success_response()
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Reset orientation
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/reset", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Reinitialise the fusion filter from identity and clear tare, the stream is not interrupted.",
+                    Emoji::START
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone24);
+                Box::pin(async move {
+                    imu_socket.reset_orientation();
+                    success_response()
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Get health
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/health", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Get sensor health since start: current faults (stuck, read_errors, saturated, low_sample_rate), per-channel read errors, saturated samples and how long values have been unchanged, measured vs nominal sample rate, and reopens. Faults and recoveries are also sent as sensor_fault / sensor_recovered events.",
+                    Emoji::INFO
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone25);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&imu_socket.health())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Set health settings
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/health/set", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Set whether the device is stopped and started again after being unhealthy for 5 seconds, retries back off up to 5 minutes. Persisted. Use query parameter: /{}/health/set?auto_reopen=true",
+                    Emoji::STORAGE,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone26);
+                Box::pin(async move {
+                    let mut settings = imu_socket.health.settings();
+                    if let Some(auto_reopen) = query_param(request.uri(), "auto_reopen") {
+                        settings.auto_reopen = match auto_reopen.parse::<bool>() {
+                            Ok(auto_reopen) => auto_reopen,
+                            Err(_) => {
+                                return error_response(io::Error::new(
+                                    io::ErrorKind::InvalidInput,
+                                    "invalid auto_reopen value, must be true or false",
+                                ));
+                            }
+                        };
+                    }
+
+                    match imu_socket.health.set_settings(settings) {
+                        Ok(_) => success_response(),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Get wake-on-motion
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/wake", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Get wake-on-motion settings, what watches for motion now (off, streaming, hardware interrupt or software polling), whether the driver has a wake-on-motion interrupt, and motions so far.",
+                    Emoji::INFO
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone32);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&imu_socket.wake_info())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Set wake-on-motion
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/wake/set", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Set wake-on-motion: while no session runs the imu it stays in low power, using the driver's motion interrupt or polling sysfs at 10Hz, and sends a motion event when accel changes by more than threshold g, waking idle screens and backlights when wake_screens is true. Only given parameters change, persisted. Use query parameter: /{}/wake/set?enabled=true&threshold=0.1&wake_screens=true",
+                    Emoji::STORAGE,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone33);
+                Box::pin(async move {
+                    let settings = match wake_params(request.uri(), imu_socket.wake.settings()) {
+                        Ok(settings) => settings,
+                        Err(e) => return error_response(e),
+                    };
+                    match imu_socket.set_wake(settings) {
+                        Ok(info) => json_response(
+                            serde_json::to_string_pretty(&info).unwrap_or("wtf?🤡".to_string()),
+                        ),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Get mount matrix
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/mount", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Get mount matrix mapping sensor axes to device axes, and its source: iio, config or default.",
+                    Emoji::INFO
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone11);
+                Box::pin(async move {
+                    let (matrix, source) = imu_socket.mount();
+                    json_response(
+                        serde_json::to_string_pretty(&serde_json::json!({
+                            "matrix": matrix,
+                            "source": source,
+                        }))
+                        .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Set mount matrix
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/mount/set", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Set mount matrix as 3 rows like the iio mount_matrix, device = matrix * sensor. Persisted, refused when the driver reports one. Use query parameter: /{}/mount/set?matrix=0,0,1;0,1,0;1,0,0",
+                    Emoji::STORAGE,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone12);
+                Box::pin(async move {
+                    let Some(matrix) = query_param(request.uri(), "matrix") else {
+                        return error_response(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "missing matrix parameter",
+                        ));
+                    };
+                    let mount_matrix = match matrix.parse::<MountMatrix>() {
+                        Ok(mount_matrix) => mount_matrix,
+                        Err(e) => {
+                            return error_response(io::Error::new(io::ErrorKind::InvalidInput, e));
+                        }
+                    };
+
+                    match imu_socket.set_mount_matrix(mount_matrix) {
+                        Ok(_) => success_response(),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Get sample rate and scales
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/config", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Get sample rate, accel and gyro scale (value per LSB, sets the full-scale range) and the values the driver accepts.",
+                    Emoji::INFO
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone20);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&imu_socket.config())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Set sample rate and scales
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/config/set", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Set sample rate, accel and gyro scale, values must be in the driver's *_available lists. Only given parameters change, persisted, a running imu restarts. Returns the config the driver actually set. Use query parameter: /{}/config/set?sample_rate=200&accel_scale=0.002394",
+                    Emoji::STORAGE,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone21);
+                Box::pin(async move {
+                    let config = match config_params(request.uri()) {
+                        Ok(config) => config,
+                        Err(e) => return error_response(e),
+                    };
+
+                    match imu_socket.set_config(config).await {
+                        Ok(info) => json_response(
+                            serde_json::to_string_pretty(&info).unwrap_or("wtf?🤡".to_string()),
+                        ),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Get motion events
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/events", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Get recent motion events (tap, double_tap, shake, face_up, face_down, pick_up, orientation), sensor_fault / sensor_recovered from health monitoring and motion from wake-on-motion after a seq, optionally waiting up to 30000 ms for one. Use query parameter: /{}/events?since=0&wait=5000",
+                    Emoji::INFO,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone13);
+                Box::pin(async move {
+                    let since = match query_param(request.uri(), "since").map(|since| since.parse::<u64>()) {
+                        Some(Ok(since)) => since,
+                        Some(Err(_)) => {
+                            return error_response(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                "invalid since value, must be an event seq",
+                            ));
+                        }
+                        None => 0,
+                    };
+                    let wait = match query_param(request.uri(), "wait").map(|wait| wait.parse::<u64>()) {
+                        Some(Ok(wait)) if wait <= 30000 => Duration::from_millis(wait),
+                        Some(_) => {
+                            return error_response(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                "invalid wait value, must be 0 to 30000 ms",
+                            ));
+                        }
+                        None => Duration::ZERO,
+                    };
+
+                    let events = imu_socket.events.wait_since(since, wait).await;
+                    json_response(serde_json::to_string_pretty(&events).unwrap_or("wtf?🤡".to_string()))
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // List decimated streams
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/streams", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} List decimated streams published on <imu_topic_port>.",
+                    Emoji::INFO
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone14);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&imu_socket.streams.list())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Add decimated stream
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/streams/add", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Publish raw or orientation at a lower rate on <imu_topic_port>, returns the topic to subscribe to, like \"10hz/orientation\". The stream is held by the session from start and ends when every session holding it is removed, closed or expired. Use query parameter: /{}/streams/add?topic=orientation&rate=10&session=<session>",
+                    Emoji::STREAM,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone15);
+                Box::pin(async move {
+                    let (topic, rate, session) = match stream_params(request.uri()) {
+                        Ok(params) => params,
+                        Err(e) => return error_response(e),
+                    };
+                    match imu_socket.add_stream(topic, rate, &session) {
+                        Ok(name) => json_response(
+                            serde_json::to_string_pretty(&serde_json::json!({ "topic": name }))
+                                .unwrap_or("wtf?🤡".to_string()),
+                        ),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Remove decimated stream
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/streams/remove", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Release a session's hold on a decimated stream, it stops when no session holds it. Use query parameter: /{}/streams/remove?topic=orientation&rate=10&session=<session>",
+                    Emoji::STOP,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone16);
+                Box::pin(async move {
+                    let (topic, rate, session) = match stream_params(request.uri()) {
+                        Ok(params) => params,
+                        Err(e) => return error_response(e),
+                    };
+                    match imu_socket.streams.remove(topic, rate, &session) {
+                        Ok(_) => success_response(),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // List output sinks
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/sinks", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} List UDP output sinks with samples sent and send errors.",
+                    Emoji::INFO
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone29);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&imu_socket.sinks.list())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Add output sink
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/sinks/add", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Send every sample over UDP as OSC messages (/rebecca/{}/quat, /rebecca/{}/accel...) or as compact JSON, for tools without ZMQ or Protobuf. rate in Hz is optional, default every sample. Persisted, the imu keeps publishing while there are sinks. Use query parameter: /{}/sinks/add?format=osc&address=192.168.1.20:9000&rate=60",
+                    Emoji::PUBLISH,
+                    imu_socket.id,
+                    imu_socket.id,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone30);
+                Box::pin(async move {
+                    let (format, address) = match sink_params(request.uri()) {
+                        Ok(params) => params,
+                        Err(e) => return error_response(e),
+                    };
+                    let rate = match query_param(request.uri(), "rate").map(|rate| rate.parse::<u32>()) {
+                        Some(Ok(rate)) => Some(rate),
+                        Some(Err(_)) => {
+                            return error_response(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                "invalid rate value, must be a number in Hz",
+                            ));
+                        }
+                        None => None,
+                    };
+                    match imu_socket.add_sink(format, &address, rate).await {
+                        Ok(info) => json_response(
+                            serde_json::to_string_pretty(&info).unwrap_or("wtf?🤡".to_string()),
+                        ),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Remove output sink
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/sinks/remove", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Stop sending to a UDP sink. Use query parameter: /{}/sinks/remove?format=osc&address=192.168.1.20:9000",
+                    Emoji::STOP,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone31);
+                Box::pin(async move {
+                    let (format, address) = match sink_params(request.uri()) {
+                        Ok(params) => params,
+                        Err(e) => return error_response(e),
+                    };
+                    match imu_socket.remove_sink(format, &address).await {
                         Ok(_) => success_response(),
                         Err(e) => error_response(e),
                     }
@@ -132,15 +1076,206 @@ async fn register_device(imu_socket: &Arc<ImuSocket>) {
     {
         warn!("add api failed: {}", e);
     }
+
+    // Get recording status
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/record", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Get recording status, or the last recording when stopped.",
+                    Emoji::INFO
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone17);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&imu_socket.recorder.status())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Start recording
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/record/start", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Record published samples as length-delimited ImuDataProto to <config-dir>/recordings/, replay with --replay-imu. Default name is {}-<unix time>.pb. Use query parameter: /{}/record/start?name=walk.pb",
+                    Emoji::START,
+                    imu_socket.id,
+                    imu_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let imu_socket = Arc::clone(&imu_socket_clone18);
+                Box::pin(async move {
+                    let name = query_param(request.uri(), "name").unwrap_or(format!(
+                        "{}-{}.pb",
+                        imu_socket.id,
+                        timestamp_now() / 1_000_000
+                    ));
+                    // Recordings only go to the config dir, never anywhere a client names
+                    let path = match storage::storage_dir()
+                        .and_then(|dir| recording_path(&dir.join("recordings"), &name))
+                    {
+                        Ok(path) => path,
+                        Err(e) => return error_response(e),
+                    };
+                    match imu_socket.recorder.start(path) {
+                        Ok(info) => json_response(
+                            serde_json::to_string_pretty(&info).unwrap_or("wtf?🤡".to_string()),
+                        ),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Stop recording
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/record/stop", imu_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Stop recording, returns the file path and sample count.",
+                    Emoji::STOP
+                ),
+            },
+            Box::new(move |_request| {
+                let imu_socket = Arc::clone(&imu_socket_clone19);
+                Box::pin(async move {
+                    match imu_socket.recorder.stop() {
+                        Ok(info) => json_response(
+                            serde_json::to_string_pretty(&info).unwrap_or("wtf?🤡".to_string()),
+                        ),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+}
+
+fn stream_params(uri: &Uri) -> io::Result<(ImuTopic, u32, String)> {
+    let invalid_input = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let topic = query_param(uri, "topic")
+        .ok_or_else(|| invalid_input("missing topic parameter".to_string()))?
+        .parse::<ImuTopic>()
+        .map_err(invalid_input)?;
+    let rate = query_param(uri, "rate")
+        .ok_or_else(|| invalid_input("missing rate parameter".to_string()))?
+        .parse::<u32>()
+        .map_err(|_| invalid_input("invalid rate value, must be a number in Hz".to_string()))?;
+    let session = query_param(uri, "session").ok_or_else(|| {
+        invalid_input("missing session parameter, use the one from start".to_string())
+    })?;
+    Ok((topic, rate, session))
+}
+
+fn config_params(uri: &Uri) -> io::Result<ImuConfig> {
+    let param = |key: &str| -> io::Result<Option<f64>> {
+        query_param(uri, key)
+            .map(|param| {
+                param
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite() && *value > 0.0)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("invalid {} '{}', must be a positive number", key, param),
+                        )
+                    })
+            })
+            .transpose()
+    };
+    Ok(ImuConfig {
+        sample_rate: param("sample_rate")?,
+        accel_scale: param("accel_scale")?,
+        gyro_scale: param("gyro_scale")?,
+    })
+}
+
+fn sink_params(uri: &Uri) -> io::Result<(SinkFormat, String)> {
+    let invalid_input = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let format = query_param(uri, "format")
+        .ok_or_else(|| invalid_input("missing format parameter".to_string()))?
+        .parse::<SinkFormat>()
+        .map_err(invalid_input)?;
+    let address = query_param(uri, "address")
+        .ok_or_else(|| invalid_input("missing address parameter".to_string()))?;
+    Ok((format, address))
+}
+
+fn wake_params(uri: &Uri, mut settings: WakeSettings) -> io::Result<WakeSettings> {
+    let invalid_input = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+    if let Some(enabled) = query_param(uri, "enabled") {
+        settings.enabled = enabled
+            .parse()
+            .map_err(|_| invalid_input("invalid enabled value, must be true or false"))?;
+    }
+    if let Some(threshold) = query_param(uri, "threshold") {
+        settings.threshold = threshold
+            .parse()
+            .map_err(|_| invalid_input("invalid threshold value, must be a number in g"))?;
+    }
+    if let Some(wake_screens) = query_param(uri, "wake_screens") {
+        settings.wake_screens = wake_screens
+            .parse()
+            .map_err(|_| invalid_input("invalid wake_screens value, must be true or false"))?;
+    }
+    Ok(settings)
+}
+
+fn capture_duration(uri: &Uri, default_seconds: u64) -> io::Result<Duration> {
+    match query_param(uri, "seconds").map(|seconds| seconds.parse::<u64>()) {
+        Some(Ok(seconds)) if (1..=300).contains(&seconds) => Ok(Duration::from_secs(seconds)),
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seconds value, must be 1 to 300",
+        )),
+        None => Ok(Duration::from_secs(default_seconds)),
+    }
+}
+
+/// Calibrate in its own task so a dropped request can't leave a capture behind
+async fn calibrate(
+    imu_socket: Arc<ImuSocket>,
+    kind: CalibrationKind,
+    duration: Duration,
+) -> io::Result<String> {
+    let calibration = task::spawn(async move { imu_socket.calibrate(kind, duration).await })
+        .await
+        .map_err(|e| io::Error::other(format!("calibration task failed: {}", e)))??;
+    Ok(serde_json::to_string_pretty(&calibration).unwrap_or("wtf?🤡".to_string()))
 }
 
 fn add_custom_imus(imus: &mut Vec<Box<dyn Imu + Send + Sync + 'static>>) {
-    // Try to create mpu6500 from iio
-    if let Some(mpu6500_iio) = IioImu::new("mpu6500") {
-        imus.push(Box::new(mpu6500_iio));
-    } else {
-        error!("failed to create imu from iio by name: mpu6500");
-        return;
+    // Every IIO device with accel or gyro channels, filtered and ordered by discovery settings
+    let iio_imus = discover_iio_imus(Path::new(IIO_DEVICES_PATH), Path::new(IIO_DEV_PATH));
+    if iio_imus.is_empty() {
+        warn!("no iio imu found");
+    }
+    for iio_imu in iio_imus {
+        imus.push(Box::new(iio_imu));
     }
 }
 
@@ -155,6 +1290,7 @@ pub async fn start_imu_service(
     host: &str,
     shutdown_notify: Arc<Notify>,
     mock_imu: bool,
+    replay_imu: Option<ReplayOptions>,
 ) -> io::Result<task::JoinHandle<()>> {
     let mut imus: Vec<Box<dyn Imu + Send + Sync + 'static>> = Vec::new();
 
@@ -163,6 +1299,13 @@ pub async fn start_imu_service(
         imus.push(Box::new(MockImu::new()));
     }
 
+    if let Some(replay_options) = replay_imu {
+        match ReplayImu::new(&replay_options) {
+            Ok(replay_imu) => imus.push(Box::new(replay_imu)),
+            Err(e) => error!("failed to create replay imu: {}", e),
+        }
+    }
+
     add_custom_imus(&mut imus);
 
     // Create imu sockets
@@ -186,13 +1329,21 @@ pub async fn start_imu_service(
 
             let worker = task::spawn(async move {
                 let imu_socket = Arc::clone(&imu_socket);
+                // Saved sinks keep streaming across restarts
+                if let Err(e) = imu_socket.hold_sinks_session().await {
+                    warn!("failed to start imu for sinks: {}", e);
+                }
                 tokio::select! {
                     _ = notify.notified() => {
                         info!("imu service shutdown...");
+                        imu_socket.release_low_power();
                         if let Err(e) = imu_socket.stop().await {
                             warn!("failed to stop imu socket: {}", e);
                         }
                     }
+                    _ = imu_socket.watch_health() => {}
+                    _ = imu_socket.expire_sessions() => {}
+                    _ = imu_socket.watch_motion() => {}
                 }
                 info!("imu service shutdown complete");
             });
@@ -0,0 +1,67 @@
+use crate::devices::imu::ImuData;
+use nalgebra::{UnitQuaternion, Vector3};
+use std::f64::consts::TAU;
+
+const STANDARD_GRAVITY: f64 = 9.80665;
+// Weight of a new sample in the accel norm average
+const NORM_SMOOTHING: f64 = 0.01;
+
+/// Outputs derived from orientation, accel values in the driver's unit
+#[derive(Debug, Clone, Default)]
+pub struct Kinematics {
+    pub gravity: [f32; 3],      // device frame, what accel reads at rest
+    pub linear_accel: [f32; 3], // device frame, accel minus gravity
+    pub heading: Option<f32>,   // radians clockwise from magnetic north, 0 to 2π, None without mag
+    pub world_gyro: [f32; 3],   // radians/s in world frame, z up
+}
+
+/// Kinematics estimator
+/// 根据融合的姿态计算重力向量、去掉重力的线性加速度、倾斜补偿的磁航向和世界坐标系角速度
+#[derive(Default)]
+pub struct KinematicsEstimator {
+    accel_norm: Option<f64>,
+}
+
+impl KinematicsEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `quat` rotates device frame into world frame, like fusion returns
+    pub fn update(&mut self, quat: &UnitQuaternion<f64>, imu_data: &ImuData) -> Kinematics {
+        let accel = Vector3::from(imu_data.accel.map(|v| v as f64));
+        let gyro = Vector3::from(imu_data.gyro.map(|v| v as f64));
+        let mag = Vector3::from(imu_data.mag.map(|v| v as f64));
+
+        // Drivers report g or m/s², pick whichever the average accel norm is closer to
+        let norm = accel.norm();
+        let accel_norm = match self.accel_norm {
+            Some(accel_norm) => accel_norm + (norm - accel_norm) * NORM_SMOOTHING,
+            None => norm,
+        };
+        self.accel_norm = Some(accel_norm);
+        let gravity_norm = if (accel_norm - 1.0).abs() < (accel_norm - STANDARD_GRAVITY).abs() {
+            1.0
+        } else {
+            STANDARD_GRAVITY
+        };
+
+        let gravity = quat.inverse_transform_vector(&Vector3::new(0.0, 0.0, gravity_norm));
+        let linear_accel = accel - gravity;
+        let world_gyro = quat.transform_vector(&gyro);
+
+        // Level mag with roll and pitch only, heading is independent of fusion yaw
+        let heading = (mag.norm() > 0.0).then(|| {
+            let (roll, pitch, _) = quat.euler_angles();
+            let level = UnitQuaternion::from_euler_angles(roll, pitch, 0.0).transform_vector(&mag);
+            level.y.atan2(level.x).rem_euclid(TAU) as f32
+        });
+
+        Kinematics {
+            gravity: gravity.map(|v| v as f32).into(),
+            linear_accel: linear_accel.map(|v| v as f32).into(),
+            heading,
+            world_gyro: world_gyro.map(|v| v as f32).into(),
+        }
+    }
+}
@@ -1,11 +1,12 @@
-use crate::devices::imu::{Imu, ImuData};
+use crate::devices::imu::{Imu, ImuConfig, ImuData, timestamp_now};
 use log::debug;
 use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::Instant;
 
 pub struct MockImu {
     name: String,
-    sample_rate: u32,
+    sample_rate: AtomicU32,
     start: Instant,
 }
 
@@ -13,7 +14,7 @@ impl MockImu {
     pub fn new() -> Self {
         Self {
             name: "mock".to_string(),
-            sample_rate: 50,
+            sample_rate: AtomicU32::new(50),
             start: Instant::now(),
         }
     }
@@ -68,6 +69,7 @@ impl Imu for MockImu {
         let temp = 25_000.0 + 500.0 * (t * 0.1).sin();
 
         ImuData {
+            timestamp: timestamp_now(),
             accel,
             gyro,
             mag,
@@ -87,7 +89,30 @@ impl Imu for MockImu {
         Ok(())
     }
 
-    fn sample_rate(&self) -> u32 {
-        self.sample_rate
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate.load(Ordering::Acquire) as f64
+    }
+
+    /// Any whole rate up to 1000Hz, no scales
+    fn set_config(&self, config: &ImuConfig) -> io::Result<()> {
+        if config.accel_scale.is_some() || config.gyro_scale.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "mock imu has no accel or gyro scale",
+            ));
+        }
+        if let Some(rate) = config.sample_rate {
+            if !(1.0..=1000.0).contains(&rate) || rate.fract() != 0.0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "invalid sample rate {}, must be a whole number 1 to 1000",
+                        rate
+                    ),
+                ));
+            }
+            self.sample_rate.store(rate as u32, Ordering::Release);
+        }
+        Ok(())
     }
 }
@@ -1,10 +1,41 @@
+mod calibration;
+mod discovery;
+mod fusion;
+mod health;
 mod iio;
+mod iio_buffer;
+mod iio_event;
+#[allow(clippy::module_inception)]
 mod imu;
+mod kinematics;
 mod mock;
+mod motion;
+mod mount;
+mod recording;
+mod replay;
+mod session;
+mod sinks;
 pub mod socket;
+mod stream;
 mod types;
+mod wake;
 
+pub use calibration::{Calibration, CalibrationKind, Calibrator};
+pub use discovery::discover_iio_imus;
+pub use fusion::{Fusion, FusionAlgorithm};
+pub use health::{HealthFault, HealthMonitor, ImuHealth};
 pub use iio::IioImu;
+use iio_buffer::{IioBuffer, IioSample};
+use iio_event::IioMotionEvent;
 pub use imu::start_imu_service;
+pub use kinematics::KinematicsEstimator;
 pub use mock::MockImu;
+pub use motion::{MotionDetector, MotionEventKind, MotionEvents};
+pub use mount::{MountMatrix, MountSource};
+pub use recording::{Recorder, recording_path};
+pub use replay::{ReplayImu, ReplayOptions};
+pub use session::{ImuSessions, SessionInfo};
+pub use sinks::{OutputSinks, SinkFormat, SinkInfo};
+pub use stream::{ImuStreams, ImuTopic};
 pub use types::*;
+pub use wake::{WakeInfo, WakeMode, WakeOnMotion, WakeSettings};
@@ -0,0 +1,575 @@
+use crate::devices::imu::{HealthFault, ImuData};
+use nalgebra::Vector3;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::{sync::Notify, time};
+
+// Events kept for HTTP clients
+const RECENT_EVENTS: usize = 64;
+
+// Gravity low-pass time constant, seconds
+const GRAVITY_TAU: f32 = 0.2;
+
+// Accel thresholds are in g of linear acceleration (accel minus gravity)
+const TAP_THRESHOLD: f32 = 0.6;
+const TAP_SETTLE: f32 = 0.2;
+const TAP_QUIET_BEFORE: u64 = 100_000; // microseconds
+const TAP_MAX_DURATION: u64 = 100_000;
+const DOUBLE_TAP_WINDOW: u64 = 400_000;
+
+const SHAKE_THRESHOLD: f32 = 1.0;
+const SHAKE_PEAKS: usize = 4; // direction reversals within the window
+const SHAKE_WINDOW: u64 = 1_000_000;
+const SHAKE_COOLDOWN: u64 = 1_000_000;
+
+const STILL_GYRO: f32 = 0.1; // radians/s
+const STILL_ACCEL: f32 = 0.05;
+const PICK_UP_REST: u64 = 1_000_000;
+const PICK_UP_MOTION: u64 = 150_000;
+
+// Share of gravity, the gap between enter and leave thresholds is the hysteresis
+const FACE_ENTER: f32 = 0.9;
+const FACE_LEAVE: f32 = 0.7;
+const FACE_HOLD: u64 = 500_000;
+const ORIENTATION_MIN_TILT: f32 = 0.5;
+const ORIENTATION_RATIO: f32 = 1.5; // dominant axis over the other one
+const ORIENTATION_HOLD: u64 = 300_000;
+
+/// Screen orientation, named by the edge pointing down
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreenOrientation {
+    Portrait,
+    PortraitUpsideDown,
+    LandscapeLeft,
+    LandscapeRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Face {
+    Up,
+    Down,
+    Tilted,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MotionEventKind {
+    Tap,
+    DoubleTap,
+    Shake,
+    FaceUp,
+    FaceDown,
+    PickUp,
+    Orientation {
+        orientation: ScreenOrientation,
+    },
+    // From the health monitor, channel is like IMU_CHANNELS, "buffer" or "imu"
+    SensorFault {
+        channel: &'static str,
+        fault: HealthFault,
+    },
+    SensorRecovered {
+        channel: &'static str,
+        fault: HealthFault,
+    },
+    // From wake-on-motion, accel moved past the threshold
+    Motion,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MotionEvent {
+    pub seq: u64,
+    pub timestamp: u64, // microseconds since UNIX_EPOCH, of the sample that triggered it
+    #[serde(flatten)]
+    pub kind: MotionEventKind,
+}
+
+/// Motion detector
+/// 从设备坐标系下的加速度计和陀螺仪数据检测敲击、双击、摇晃、翻面、拿起和屏幕方向变化
+#[derive(Default)]
+pub struct MotionDetector {
+    last_timestamp: Option<u64>,
+    gravity: Option<Vector3<f32>>, // low-passed accel, in driver units
+    last_active: u64,              // last sample with linear accel above TAP_SETTLE
+    tap_start: Option<u64>,
+    last_tap: Option<u64>,
+    shake_peaks: VecDeque<(u64, Vector3<f32>)>,
+    shake_peak_armed: bool,
+    shake_cooldown_until: u64,
+    rest_since: Option<u64>,
+    pick_up_since: Option<u64>,
+    face: Debounce<Face>,
+    orientation: Debounce<ScreenOrientation>,
+}
+
+/// A state that changes only after the new value holds for a while
+struct Debounce<T> {
+    current: Option<T>,
+    candidate: Option<(T, u64)>,
+}
+
+impl<T> Default for Debounce<T> {
+    fn default() -> Self {
+        Self {
+            current: None,
+            candidate: None,
+        }
+    }
+}
+
+impl<T: Copy + PartialEq> Debounce<T> {
+    /// Returns the new state once it changed, `None` input means undecided and keeps the state
+    fn update(&mut self, value: Option<T>, now: u64, hold: u64) -> Option<T> {
+        let Some(value) = value.filter(|value| self.current != Some(*value)) else {
+            self.candidate = None;
+            return None;
+        };
+        match self.candidate {
+            Some((candidate, since)) if candidate == value => {
+                if now.saturating_sub(since) >= hold {
+                    self.current = Some(value);
+                    self.candidate = None;
+                    return Some(value);
+                }
+            }
+            _ => self.candidate = Some((value, now)),
+        }
+        None
+    }
+}
+
+impl MotionDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one calibrated sample in device frame, returns the events it triggered
+    pub fn update(&mut self, imu_data: &ImuData) -> Vec<MotionEventKind> {
+        let now = imu_data.timestamp;
+        let accel = Vector3::from(imu_data.accel);
+        let gyro = Vector3::from(imu_data.gyro);
+        let dt = self
+            .last_timestamp
+            .map(|last| now.saturating_sub(last) as f32 / 1e6)
+            .unwrap_or(0.0)
+            .min(0.1);
+        self.last_timestamp = Some(now);
+
+        let gravity = match self.gravity {
+            Some(gravity) => gravity + (accel - gravity) * (dt / (GRAVITY_TAU + dt)),
+            None => accel,
+        };
+        self.gravity = Some(gravity);
+        // Drivers report either g or m/s², thresholds are relative to gravity
+        let g = gravity.norm();
+        if g <= f32::EPSILON {
+            return vec![];
+        }
+        let linear = (accel - gravity) / g;
+        let linear_norm = linear.norm();
+
+        let mut events = Vec::new();
+        self.detect_shake(now, &linear, &mut events);
+        self.detect_tap(now, linear_norm, &mut events);
+        self.detect_pick_up(now, linear_norm, gyro.norm(), &mut events);
+        self.detect_face(now, gravity / g, &mut events);
+        self.detect_orientation(now, gravity / g, &mut events);
+        events
+    }
+
+    /// Short spike after a quiet moment that settles again quickly
+    fn detect_tap(&mut self, now: u64, linear_norm: f32, events: &mut Vec<MotionEventKind>) {
+        let quiet_for = now.saturating_sub(self.last_active);
+        if linear_norm > TAP_SETTLE {
+            self.last_active = now;
+        }
+
+        match self.tap_start {
+            None => {
+                if linear_norm > TAP_THRESHOLD
+                    && quiet_for >= TAP_QUIET_BEFORE
+                    && now >= self.shake_cooldown_until
+                {
+                    self.tap_start = Some(now);
+                }
+            }
+            Some(start) if linear_norm < TAP_SETTLE => {
+                self.tap_start = None;
+                if now.saturating_sub(start) > TAP_MAX_DURATION {
+                    return;
+                }
+
+                events.push(MotionEventKind::Tap);
+                match self.last_tap.take() {
+                    Some(last) if now.saturating_sub(last) <= DOUBLE_TAP_WINDOW => {
+                        events.push(MotionEventKind::DoubleTap);
+                    }
+                    _ => self.last_tap = Some(now),
+                }
+            }
+            Some(start) if now.saturating_sub(start) > TAP_MAX_DURATION => self.tap_start = None,
+            Some(_) => {}
+        }
+    }
+
+    /// Strong linear accel peaks that keep reversing direction
+    fn detect_shake(&mut self, now: u64, linear: &Vector3<f32>, events: &mut Vec<MotionEventKind>) {
+        while self
+            .shake_peaks
+            .front()
+            .is_some_and(|(time, _)| now.saturating_sub(*time) > SHAKE_WINDOW)
+        {
+            self.shake_peaks.pop_front();
+        }
+
+        if linear.norm() < SHAKE_THRESHOLD {
+            self.shake_peak_armed = true;
+            return;
+        }
+        if !self.shake_peak_armed || now < self.shake_cooldown_until {
+            return;
+        }
+        self.shake_peak_armed = false;
+
+        let reversed = self
+            .shake_peaks
+            .back()
+            .is_none_or(|(_, direction)| direction.dot(linear) < 0.0);
+        if reversed {
+            self.shake_peaks.push_back((now, *linear));
+        }
+        if self.shake_peaks.len() >= SHAKE_PEAKS {
+            events.push(MotionEventKind::Shake);
+            self.shake_peaks.clear();
+            self.shake_cooldown_until = now.saturating_add(SHAKE_COOLDOWN);
+            // A shake is no tap
+            self.tap_start = None;
+            self.last_tap = None;
+        }
+    }
+
+    /// Lasting motion after the device rested for a while
+    fn detect_pick_up(
+        &mut self,
+        now: u64,
+        linear_norm: f32,
+        gyro_norm: f32,
+        events: &mut Vec<MotionEventKind>,
+    ) {
+        if gyro_norm < STILL_GYRO && linear_norm < STILL_ACCEL {
+            self.rest_since.get_or_insert(now);
+            self.pick_up_since = None;
+            return;
+        }
+
+        if let Some(rest_since) = self.rest_since.take()
+            && now.saturating_sub(rest_since) >= PICK_UP_REST
+        {
+            self.pick_up_since = Some(now);
+        }
+        if let Some(since) = self.pick_up_since
+            && now.saturating_sub(since) >= PICK_UP_MOTION
+        {
+            events.push(MotionEventKind::PickUp);
+            self.pick_up_since = None;
+        }
+    }
+
+    fn detect_face(&mut self, now: u64, gravity: Vector3<f32>, events: &mut Vec<MotionEventKind>) {
+        let face = if gravity.z > FACE_ENTER {
+            Some(Face::Up)
+        } else if gravity.z < -FACE_ENTER {
+            Some(Face::Down)
+        } else if gravity.z.abs() < FACE_LEAVE {
+            Some(Face::Tilted)
+        } else {
+            None
+        };
+        match self.face.update(face, now, FACE_HOLD) {
+            Some(Face::Up) => events.push(MotionEventKind::FaceUp),
+            Some(Face::Down) => events.push(MotionEventKind::FaceDown),
+            _ => {}
+        }
+    }
+
+    /// Orientation follows the axis gravity is mostly on, undecided while lying flat
+    fn detect_orientation(
+        &mut self,
+        now: u64,
+        gravity: Vector3<f32>,
+        events: &mut Vec<MotionEventKind>,
+    ) {
+        let (x, y) = (gravity.x, gravity.y);
+        let orientation = if x.hypot(y) < ORIENTATION_MIN_TILT {
+            None
+        } else if y.abs() > x.abs() * ORIENTATION_RATIO {
+            Some(if y > 0.0 {
+                ScreenOrientation::Portrait
+            } else {
+                ScreenOrientation::PortraitUpsideDown
+            })
+        } else if x.abs() > y.abs() * ORIENTATION_RATIO {
+            // Accel reads +1g on the axis pointing up, x up means the left edge is down
+            Some(if x > 0.0 {
+                ScreenOrientation::LandscapeLeft
+            } else {
+                ScreenOrientation::LandscapeRight
+            })
+        } else {
+            None
+        };
+        if let Some(orientation) = self.orientation.update(orientation, now, ORIENTATION_HOLD) {
+            events.push(MotionEventKind::Orientation { orientation });
+        }
+    }
+}
+
+/// Recent motion events for HTTP clients
+pub struct MotionEvents {
+    recent: Mutex<(u64, VecDeque<MotionEvent>)>, // last seq and events
+    notify: Notify,
+}
+
+impl MotionEvents {
+    pub fn new() -> Self {
+        Self {
+            recent: Mutex::new((0, VecDeque::new())),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn push(&self, timestamp: u64, kind: MotionEventKind) -> MotionEvent {
+        let mut recent = self.recent.lock().unwrap();
+        recent.0 += 1;
+        let event = MotionEvent {
+            seq: recent.0,
+            timestamp,
+            kind,
+        };
+        if recent.1.len() >= RECENT_EVENTS {
+            recent.1.pop_front();
+        }
+        recent.1.push_back(event.clone());
+        drop(recent);
+
+        self.notify.notify_waiters();
+        event
+    }
+
+    /// Events with a seq after `since`
+    pub fn since(&self, since: u64) -> Vec<MotionEvent> {
+        let recent = self.recent.lock().unwrap();
+        recent
+            .1
+            .iter()
+            .filter(|event| event.seq > since)
+            .cloned()
+            .collect()
+    }
+
+    /// Like `since`, but wait up to `timeout` for one when there are none yet
+    pub async fn wait_since(&self, since: u64, timeout: Duration) -> Vec<MotionEvent> {
+        let deadline = time::Instant::now() + timeout;
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let events = self.since(since);
+            if !events.is_empty() {
+                return events;
+            }
+            if time::timeout_at(deadline, notified).await.is_err() {
+                return vec![];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 100Hz samples in g
+    const STEP_MS: u64 = 10;
+    const FLAT: [f32; 3] = [0.0, 0.0, 1.0];
+
+    #[derive(Default)]
+    struct Feeder {
+        detector: MotionDetector,
+        now_ms: u64,
+    }
+
+    impl Feeder {
+        /// Feed `ms` worth of samples reading `accel`, returns the events
+        fn feed(&mut self, ms: u64, accel: [f32; 3]) -> Vec<MotionEventKind> {
+            let mut events = Vec::new();
+            for _ in 0..ms / STEP_MS {
+                self.now_ms += STEP_MS;
+                events.extend(self.sample(self.now_ms, accel, [0.0; 3]));
+            }
+            events
+        }
+
+        fn sample(&mut self, ms: u64, accel: [f32; 3], gyro: [f32; 3]) -> Vec<MotionEventKind> {
+            self.detector.update(&ImuData {
+                timestamp: ms * 1000,
+                accel,
+                gyro,
+                ..Default::default()
+            })
+        }
+
+        /// Like `feed`, keeping only face and orientation changes
+        fn feed_turn(&mut self, ms: u64, accel: [f32; 3]) -> Vec<MotionEventKind> {
+            self.feed(ms, accel)
+                .into_iter()
+                .filter(|event| {
+                    matches!(
+                        event,
+                        MotionEventKind::FaceUp
+                            | MotionEventKind::FaceDown
+                            | MotionEventKind::Orientation { .. }
+                    )
+                })
+                .collect()
+        }
+
+        fn tap(&mut self) -> Vec<MotionEventKind> {
+            let mut events = self.feed(20, [0.8, 0.0, 1.0]);
+            events.extend(self.feed(100, FLAT));
+            events
+        }
+    }
+
+    fn count(events: &[MotionEventKind], kind: MotionEventKind) -> usize {
+        events.iter().filter(|event| **event == kind).count()
+    }
+
+    #[test]
+    fn tap() {
+        let mut feeder = Feeder::default();
+        assert!(!feeder.feed(500, FLAT).contains(&MotionEventKind::Tap));
+
+        let events = feeder.tap();
+        assert_eq!(count(&events, MotionEventKind::Tap), 1);
+        assert_eq!(count(&events, MotionEventKind::DoubleTap), 0);
+
+        // Taps too far apart are no double tap
+        feeder.feed(500, FLAT);
+        let events = feeder.tap();
+        assert_eq!(count(&events, MotionEventKind::Tap), 1);
+        assert_eq!(count(&events, MotionEventKind::DoubleTap), 0);
+    }
+
+    #[test]
+    fn long_push_is_no_tap() {
+        let mut feeder = Feeder::default();
+        feeder.feed(500, FLAT);
+        let mut events = feeder.feed(300, [0.8, 0.0, 1.0]);
+        events.extend(feeder.feed(300, FLAT));
+        assert_eq!(count(&events, MotionEventKind::Tap), 0);
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut feeder = Feeder::default();
+        feeder.feed(500, FLAT);
+        let mut events = feeder.tap();
+        events.extend(feeder.feed(100, FLAT));
+        events.extend(feeder.tap());
+        assert_eq!(count(&events, MotionEventKind::Tap), 2);
+        assert_eq!(count(&events, MotionEventKind::DoubleTap), 1);
+    }
+
+    #[test]
+    fn shake() {
+        let mut feeder = Feeder::default();
+        feeder.feed(500, FLAT);
+
+        let mut events = Vec::new();
+        for direction in [1.0, -1.0, 1.0, -1.0, 1.0, -1.0] {
+            events.extend(feeder.feed(30, [2.0 * direction, 0.0, 1.0]));
+            events.extend(feeder.feed(50, FLAT));
+        }
+        assert_eq!(count(&events, MotionEventKind::Shake), 1);
+
+        // Peaks in one direction only are no shake
+        let mut feeder = Feeder::default();
+        feeder.feed(500, FLAT);
+        let mut events = Vec::new();
+        for _ in 0..6 {
+            events.extend(feeder.feed(30, [2.0, 0.0, 1.0]));
+            events.extend(feeder.feed(50, FLAT));
+        }
+        assert_eq!(count(&events, MotionEventKind::Shake), 0);
+    }
+
+    #[test]
+    fn face_and_orientation() {
+        let mut feeder = Feeder::default();
+        let events = feeder.feed_turn(1000, FLAT);
+        assert_eq!(events, [MotionEventKind::FaceUp]);
+
+        // Standing upright, top edge up
+        let events = feeder.feed_turn(1000, [0.0, 1.0, 0.0]);
+        assert_eq!(
+            events,
+            [MotionEventKind::Orientation {
+                orientation: ScreenOrientation::Portrait
+            }]
+        );
+
+        // Turned onto the left edge
+        let events = feeder.feed_turn(1000, [1.0, 0.0, 0.0]);
+        assert_eq!(
+            events,
+            [MotionEventKind::Orientation {
+                orientation: ScreenOrientation::LandscapeLeft
+            }]
+        );
+
+        // Too short to count
+        let mut events = feeder.feed_turn(100, [-1.0, 0.0, 0.0]);
+        events.extend(feeder.feed_turn(1000, [1.0, 0.0, 0.0]));
+        assert!(events.is_empty());
+
+        let events = feeder.feed_turn(1000, [0.0, 0.0, -1.0]);
+        assert_eq!(events, [MotionEventKind::FaceDown]);
+    }
+
+    #[test]
+    fn pick_up() {
+        let mut feeder = Feeder::default();
+        feeder.feed(1500, FLAT);
+        let mut events = Vec::new();
+        for _ in 0..30 {
+            feeder.now_ms += STEP_MS;
+            events.extend(feeder.sample(feeder.now_ms, FLAT, [0.5, 0.0, 0.0]));
+        }
+        assert_eq!(count(&events, MotionEventKind::PickUp), 1);
+    }
+
+    #[test]
+    fn timestamps_going_backwards() {
+        // Tap and pick up started, then a sample from before they did
+        let mut feeder = Feeder::default();
+        feeder.feed(1500, FLAT);
+        feeder.feed(10, [0.8, 0.0, 1.0]);
+        feeder.sample(100, FLAT, [0.5, 0.0, 0.0]);
+
+        // Shake peak kept, then an earlier sample
+        let mut feeder = Feeder::default();
+        feeder.feed(1500, FLAT);
+        feeder.feed(10, [2.0, 0.0, 1.0]);
+        feeder.sample(100, FLAT, [0.0; 3]);
+
+        // Resting since later than the next sample
+        let mut feeder = Feeder::default();
+        feeder.sample(5000, FLAT, [0.0; 3]);
+        feeder.sample(100, FLAT, [0.5, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::devices::imu::ImuData;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Mount matrix, row major, maps sensor axes to device axes: device = matrix * sensor
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MountMatrix(pub [[f32; 3]; 3]);
+
+/// Where the mount matrix comes from
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MountSource {
+    Iio,     // driver's mount_matrix, from device tree
+    Config,  // set with /imuN/mount/set
+    Default, // the IMU's default, see Imu::default_mount_matrix
+}
+
+impl MountMatrix {
+    pub const IDENTITY: MountMatrix =
+        MountMatrix([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+
+    /// x and z swapped, what every IMU used before the mount matrix could be set
+    pub const LEGACY: MountMatrix =
+        MountMatrix([[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]);
+
+    pub fn apply(&self, imu_data: &mut ImuData) {
+        imu_data.accel = self.rotate(imu_data.accel);
+        imu_data.gyro = self.rotate(imu_data.gyro);
+        imu_data.mag = self.rotate(imu_data.mag);
+    }
+
+    fn rotate(&self, v: [f32; 3]) -> [f32; 3] {
+        std::array::from_fn(|i| (0..3).map(|j| self.0[i][j] * v[j]).sum())
+    }
+}
+
+impl FromStr for MountMatrix {
+    type Err = String;
+
+    /// IIO format: "x0, y0, z0; x1, y1, z1; x2, y2, z2"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows: Vec<Vec<f32>> = s
+            .split(';')
+            .map(|row| row.split(',').map(|value| value.trim().parse()).collect())
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("invalid mount matrix '{}': {}", s, e))?;
+        if rows.len() != 3 || rows.iter().any(|row| row.len() != 3) {
+            return Err(format!("invalid mount matrix '{}', expected 3x3", s));
+        }
+        Ok(MountMatrix(std::array::from_fn(|i| {
+            std::array::from_fn(|j| rows[i][j])
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_swaps_x_and_z() {
+        let mut imu_data = ImuData {
+            accel: [1.0, 2.0, 3.0],
+            gyro: [4.0, 5.0, 6.0],
+            mag: [7.0, 8.0, 9.0],
+            ..Default::default()
+        };
+        MountMatrix::LEGACY.apply(&mut imu_data);
+        assert_eq!(imu_data.accel, [3.0, 2.0, 1.0]);
+        assert_eq!(imu_data.gyro, [6.0, 5.0, 4.0]);
+        assert_eq!(imu_data.mag, [9.0, 8.0, 7.0]);
+    }
+
+    #[test]
+    fn parse_iio_format() {
+        assert_eq!(
+            "1, 0, 0; 0, 1, 0; 0, 0, 1".parse::<MountMatrix>(),
+            Ok(MountMatrix::IDENTITY)
+        );
+        assert_eq!(
+            "0, 0, 1; 0, 1, 0; 1, 0, 0".parse::<MountMatrix>(),
+            Ok(MountMatrix::LEGACY)
+        );
+        assert!("1, 0; 0, 1".parse::<MountMatrix>().is_err());
+        assert!("a, 0, 0; 0, 1, 0; 0, 0, 1".parse::<MountMatrix>().is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use crate::devices::imu::socket::ImuDataProto;
+use log::{error, info};
+use prost::Message;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RecordingInfo {
+    pub recording: bool,
+    pub path: Option<PathBuf>,
+    pub samples: u64,
+}
+
+/// Path of a recording called `name` in `dir`, only a plain file name is accepted
+pub fn recording_path(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(file_name)), None) => Ok(dir.join(file_name)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "invalid recording name '{}', must be a file name like walk.pb",
+                name
+            ),
+        )),
+    }
+}
+
+struct Recording {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    samples: u64,
+}
+
+/// IMU recorder
+/// 把发布的 ImuDataProto 按 length-delimited protobuf 依次写进文件，可以用 ReplayImu 回放
+pub struct Recorder {
+    id: String,
+    recording: Mutex<Option<Recording>>,
+    // Last finished recording, for status
+    last: Mutex<Option<RecordingInfo>>,
+}
+
+impl Recorder {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            recording: Mutex::new(None),
+            last: Mutex::new(None),
+        }
+    }
+
+    pub fn status(&self) -> RecordingInfo {
+        match self.recording.lock().unwrap().as_ref() {
+            Some(recording) => RecordingInfo {
+                recording: true,
+                path: Some(recording.path.clone()),
+                samples: recording.samples,
+            },
+            None => self.last.lock().unwrap().clone().unwrap_or(RecordingInfo {
+                recording: false,
+                path: None,
+                samples: 0,
+            }),
+        }
+    }
+
+    /// Start writing published samples to `path`, one recording at a time
+    pub fn start(&self, path: PathBuf) -> io::Result<RecordingInfo> {
+        let mut recording = self.recording.lock().unwrap();
+        if let Some(recording) = recording.as_ref() {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                format!(
+                    "{} is already recording to {}",
+                    self.id,
+                    recording.path.display()
+                ),
+            ));
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(&path).map_err(|e| {
+            io::Error::new(e.kind(), format!("create {} failed: {}", path.display(), e))
+        })?;
+        info!("{} start recording to {}", self.id, path.display());
+
+        *recording = Some(Recording {
+            path: path.clone(),
+            writer: BufWriter::new(file),
+            samples: 0,
+        });
+        Ok(RecordingInfo {
+            recording: true,
+            path: Some(path),
+            samples: 0,
+        })
+    }
+
+    /// Flush and close the file
+    pub fn stop(&self) -> io::Result<RecordingInfo> {
+        let Some(mut recording) = self.recording.lock().unwrap().take() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not recording", self.id),
+            ));
+        };
+        recording.writer.flush()?;
+
+        let info = RecordingInfo {
+            recording: false,
+            path: Some(recording.path),
+            samples: recording.samples,
+        };
+        info!("{} stop recording, {} samples", self.id, info.samples);
+        *self.last.lock().unwrap() = Some(info.clone());
+        Ok(info)
+    }
+
+    /// Append a sample when recording, a write error ends the recording
+    pub fn write(&self, proto_msg: &ImuDataProto) {
+        let mut recording = self.recording.lock().unwrap();
+        let Some(current) = recording.as_mut() else {
+            return;
+        };
+
+        match current
+            .writer
+            .write_all(&proto_msg.encode_length_delimited_to_vec())
+        {
+            Ok(_) => current.samples += 1,
+            Err(e) => {
+                error!(
+                    "{} write {} failed, recording stopped: {}",
+                    self.id,
+                    current.path.display(),
+                    e
+                );
+                let _ = current.writer.flush();
+                *self.last.lock().unwrap() = Some(RecordingInfo {
+                    recording: false,
+                    path: Some(current.path.clone()),
+                    samples: current.samples,
+                });
+                *recording = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::imu::{Imu, ReplayImu, ReplayOptions};
+    use std::time::Duration;
+
+    #[test]
+    fn recording_names() {
+        let dir = Path::new("/config/recordings");
+        assert_eq!(recording_path(dir, "walk.pb").unwrap(), dir.join("walk.pb"));
+        for name in [
+            "",
+            ".",
+            "..",
+            "../walk.pb",
+            "a/walk.pb",
+            "/tmp/walk.pb",
+            "./walk.pb",
+        ] {
+            let e = recording_path(dir, name).err().unwrap();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{}", name);
+        }
+    }
+
+    #[test]
+    fn replay_what_was_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recordings/walk.pb");
+
+        let recorder = Recorder::new("imu0");
+        // Not recording yet, nothing is written
+        recorder.write(&ImuDataProto::default());
+        recorder.start(path.clone()).unwrap();
+        assert_eq!(
+            recorder.start(path.clone()).err().unwrap().kind(),
+            io::ErrorKind::ResourceBusy
+        );
+
+        let recorded: Vec<ImuDataProto> = (0..5u64)
+            .map(|i| {
+                let value = i as f32;
+                ImuDataProto {
+                    timestamp: 1_000_000 + i * 10_000,
+                    accel: vec![value, -value, 9.8],
+                    gyro: vec![0.1 * value, 0.2, -0.3],
+                    mag: vec![30.0, value, -40.0],
+                    temp: 25000.0 + value,
+                    quaternion: vec![1.0, 0.0, 0.0, 0.0],
+                    ..Default::default()
+                }
+            })
+            .collect();
+        for proto_msg in &recorded {
+            recorder.write(proto_msg);
+        }
+        let info = recorder.stop().unwrap();
+        assert_eq!(info.samples, 5);
+        assert_eq!(info.path.as_ref(), Some(&path));
+        assert!(!recorder.status().recording);
+
+        let replay = ReplayImu::new(&ReplayOptions {
+            path,
+            speed: 1000.0,
+            looping: false,
+        })
+        .unwrap();
+        assert_eq!(replay.sample_rate(), 100.0);
+        replay.init().unwrap();
+
+        let mut replayed = Vec::new();
+        while replayed.len() < recorded.len() {
+            let samples = replay.read_buffer(Duration::from_millis(100)).unwrap();
+            assert!(!samples.is_empty(), "replay ended early");
+            replayed.extend(samples);
+        }
+        assert_eq!(replayed.len(), recorded.len());
+
+        for (replayed, recorded) in replayed.iter().zip(&recorded) {
+            assert_eq!(replayed.accel.as_slice(), recorded.accel.as_slice());
+            assert_eq!(replayed.gyro.as_slice(), recorded.gyro.as_slice());
+            assert_eq!(replayed.mag.as_slice(), recorded.mag.as_slice());
+            assert_eq!(replayed.temp, recorded.temp);
+        }
+        // Timestamps start at replay time, intervals are kept
+        for pair in replayed.windows(2) {
+            assert_eq!(pair[1].timestamp - pair[0].timestamp, 10_000);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::devices::imu::socket::ImuDataProto;
+use crate::devices::imu::{Imu, ImuData, MountMatrix, timestamp_now};
+use log::{debug, info};
+use prost::Message;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Replay options from command line
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    pub path: PathBuf,
+    pub speed: f64,
+    pub looping: bool,
+}
+
+/// IMU replaying a recording
+/// 按录制时的时间间隔（可加速/减速、可循环）回放 /imuN/record 录下的数据，不用硬件就能复现姿态问题
+pub struct ReplayImu {
+    path: PathBuf,
+    samples: Vec<ImuData>,
+    speed: f64,
+    looping: bool,
+    sample_rate: f64,
+    period: u64, // median sample interval in microseconds, between loops
+    state: Mutex<ReplayState>,
+}
+
+struct ReplayState {
+    index: usize,
+    started: Instant,
+    base_timestamp: u64, // replayed timestamps start here, deltas are kept as recorded
+    offset: u64,         // recording time of the current loop's first sample, from the first sample
+    finished: bool,
+}
+
+impl ReplayImu {
+    pub fn new(options: &ReplayOptions) -> io::Result<Self> {
+        if !(options.speed > 0.0 && options.speed.is_finite()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid replay speed {}, must be above 0", options.speed),
+            ));
+        }
+
+        let samples = Self::load(&options.path)?;
+        let mut intervals: Vec<u64> = samples
+            .windows(2)
+            .map(|pair| pair[1].timestamp.saturating_sub(pair[0].timestamp))
+            .filter(|interval| *interval > 0)
+            .collect();
+        intervals.sort_unstable();
+        let period = intervals
+            .get(intervals.len() / 2)
+            .copied()
+            .unwrap_or(20_000);
+        let sample_rate = 1e6 / period.max(1) as f64;
+
+        info!(
+            "replay {} samples from {} in {}Hz, speed {}x{}",
+            samples.len(),
+            options.path.display(),
+            sample_rate,
+            options.speed,
+            if options.looping { ", looping" } else { "" }
+        );
+
+        Ok(Self {
+            path: options.path.clone(),
+            samples,
+            speed: options.speed,
+            looping: options.looping,
+            sample_rate,
+            period,
+            state: Mutex::new(ReplayState {
+                index: 0,
+                started: Instant::now(),
+                base_timestamp: timestamp_now(),
+                offset: 0,
+                finished: false,
+            }),
+        })
+    }
+
+    /// Read a length-delimited ImuDataProto stream
+    fn load(path: &Path) -> io::Result<Vec<ImuData>> {
+        let data = fs::read(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("read {} failed: {}", path.display(), e))
+        })?;
+
+        let mut buf = data.as_slice();
+        let mut samples = Vec::new();
+        while !buf.is_empty() {
+            let proto_msg = ImuDataProto::decode_length_delimited(&mut buf).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} is not an imu recording, sample {}: {}",
+                        path.display(),
+                        samples.len(),
+                        e
+                    ),
+                )
+            })?;
+            let vec3 = |values: &[f32]| values.try_into().unwrap_or([0.0; 3]);
+            samples.push(ImuData {
+                timestamp: proto_msg.timestamp,
+                accel: vec3(&proto_msg.accel),
+                gyro: vec3(&proto_msg.gyro),
+                mag: vec3(&proto_msg.mag),
+                temp: proto_msg.temp,
+                quaternion: [0.0; 4],
+                euler_angles: [0.0; 3],
+            });
+        }
+
+        if samples.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has no samples", path.display()),
+            ));
+        }
+        Ok(samples)
+    }
+
+    /// Recording time of a sample from the start of the replay
+    fn offset_of(&self, state: &ReplayState, index: usize) -> u64 {
+        state.offset
+            + self.samples[index]
+                .timestamp
+                .saturating_sub(self.samples[0].timestamp)
+    }
+
+    fn due_at(&self, state: &ReplayState, index: usize) -> Instant {
+        state.started
+            + Duration::from_secs_f64(self.offset_of(state, index) as f64 / 1e6 / self.speed)
+    }
+}
+
+impl Imu for ReplayImu {
+    fn name(&self) -> String {
+        "replay".to_string()
+    }
+
+    /// Current sample, replay goes through `read_buffer`
+    fn imu_data(&self) -> ImuData {
+        let state = self.state.lock().unwrap();
+        let index = state.index.min(self.samples.len() - 1);
+        let mut imu_data = self.samples[index].clone();
+        imu_data.timestamp = state.base_timestamp + self.offset_of(&state, index);
+        imu_data
+    }
+
+    fn init(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        *state = ReplayState {
+            index: 0,
+            started: Instant::now(),
+            base_timestamp: timestamp_now(),
+            offset: 0,
+            finished: false,
+        };
+        debug!("replay {} from start", self.path.display());
+        Ok(())
+    }
+
+    fn deinit(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Recordings are already in device frame
+    fn default_mount_matrix(&self) -> MountMatrix {
+        MountMatrix::IDENTITY
+    }
+
+    fn is_buffered(&self) -> bool {
+        true
+    }
+
+    /// Samples that are due by now, waiting up to `timeout` for the next one
+    fn read_buffer(&self, timeout: Duration) -> io::Result<Vec<ImuData>> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.index >= self.samples.len() {
+                if !self.looping {
+                    if !state.finished {
+                        state.finished = true;
+                        info!("replay {} finished", self.path.display());
+                    }
+                    drop(state);
+                    std::thread::sleep(timeout);
+                    return Ok(vec![]);
+                }
+                state.offset = self.offset_of(&state, self.samples.len() - 1) + self.period;
+                state.index = 0;
+            }
+
+            let now = Instant::now();
+            let mut samples = Vec::new();
+            while state.index < self.samples.len() && self.due_at(&state, state.index) <= now {
+                let mut imu_data = self.samples[state.index].clone();
+                imu_data.timestamp = state.base_timestamp + self.offset_of(&state, state.index);
+                samples.push(imu_data);
+                state.index += 1;
+            }
+            if !samples.is_empty() || now >= deadline {
+                return Ok(samples);
+            }
+
+            if state.index < self.samples.len() {
+                let wake = self.due_at(&state, state.index).min(deadline);
+                drop(state);
+                std::thread::sleep(wake.saturating_duration_since(Instant::now()));
+                state = self.state.lock().unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use log::{debug, info};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, RandomState};
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionInfo {
+    pub session: String,
+    pub client: String,
+    pub ttl: Option<f64>, // seconds, None for internal sessions that never expire
+    pub expires_in: Option<f64>, // seconds until it ends without a keepalive
+}
+
+struct Session {
+    client: String,
+    ttl: Option<Duration>,
+    expires: Option<Instant>,
+}
+
+/// Streaming sessions of an IMU
+/// 每个客户端 start 时拿到一个会话并定时 keepalive 续期，还有会话时设备保持运行，最后一个会话结束或过期后停止
+pub struct ImuSessions {
+    id: String,
+    sessions: Mutex<BTreeMap<String, Session>>,
+    random: RandomState,
+}
+
+impl ImuSessions {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            sessions: Mutex::new(BTreeMap::new()),
+            random: RandomState::new(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<SessionInfo> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, session)| session.info(id))
+            .collect()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.sessions.lock().unwrap().contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn open(&self, client: &str, ttl: Option<Duration>) -> SessionInfo {
+        let mut sessions = self.sessions.lock().unwrap();
+        let mut id = String::new();
+        while id.is_empty() || sessions.contains_key(&id) {
+            id = format!("{:016x}", self.random.hash_one(Instant::now()));
+        }
+
+        let session = Session {
+            client: client.to_string(),
+            ttl,
+            expires: ttl.map(|ttl| Instant::now() + ttl),
+        };
+        let info = session.info(&id);
+        info!("{} session {} opened by {}", self.id, id, client);
+        sessions.insert(id, session);
+        info
+    }
+
+    /// Extend a session by its ttl
+    pub fn keepalive(&self, id: &str) -> io::Result<SessionInfo> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(id).ok_or_else(|| self.not_found(id))?;
+        session.expires = session.ttl.map(|ttl| Instant::now() + ttl);
+        debug!("{} session {} keepalive", self.id, id);
+        Ok(session.info(id))
+    }
+
+    pub fn close(&self, id: &str) -> io::Result<SessionInfo> {
+        let session = self
+            .sessions
+            .lock()
+            .unwrap()
+            .remove(id)
+            .ok_or_else(|| self.not_found(id))?;
+        info!("{} session {} of {} closed", self.id, id, session.client);
+        Ok(session.info(id))
+    }
+
+    /// Close every client session, returns how many there were
+    /// Internal sessions without ttl (sinks, calibration) are kept, their holders close them
+    pub fn close_all(&self) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let count = sessions.len();
+        sessions.retain(|_, session| session.ttl.is_none());
+        let count = count - sessions.len();
+        info!("{} all {} client sessions closed", self.id, count);
+        count
+    }
+
+    /// Remove sessions whose keepalive is overdue, returns how many
+    pub fn expire(&self) -> usize {
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        let count = sessions.len();
+        sessions.retain(|id, session| {
+            let alive = session.expires.is_none_or(|expires| expires > now);
+            if !alive {
+                info!("{} session {} of {} expired", self.id, id, session.client);
+            }
+            alive
+        });
+        count - sessions.len()
+    }
+
+    fn not_found(&self, id: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no session {}, it may have expired", self.id, id),
+        )
+    }
+}
+
+impl Session {
+    fn info(&self, id: &str) -> SessionInfo {
+        SessionInfo {
+            session: id.to_string(),
+            client: self.client.clone(),
+            ttl: self.ttl.map(|ttl| ttl.as_secs_f64()),
+            expires_in: self.expires.map(|expires| {
+                expires
+                    .saturating_duration_since(Instant::now())
+                    .as_secs_f64()
+            }),
+        }
+    }
+}
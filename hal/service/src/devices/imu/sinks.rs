@@ -0,0 +1,397 @@
+use crate::common::storage;
+use crate::devices::imu::socket::ImuDataProto;
+use crate::devices::imu::stream::due_at_rate;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+// Each sink sends one datagram or more per sample, keep the fan-out bounded
+const MAX_SINKS: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkFormat {
+    Osc,  // one OSC message per field, like /rebecca/imu0/quat ffff
+    Json, // one compact JSON object per sample
+}
+
+impl SinkFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SinkFormat::Osc => "osc",
+            SinkFormat::Json => "json",
+        }
+    }
+}
+
+impl fmt::Display for SinkFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SinkFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "osc" => Ok(SinkFormat::Osc),
+            "json" => Ok(SinkFormat::Json),
+            _ => Err(format!("invalid format '{}', expected osc or json", s)),
+        }
+    }
+}
+
+/// A UDP destination, persisted per IMU
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SinkConfig {
+    pub format: SinkFormat,
+    pub address: SocketAddr,
+    pub rate: Option<u32>, // Hz, None sends every sample
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SinkInfo {
+    #[serde(flatten)]
+    pub config: SinkConfig,
+    pub sent: u64, // samples
+    pub errors: u64,
+    pub last_error: Option<String>,
+}
+
+struct Sink {
+    config: SinkConfig,
+    socket: UdpSocket,
+    next_due: Option<u64>,
+    sent: u64,
+    errors: u64,
+    last_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonSample<'a> {
+    id: &'a str,
+    timestamp: u64,
+    accel: &'a [f32],
+    gyro: &'a [f32],
+    mag: &'a [f32],
+    temp: f32,
+    quaternion: &'a [f32],
+    euler_angles: &'a [f32],
+    gravity: &'a [f32],
+    linear_accel: &'a [f32],
+    heading: Option<f32>,
+    world_gyro: &'a [f32],
+}
+
+/// UDP output sinks of an IMU
+/// 把每个采样以 OSC 或紧凑 JSON 发到 UDP 地址，给 TouchDesigner、Pure Data、Processing 这类不支持 ZMQ 和 protobuf 的工具用
+pub struct OutputSinks {
+    id: String,
+    sinks: Mutex<Vec<Sink>>,
+}
+
+impl OutputSinks {
+    pub fn new(id: &str) -> Self {
+        let configs: Vec<SinkConfig> = storage::load(&format!("{}/sinks", id)).unwrap_or_default();
+        let sinks = configs
+            .into_iter()
+            .filter_map(|config| match Sink::open(config.clone()) {
+                Ok(sink) => Some(sink),
+                Err(e) => {
+                    warn!("{} open sink {:?} failed: {}", id, config, e);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            id: id.to_string(),
+            sinks: Mutex::new(sinks),
+        }
+    }
+
+    pub fn list(&self) -> Vec<SinkInfo> {
+        self.sinks.lock().unwrap().iter().map(Sink::info).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.lock().unwrap().is_empty()
+    }
+
+    /// Add a destination like "192.168.1.20:9000", adding it again changes its rate
+    pub fn add(
+        &self,
+        format: SinkFormat,
+        address: &str,
+        rate: Option<u32>,
+    ) -> io::Result<SinkInfo> {
+        if rate == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid rate 0, leave it out to send every sample",
+            ));
+        }
+        let address = resolve(address)?;
+
+        let mut sinks = self.sinks.lock().unwrap();
+        let info = match sinks.iter_mut().find(|sink| sink.matches(format, &address)) {
+            Some(sink) => {
+                sink.config.rate = rate;
+                sink.next_due = None;
+                sink.info()
+            }
+            None => {
+                if sinks.len() >= MAX_SINKS {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{} already has {} sinks, remove one first",
+                            self.id, MAX_SINKS
+                        ),
+                    ));
+                }
+                let sink = Sink::open(SinkConfig {
+                    format,
+                    address,
+                    rate,
+                })?;
+                let info = sink.info();
+                sinks.push(sink);
+                info
+            }
+        };
+        info!(
+            "{} {} sink to {}, rate {:?}",
+            self.id, format, address, rate
+        );
+        self.save(&sinks)?;
+        Ok(info)
+    }
+
+    pub fn remove(&self, format: SinkFormat, address: &str) -> io::Result<()> {
+        let address = resolve(address)?;
+        let mut sinks = self.sinks.lock().unwrap();
+        let len = sinks.len();
+        sinks.retain(|sink| !sink.matches(format, &address));
+        if sinks.len() == len {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no {} sink to {}", format, address),
+            ));
+        }
+        info!("{} {} sink to {} removed", self.id, format, address);
+        self.save(&sinks)
+    }
+
+    /// Send a published sample to every sink that is due, never blocks
+    pub fn send(&self, sample: &ImuDataProto) {
+        let mut sinks = self.sinks.lock().unwrap();
+        if sinks.is_empty() {
+            return;
+        }
+
+        let mut osc: Option<Vec<Vec<u8>>> = None;
+        let mut json: Option<Vec<u8>> = None;
+        for sink in sinks.iter_mut() {
+            if let Some(rate) = sink.config.rate
+                && !due_at_rate(&mut sink.next_due, rate, sample.timestamp)
+            {
+                continue;
+            }
+            // Encode once per format, only when some sink wants it
+            let result = match sink.config.format {
+                SinkFormat::Osc => osc
+                    .get_or_insert_with(|| osc_messages(&self.id, sample))
+                    .iter()
+                    .try_for_each(|message| sink.socket.send(message).map(|_| ())),
+                SinkFormat::Json => sink
+                    .socket
+                    .send(json.get_or_insert_with(|| json_sample(&self.id, sample)))
+                    .map(|_| ()),
+            };
+            match result {
+                Ok(_) => sink.sent += 1,
+                Err(e) => {
+                    // Nothing listening shows up as refused on a later send, keep going
+                    if sink.errors == 0 {
+                        warn!("{} send to {} failed: {}", self.id, sink.config.address, e);
+                    } else {
+                        debug!("{} send to {} failed: {}", self.id, sink.config.address, e);
+                    }
+                    sink.errors += 1;
+                    sink.last_error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    /// Start over with the next sample, timestamps may jump between runs
+    pub fn reset(&self) {
+        for sink in self.sinks.lock().unwrap().iter_mut() {
+            sink.next_due = None;
+        }
+    }
+
+    fn save(&self, sinks: &[Sink]) -> io::Result<()> {
+        let configs: Vec<&SinkConfig> = sinks.iter().map(|sink| &sink.config).collect();
+        storage::save(&format!("{}/sinks", self.id), &configs)
+    }
+}
+
+impl Sink {
+    fn open(config: SinkConfig) -> io::Result<Self> {
+        let local: SocketAddr = if config.address.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        // Broadcast addresses like 192.168.1.255 reach every machine on the subnet
+        if config.address.is_ipv4() {
+            socket.set_broadcast(true)?;
+        }
+        socket.connect(config.address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            config,
+            socket,
+            next_due: None,
+            sent: 0,
+            errors: 0,
+            last_error: None,
+        })
+    }
+
+    fn matches(&self, format: SinkFormat, address: &SocketAddr) -> bool {
+        self.config.format == format && self.config.address == *address
+    }
+
+    fn info(&self) -> SinkInfo {
+        SinkInfo {
+            config: self.config.clone(),
+            sent: self.sent,
+            errors: self.errors,
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+fn resolve(address: &str) -> io::Result<SocketAddr> {
+    let invalid_input = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "invalid address '{}', expected host:port like 192.168.1.20:9000",
+                address
+            ),
+        )
+    };
+    let address = address
+        .to_socket_addrs()
+        .map_err(|_| invalid_input())?
+        .next()
+        .ok_or_else(invalid_input)?;
+    if address.port() == 0 {
+        return Err(invalid_input());
+    }
+    Ok(address)
+}
+
+fn json_sample(id: &str, sample: &ImuDataProto) -> Vec<u8> {
+    serde_json::to_vec(&JsonSample {
+        id,
+        timestamp: sample.timestamp,
+        accel: &sample.accel,
+        gyro: &sample.gyro,
+        mag: &sample.mag,
+        temp: sample.temp,
+        quaternion: &sample.quaternion,
+        euler_angles: &sample.euler_angles,
+        gravity: &sample.gravity,
+        linear_accel: &sample.linear_accel,
+        heading: sample.heading,
+        world_gyro: &sample.world_gyro,
+    })
+    .unwrap_or_default()
+}
+
+/// One OSC message per field under /rebecca/<id>, quaternion as quat, float arguments in the same order as ImuDataProto
+fn osc_messages(id: &str, sample: &ImuDataProto) -> Vec<Vec<u8>> {
+    let mut messages = vec![
+        osc_message(id, "quat", &sample.quaternion),
+        osc_message(id, "euler_angles", &sample.euler_angles),
+        osc_message(id, "accel", &sample.accel),
+        osc_message(id, "gyro", &sample.gyro),
+        osc_message(id, "mag", &sample.mag),
+        osc_message(id, "temp", &[sample.temp]),
+        osc_message(id, "gravity", &sample.gravity),
+        osc_message(id, "linear_accel", &sample.linear_accel),
+        osc_message(id, "world_gyro", &sample.world_gyro),
+    ];
+    if let Some(heading) = sample.heading {
+        messages.push(osc_message(id, "heading", &[heading]));
+    }
+    messages
+}
+
+fn osc_message(id: &str, field: &str, values: &[f32]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(64);
+    osc_string(&mut message, &format!("/rebecca/{}/{}", id, field));
+    osc_string(&mut message, &format!(",{}", "f".repeat(values.len())));
+    for value in values {
+        message.extend_from_slice(&value.to_be_bytes());
+    }
+    message
+}
+
+// Null terminated, padded to 4 bytes
+fn osc_string(message: &mut Vec<u8>, s: &str) {
+    message.extend_from_slice(s.as_bytes());
+    message.push(0);
+    while !message.len().is_multiple_of(4) {
+        message.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn osc_message_is_padded_big_endian() {
+        let message = osc_message("imu0", "quat", &[1.0, 0.0, -0.5, 0.25]);
+        let mut expected = b"/rebecca/imu0/quat\0\0".to_vec(); // 18 bytes + null, padded to 20
+        expected.extend_from_slice(b",ffff\0\0\0"); // 5 bytes + null, padded to 8
+        expected.extend_from_slice(&[0x3f, 0x80, 0x00, 0x00]); // 1.0
+        expected.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // 0.0
+        expected.extend_from_slice(&[0xbf, 0x00, 0x00, 0x00]); // -0.5
+        expected.extend_from_slice(&[0x3e, 0x80, 0x00, 0x00]); // 0.25
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn osc_string_aligned_still_gets_a_null() {
+        let mut message = Vec::new();
+        osc_string(&mut message, "/abc");
+        assert_eq!(message, b"/abc\0\0\0\0");
+    }
+
+    #[test]
+    fn osc_messages_use_quat_address() {
+        let sample = ImuDataProto {
+            quaternion: vec![1.0, 0.0, 0.0, 0.0],
+            ..Default::default()
+        };
+        let messages = osc_messages("imu0", &sample);
+        assert!(messages[0].starts_with(b"/rebecca/imu0/quat\0"));
+        assert!(
+            messages
+                .iter()
+                .all(|message| message.len().is_multiple_of(4))
+        );
+    }
+}
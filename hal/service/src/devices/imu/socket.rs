@@ -1,20 +1,40 @@
-use crate::common::Emoji;
-use crate::devices::imu::{Imu, ImuData};
-use ahrs::{Ahrs, Madgwick};
-use log::{debug, error, warn};
-use nalgebra::Vector3;
+use crate::common::{Emoji, storage};
+use crate::devices::backlight::wake_backlights;
+use crate::devices::imu::{
+    Calibration, CalibrationKind, Calibrator, Fusion, HealthMonitor, Imu, ImuConfig, ImuConfigInfo,
+    ImuData, ImuHealth, ImuSessions, ImuStreams, ImuTopic, KinematicsEstimator, MotionDetector,
+    MotionEventKind, MotionEvents, MountMatrix, MountSource, OutputSinks, Recorder, SessionInfo,
+    SinkFormat, SinkInfo, WakeInfo, WakeMode, WakeOnMotion, WakeSettings, timestamp_now,
+};
+use crate::devices::screen::wake_screens;
+use log::{debug, error, info, warn};
+use nalgebra::{UnitQuaternion, Vector3};
 use prost::Message;
 use regex::Regex;
 use serde::Serialize;
 use std::f64;
 use std::io;
 use std::sync::{
-    Arc,
+    Arc, Mutex, RwLock,
     atomic::{AtomicBool, Ordering},
 };
 use std::time::Duration;
-use tokio::{sync::Notify, task, time};
-use zeromq::{Socket, SocketSend};
+use tokio::{
+    sync::{Notify, mpsc},
+    task, time,
+};
+use zeromq::{Socket, SocketSend, ZmqMessage};
+
+// Blocking buffer reads wake up this often to check for stop
+const BUFFER_READ_TIMEOUT: Duration = Duration::from_millis(100);
+// Health is checked, and a reopen considered, this often
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// Sessions past their keepalive are closed this often
+const SESSION_EXPIRE_INTERVAL: Duration = Duration::from_secs(1);
+// Low power polls sysfs this often without a wake-on-motion interrupt, and checks for leaving as often
+const LOW_POWER_INTERVAL: Duration = Duration::from_millis(100);
+// Low power is tried again after failing this long ago
+const LOW_POWER_RETRY: Duration = Duration::from_secs(5);
 
 // Protobuf message definition for IMU data
 #[derive(Clone, PartialEq, Message)]
@@ -33,6 +53,14 @@ pub struct ImuDataProto {
     pub quaternion: Vec<f32>,
     #[prost(float, repeated, tag = "7")]
     pub euler_angles: Vec<f32>,
+    #[prost(float, repeated, tag = "8")]
+    pub gravity: Vec<f32>,
+    #[prost(float, repeated, tag = "9")]
+    pub linear_accel: Vec<f32>,
+    #[prost(float, optional, tag = "10")]
+    pub heading: Option<f32>,
+    #[prost(float, repeated, tag = "11")]
+    pub world_gyro: Vec<f32>,
 }
 
 // Human-readable protobuf schema to be exposed via schema API
@@ -46,6 +74,10 @@ message ImuDataProto {
   float         temp   = 5;   // milli-degree Celsius
   repeated float quaternion = 6; // quaternion (4 floats)
   repeated float euler_angles = 7; // yaw, pitch, roll (radians)
+  repeated float gravity = 8;      // gx, gy, gz in device frame, same unit as accel
+  repeated float linear_accel = 9; // accel minus gravity, device frame
+  optional float heading = 10;     // tilt-compensated, radians clockwise from magnetic north, unset without mag
+  repeated float world_gyro = 11;  // angular velocity in world frame, z up (radians/s)
 }
 "#;
 
@@ -54,17 +86,41 @@ pub struct ImuSocket {
     imu: Arc<dyn Imu + Send + Sync>,
     imu_data_port: u16,
     imu_data_socket: Arc<tokio::sync::Mutex<zeromq::PubSocket>>,
+    imu_topic_port: u16,
+    imu_topic_socket: Arc<tokio::sync::Mutex<zeromq::PubSocket>>,
     is_running: Arc<AtomicBool>,
     update_task_handle: Arc<tokio::sync::Mutex<Option<task::JoinHandle<()>>>>,
     shutdown_notify: Arc<Notify>,
+    pub calibrator: Arc<Calibrator>,
+    pub fusion: Arc<Fusion>,
+    mount: Arc<RwLock<(MountMatrix, MountSource)>>,
+    pub events: Arc<MotionEvents>,
+    pub streams: Arc<ImuStreams>,
+    pub recorder: Arc<Recorder>,
+    pub health: Arc<HealthMonitor>,
+    pub sessions: ImuSessions,
+    pub sinks: Arc<OutputSinks>,
+    // Internal session keeping the device running while there are sinks
+    sinks_session: Mutex<Option<String>>,
+    // Held while the device is started or stopped for sessions, reopen or config
+    session_lock: tokio::sync::Mutex<()>,
+    pub wake: Arc<WakeOnMotion>,
+    // Held while in low power, the flag asks it to end
+    low_power_lock: tokio::sync::Mutex<()>,
+    low_power_leave: AtomicBool,
+    // Device stopped or wake settings changed, low power may be due
+    wake_notify: Notify,
 }
 
 #[derive(Serialize, Debug)]
 struct ImuSocketInfo {
     device_type: String,
     status: String,
-    sample_rate: u32,
+    sample_rate: f64,
+    buffered: bool,
+    sessions: usize,
     imu_data_port: u16,
+    imu_topic_port: u16,
     description: String,
 }
 
@@ -72,16 +128,75 @@ impl ImuSocket {
     pub async fn new(imu: Box<dyn Imu + Send + Sync>, id: String, host: &str) -> io::Result<Self> {
         debug!(target: &id, "creating imu socket");
 
-        // Create ZMQ PUB socket
-        let mut imu_data_socket = zeromq::PubSocket::new();
-        let ep = imu_data_socket
+        // Create ZMQ PUB sockets
+        let (imu_data_socket, imu_data_port) = Self::bind_pub_socket(host).await?;
+        debug!(target: &id, "imu data socket bound to: tcp://{}:{}", host, imu_data_port);
+        let (imu_topic_socket, imu_topic_port) = Self::bind_pub_socket(host).await?;
+        debug!(target: &id, "imu topic socket bound to: tcp://{}:{}", host, imu_topic_port);
+
+        // Persisted rate and scales, before anything reads the sample rate
+        if let Some(config) = storage::load::<ImuConfig>(&format!("{}/config", id))
+            && let Err(e) = imu.set_config(&config)
+        {
+            warn!(target: &id, "apply saved config {:?} failed: {}", config, e);
+        }
+
+        let calibrator = Arc::new(Calibrator::new(&id));
+        let fusion = Arc::new(Fusion::new(&id, imu.sample_rate()));
+        let recorder = Arc::new(Recorder::new(&id));
+        let health = Arc::new(HealthMonitor::new(&id));
+        let sessions = ImuSessions::new(&id);
+        let sinks = Arc::new(OutputSinks::new(&id));
+        let wake = Arc::new(WakeOnMotion::new(&id));
+
+        // Driver's mount matrix wins, config is for boards without one in device tree
+        let mount = match imu.mount_matrix() {
+            Some(mount_matrix) => (mount_matrix, MountSource::Iio),
+            None => match storage::load(&format!("{}/mount_matrix", id)) {
+                Some(mount_matrix) => (mount_matrix, MountSource::Config),
+                None => (imu.default_mount_matrix(), MountSource::Default),
+            },
+        };
+        debug!(target: &id, "mount matrix: {:?}", mount);
+
+        Ok(Self {
+            id,
+            imu: Arc::from(imu),
+            imu_data_port,
+            imu_data_socket: Arc::new(tokio::sync::Mutex::new(imu_data_socket)),
+            imu_topic_port,
+            imu_topic_socket: Arc::new(tokio::sync::Mutex::new(imu_topic_socket)),
+            is_running: Arc::new(AtomicBool::new(false)),
+            update_task_handle: Arc::new(tokio::sync::Mutex::new(None)),
+            shutdown_notify: Arc::new(Notify::new()),
+            calibrator,
+            fusion,
+            mount: Arc::new(RwLock::new(mount)),
+            events: Arc::new(MotionEvents::new()),
+            streams: Arc::new(ImuStreams::new()),
+            recorder,
+            health,
+            sessions,
+            sinks,
+            sinks_session: Mutex::new(None),
+            session_lock: tokio::sync::Mutex::new(()),
+            wake,
+            low_power_lock: tokio::sync::Mutex::new(()),
+            low_power_leave: AtomicBool::new(false),
+            wake_notify: Notify::new(),
+        })
+    }
+
+    async fn bind_pub_socket(host: &str) -> io::Result<(zeromq::PubSocket, u16)> {
+        let mut socket = zeromq::PubSocket::new();
+        let ep = socket
             .bind(format!("tcp://{}:0", host).as_str())
             .await
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("zmq bind failed: {}", e)))?
+            .map_err(|e| io::Error::other(format!("zmq bind failed: {}", e)))?
             .to_string();
 
         // Extract port from endpoint
-        let imu_data_port = Regex::new(r":(\d+)$")
+        let port = Regex::new(r":(\d+)$")
             .ok()
             .and_then(|re| re.captures(&ep))
             .and_then(|caps| caps.get(1))
@@ -90,21 +205,10 @@ impl ImuSocket {
                 error!("parse port from '{}' failed", ep);
                 0
             });
-
-        debug!(target: &id, "imu data socket bound to: tcp://{}:{}", host, imu_data_port);
-
-        Ok(Self {
-            id,
-            imu: Arc::from(imu),
-            imu_data_port,
-            imu_data_socket: Arc::new(tokio::sync::Mutex::new(imu_data_socket)),
-            is_running: Arc::new(AtomicBool::new(false)),
-            update_task_handle: Arc::new(tokio::sync::Mutex::new(None)),
-            shutdown_notify: Arc::new(Notify::new()),
-        })
+        Ok((socket, port))
     }
 
-    pub async fn start(&self) -> io::Result<()> {
+    async fn start(&self) -> io::Result<()> {
         if self.is_running.load(Ordering::Acquire) {
             warn!("imu socket {} is already running", self.id);
             return Ok(());
@@ -144,9 +248,9 @@ impl ImuSocket {
         // Wait for update task to finish
         let mut task_handle = self.update_task_handle.lock().await;
         if let Some(handle) = task_handle.take() {
-            handle.await.map_err(|e| {
-                io::Error::new(io::ErrorKind::Other, format!("task join failed: {}", e))
-            })?;
+            handle
+                .await
+                .map_err(|e| io::Error::other(format!("task join failed: {}", e)))?;
         }
 
         // Stop the IMU device
@@ -162,77 +266,141 @@ impl ImuSocket {
         let is_running = self.is_running.clone();
         let shutdown_notify = self.shutdown_notify.clone();
         let id = self.id.clone();
+        let calibrator = self.calibrator.clone();
+        let fusion = self.fusion.clone();
+        let mount = self.mount.clone();
+        let topic_socket = self.imu_topic_socket.clone();
+        let events = self.events.clone();
+        let streams = self.streams.clone();
+        let recorder = self.recorder.clone();
+        let health = self.health.clone();
+        let sinks = self.sinks.clone();
+        let wake = self.wake.clone();
 
         task::spawn(async move {
             let sample_rate = imu.sample_rate();
-            let interval_ms = 1000 / sample_rate as u64;
-            let interval_s = 1.0 / sample_rate as f64;
-            let mut interval = time::interval(Duration::from_millis(interval_ms));
-
-            // Orientation estimation using Madgwick filter
-            let mut ahrs = Madgwick::new(interval_s, 0.1);
-            let mut update_orientation = |imu_data: &ImuData| -> ([f32; 4], [f32; 3]) {
-                // radians/s
-                let gyroscope = Vector3::new(
-                    imu_data.gyro[2] as f64,
-                    imu_data.gyro[1] as f64,
-                    imu_data.gyro[0] as f64,
-                );
-                let accelerometer = Vector3::new(
-                    imu_data.accel[2] as f64,
-                    imu_data.accel[1] as f64,
-                    imu_data.accel[0] as f64,
-                );
 
-                let quat = ahrs.update_imu(&gyroscope, &accelerometer).unwrap();
-                let (roll, pitch, yaw) = quat.euler_angles();
+            // Samples come from a hardware buffer reader or a polling timer
+            let (sample_tx, mut sample_rx) =
+                mpsc::channel::<ImuData>((sample_rate.ceil() as usize).max(1));
+            let buffered = imu.is_buffered();
+            let sampler = if buffered {
+                Self::spawn_buffer_reader(
+                    imu.clone(),
+                    is_running.clone(),
+                    health.clone(),
+                    sample_tx,
+                    &id,
+                )
+            } else {
+                Self::spawn_poller(imu.clone(), sample_tx)
+            };
+
+            // Orientation estimation, algorithm is set by fusion settings
+            fusion.reset(sample_rate);
+            let update_orientation = |imu_data: &ImuData,
+                                      dt: Option<f64>|
+             -> ([f32; 4], [f32; 3], UnitQuaternion<f64>) {
+                // Device frame after mount matrix, radians/s
+                let gyroscope = Vector3::from(imu_data.gyro.map(|v| v as f64));
+                let accelerometer = Vector3::from(imu_data.accel.map(|v| v as f64));
+                let magnetometer = Vector3::from(imu_data.mag.map(|v| v as f64));
+
+                // Published orientation is relative to tare, kinematics need the absolute one
+                let quat = fusion.update(gyroscope, accelerometer, magnetometer, dt);
+                let relative = fusion.relative(&quat);
+                let (roll, pitch, yaw) = relative.euler_angles();
 
                 let quaternion = [
-                    quat.coords[0] as f32,
-                    quat.coords[1] as f32,
-                    quat.coords[2] as f32,
-                    quat.coords[3] as f32,
+                    relative.coords[0] as f32,
+                    relative.coords[1] as f32,
+                    relative.coords[2] as f32,
+                    relative.coords[3] as f32,
                 ];
                 let euler_angles = [yaw as f32, pitch as f32, roll as f32];
 
-                (quaternion, euler_angles)
+                (quaternion, euler_angles, quat)
             };
-
-            debug!("imu update task started for: {} in {}Hz", id, sample_rate);
-
+            let mut kinematics_estimator = KinematicsEstimator::new();
+
+            let mut motion_detector = MotionDetector::new();
+            streams.reset();
+            // Wake-on-motion keeps watching streamed samples, from a new rest reference
+            wake.set_low_power(None);
+            sinks.reset();
+
+            // Health is tracked on samples as read, faults are checked on a timer
+            health.reset();
+            let full_scale = imu.full_scale();
+            let mut health_interval = time::interval(HEALTH_CHECK_INTERVAL);
+
+            debug!(
+                "imu update task started for: {} in {}Hz, {}",
+                id,
+                sample_rate,
+                if buffered { "buffered" } else { "polled" }
+            );
+
+            let mut last_timestamp: Option<u64> = None;
             loop {
                 tokio::select! {
                     _ = shutdown_notify.notified() => {
                         debug!("imu update task shutdown for: {}", id);
                         break;
                     }
-                    _ = interval.tick() => {
+                    _ = health_interval.tick() => {
+                        let faults = health.check(&imu.read_errors(), sample_rate);
+                        if faults.is_empty() {
+                            continue;
+                        }
+                        let mut topic_socket = topic_socket.lock().await;
+                        for kind in faults {
+                            let event = events.push(timestamp_now(), kind);
+                            let payload = serde_json::to_vec(&event).unwrap_or_default();
+                            Self::publish(&mut topic_socket, ImuTopic::Events.as_str(), &payload)
+                                .await;
+                        }
+                    }
+                    imu_data = sample_rx.recv() => {
+                        let Some(mut imu_data) = imu_data else {
+                            break;
+                        };
                         if !is_running.load(Ordering::Acquire) {
                             break;
                         }
 
-                        // Read IMU data
-                        let mut imu_data = imu.imu_data();
+                        // Calibrate in sensor frame, then rotate to device frame
+                        health.update(&imu_data, full_scale);
+                        calibrator.apply(&mut imu_data);
+                        mount.read().unwrap().0.apply(&mut imu_data);
+
+                        // Skip gaps like a stalled device, the filter would jump
+                        let dt = last_timestamp
+                            .map(|last_timestamp| imu_data.timestamp.saturating_sub(last_timestamp) as f64 / 1e6)
+                            .filter(|dt| *dt > 0.0 && *dt < 1.0);
+                        last_timestamp = Some(imu_data.timestamp);
 
                         // Update orientation
-                        let (quaternion, euler_angles) = update_orientation(&mut imu_data);
+                        let (quaternion, euler_angles, quat) = update_orientation(&imu_data, dt);
                         imu_data.quaternion = quaternion;
                         imu_data.euler_angles = euler_angles;
+                        let kinematics = kinematics_estimator.update(&quat, &imu_data);
 
                         debug!("{} get imu data: {:#?}", id, imu_data);
 
                         // Convert to protobuf message
                         let proto_msg = ImuDataProto {
-                            timestamp: std::time::SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .unwrap_or_default()
-                                .as_micros() as u64,
+                            timestamp: imu_data.timestamp,
                             accel: imu_data.accel.to_vec(),
                             gyro: imu_data.gyro.to_vec(),
                             mag: imu_data.mag.to_vec(),
                             temp: imu_data.temp,
                             quaternion: imu_data.quaternion.to_vec(),
                             euler_angles: imu_data.euler_angles.to_vec(),
+                            gravity: kinematics.gravity.to_vec(),
+                            linear_accel: kinematics.linear_accel.to_vec(),
+                            heading: kinematics.heading,
+                            world_gyro: kinematics.world_gyro.to_vec(),
                         };
 
                         // Serialize to bytes
@@ -247,23 +415,526 @@ impl ImuSocket {
                         if let Err(e) = socket_guard.send(buf.into()).await {
                             error!("failed to send imu data: {}", e);
                         }
+                        drop(socket_guard);
+                        recorder.write(&proto_msg);
+                        sinks.send(&proto_msg);
+
+                        // Topic socket: full rate topics, then decimated streams that are due
+                        let raw = ImuDataProto {
+                            timestamp: proto_msg.timestamp,
+                            accel: proto_msg.accel.clone(),
+                            gyro: proto_msg.gyro.clone(),
+                            mag: proto_msg.mag.clone(),
+                            temp: proto_msg.temp,
+                            ..Default::default()
+                        }
+                        .encode_to_vec();
+                        let orientation = ImuDataProto {
+                            timestamp: proto_msg.timestamp,
+                            quaternion: proto_msg.quaternion,
+                            euler_angles: proto_msg.euler_angles,
+                            ..Default::default()
+                        }
+                        .encode_to_vec();
+                        let mut topic_socket = topic_socket.lock().await;
+                        Self::publish(&mut topic_socket, ImuTopic::Raw.as_str(), &raw).await;
+                        Self::publish(
+                            &mut topic_socket,
+                            ImuTopic::Orientation.as_str(),
+                            &orientation,
+                        )
+                        .await;
+                        for (topic, name) in streams.due(imu_data.timestamp) {
+                            let payload = match topic {
+                                ImuTopic::Raw => &raw,
+                                _ => &orientation,
+                            };
+                            Self::publish(&mut topic_socket, &name, payload).await;
+                        }
+
+                        // Motion events as JSON like the HTTP API
+                        for kind in motion_detector.update(&imu_data) {
+                            let event = events.push(imu_data.timestamp, kind);
+                            debug!("{} motion event: {:?}", id, event);
+                            let payload = serde_json::to_vec(&event).unwrap_or_default();
+                            Self::publish(&mut topic_socket, ImuTopic::Events.as_str(), &payload)
+                                .await;
+                        }
+                        if wake.settings().enabled && wake.update(&imu_data) {
+                            let timestamp = imu_data.timestamp;
+                            Self::publish_motion(&wake, &events, &mut topic_socket, timestamp).await;
+                        }
                     }
                 }
             }
 
+            // Sampler stops once the receiver is gone, device is deinit after that
+            drop(sample_rx);
+            if let Err(e) = sampler.await {
+                error!("await imu sampler error: {}", e);
+            }
+
             debug!("imu update task finished for: {}", id);
         })
     }
 
+    /// Send a [topic, payload] message
+    async fn publish(socket: &mut zeromq::PubSocket, topic: &str, payload: &[u8]) {
+        let mut message = ZmqMessage::from(topic);
+        message.push_back(payload.to_vec().into());
+        if let Err(e) = socket.send(message).await {
+            error!("failed to send imu {}: {}", topic, e);
+        }
+    }
+
+    /// Poll `imu_data` at the nominal sample rate
+    fn spawn_poller(
+        imu: Arc<dyn Imu + Send + Sync>,
+        sample_tx: mpsc::Sender<ImuData>,
+    ) -> task::JoinHandle<()> {
+        task::spawn(async move {
+            let period = Duration::from_secs_f64(1.0 / imu.sample_rate().max(f64::EPSILON));
+            let mut interval = time::interval(period);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if sample_tx.send(imu.imu_data()).await.is_err() {
+                            break;
+                        }
+                    }
+                    _ = sample_tx.closed() => break,
+                }
+            }
+        })
+    }
+
+    /// Read hardware buffer in a blocking thread, every sample is forwarded as is
+    fn spawn_buffer_reader(
+        imu: Arc<dyn Imu + Send + Sync>,
+        is_running: Arc<AtomicBool>,
+        health: Arc<HealthMonitor>,
+        sample_tx: mpsc::Sender<ImuData>,
+        id: &str,
+    ) -> task::JoinHandle<()> {
+        let id = id.to_string();
+        task::spawn_blocking(move || {
+            while is_running.load(Ordering::Acquire) && !sample_tx.is_closed() {
+                match imu.read_buffer(BUFFER_READ_TIMEOUT) {
+                    Ok(samples) => {
+                        for sample in samples {
+                            if sample_tx.blocking_send(sample).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!("{} read imu buffer failed: {}", id, e);
+                        health.record_buffer_error();
+                        std::thread::sleep(BUFFER_READ_TIMEOUT);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Open a session, the device starts if it isn't running, `ttl` None never expires
+    pub async fn open_session(
+        &self,
+        client: &str,
+        ttl: Option<Duration>,
+    ) -> io::Result<SessionInfo> {
+        let _guard = self.session_lock.lock().await;
+        if !self.is_running() {
+            self.leave_low_power().await;
+            self.start().await?;
+        }
+        Ok(self.sessions.open(client, ttl))
+    }
+
+    /// Add a decimated stream held by an open session, it ends when the session does
+    pub fn add_stream(&self, topic: ImuTopic, rate: u32, session: &str) -> io::Result<String> {
+        if !self.sessions.contains(session) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "{} has no session {}, it may have expired",
+                    self.id, session
+                ),
+            ));
+        }
+        self.streams.add(topic, rate, self.sample_rate(), session)
+    }
+
+    pub fn keepalive_session(&self, session: &str) -> io::Result<SessionInfo> {
+        self.sessions.keepalive(session)
+    }
+
+    /// Close a session, the device stops with the last one
+    pub async fn close_session(&self, session: &str) -> io::Result<SessionInfo> {
+        let _guard = self.session_lock.lock().await;
+        let info = self.sessions.close(session)?;
+        self.end_orphan_streams();
+        self.stop_when_unused().await?;
+        Ok(info)
+    }
+
+    /// Close every client session, the device stops unless sinks or calibration still hold it
+    pub async fn close_all_sessions(&self) -> io::Result<usize> {
+        let _guard = self.session_lock.lock().await;
+        let count = self.sessions.close_all();
+        self.end_orphan_streams();
+        self.stop_when_unused().await?;
+        Ok(count)
+    }
+
+    /// Close sessions past their keepalive, like clients that crashed, runs until dropped
+    pub async fn expire_sessions(&self) {
+        let mut interval = time::interval(SESSION_EXPIRE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let expired = self.sessions.expire();
+            // Also catches streams added while their session was expiring
+            self.end_orphan_streams();
+            if expired == 0 {
+                continue;
+            }
+
+            let _guard = self.session_lock.lock().await;
+            if let Err(e) = self.stop_when_unused().await {
+                error!("{} stop after sessions expired failed: {}", self.id, e);
+            }
+        }
+    }
+
+    fn end_orphan_streams(&self) {
+        self.streams
+            .retain_sessions(|session| self.sessions.contains(session));
+    }
+
+    /// Call with `session_lock` held
+    async fn stop_when_unused(&self) -> io::Result<()> {
+        if self.sessions.len() == 0 && self.is_running() {
+            debug!("{} has no sessions left, stopping", self.id);
+            self.stop().await?;
+            self.wake_notify.notify_one();
+        }
+        Ok(())
+    }
+
+    pub fn wake_info(&self) -> WakeInfo {
+        self.wake
+            .info(self.is_running(), self.imu.has_wake_on_motion())
+    }
+
+    /// Change wake-on-motion, low power starts over with the new settings
+    pub fn set_wake(&self, settings: WakeSettings) -> io::Result<WakeInfo> {
+        self.wake.set_settings(settings)?;
+        self.low_power_leave.store(true, Ordering::Release);
+        self.wake_notify.notify_one();
+        Ok(self.wake_info())
+    }
+
+    /// Low power while wake-on-motion is enabled and no session runs the device, runs until dropped
+    pub async fn watch_motion(&self) {
+        loop {
+            match self.low_power().await {
+                Ok(true) => {}
+                Ok(false) => self.wake_notify.notified().await,
+                Err(e) => {
+                    error!("{} low power failed: {}", self.id, e);
+                    tokio::select! {
+                        _ = time::sleep(LOW_POWER_RETRY) => {}
+                        _ = self.wake_notify.notified() => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Disarm the interrupt once `watch_motion` is dropped, like on shutdown
+    pub fn release_low_power(&self) {
+        if self.wake.low_power_mode() == Some(WakeMode::Hardware)
+            && let Err(e) = self.imu.disable_wake_on_motion()
+        {
+            warn!("{} disable wake-on-motion failed: {}", self.id, e);
+        }
+    }
+
+    /// Watch for motion until a session starts the device or settings change, false when not due
+    async fn low_power(&self) -> io::Result<bool> {
+        let _low_power = {
+            let _guard = self.session_lock.lock().await;
+            if !self.wake.settings().enabled || self.is_running() {
+                return Ok(false);
+            }
+            self.low_power_leave.store(false, Ordering::Release);
+            self.low_power_lock.lock().await
+        };
+
+        let threshold = self.wake.settings().threshold;
+        let result = match self.imu.enable_wake_on_motion(threshold) {
+            Ok(threshold) => {
+                info!("{} low power, wake-on-motion at {} g", self.id, threshold);
+                self.wake.set_low_power(Some(WakeMode::Hardware));
+                let result = self.watch_interrupt().await;
+                if let Err(e) = self.imu.disable_wake_on_motion() {
+                    warn!("{} disable wake-on-motion failed: {}", self.id, e);
+                }
+                result
+            }
+            Err(e) => {
+                if e.kind() != io::ErrorKind::Unsupported {
+                    warn!(
+                        "{} enable wake-on-motion failed: {}, poll instead",
+                        self.id, e
+                    );
+                }
+                info!(
+                    "{} low power, polling at {}Hz",
+                    self.id,
+                    1000 / LOW_POWER_INTERVAL.as_millis()
+                );
+                self.wake.set_low_power(Some(WakeMode::Software));
+                self.watch_polled().await;
+                Ok(())
+            }
+        };
+
+        self.wake.set_low_power(None);
+        debug!("{} leave low power", self.id);
+        result.map(|_| true)
+    }
+
+    async fn watch_interrupt(&self) -> io::Result<()> {
+        while !self.low_power_leave.load(Ordering::Acquire) {
+            let imu = self.imu.clone();
+            let fired = task::spawn_blocking(move || imu.wait_wake_on_motion(LOW_POWER_INTERVAL))
+                .await
+                .map_err(|e| io::Error::other(format!("task join failed: {}", e)))??;
+            let timestamp = timestamp_now();
+            if fired && self.wake.interrupt(timestamp) {
+                let mut topic_socket = self.imu_topic_socket.lock().await;
+                Self::publish_motion(&self.wake, &self.events, &mut topic_socket, timestamp).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn watch_polled(&self) {
+        let mut interval = time::interval(LOW_POWER_INTERVAL);
+        while !self.low_power_leave.load(Ordering::Acquire) {
+            interval.tick().await;
+            let imu_data = self.imu.imu_data();
+            if self.wake.update(&imu_data) {
+                let mut topic_socket = self.imu_topic_socket.lock().await;
+                Self::publish_motion(
+                    &self.wake,
+                    &self.events,
+                    &mut topic_socket,
+                    imu_data.timestamp,
+                )
+                .await;
+            }
+        }
+    }
+
+    /// Call with `session_lock` held, returns once out of low power
+    async fn leave_low_power(&self) {
+        self.low_power_leave.store(true, Ordering::Release);
+        drop(self.low_power_lock.lock().await);
+    }
+
+    async fn publish_motion(
+        wake: &WakeOnMotion,
+        events: &MotionEvents,
+        topic_socket: &mut zeromq::PubSocket,
+        timestamp: u64,
+    ) {
+        let event = events.push(timestamp, MotionEventKind::Motion);
+        let payload = serde_json::to_vec(&event).unwrap_or_default();
+        Self::publish(topic_socket, ImuTopic::Events.as_str(), &payload).await;
+        if wake.settings().wake_screens {
+            wake_screens("motion").await;
+            wake_backlights("motion").await;
+        }
+    }
+
+    /// Add a UDP sink, the device keeps running while there are sinks
+    pub async fn add_sink(
+        &self,
+        format: SinkFormat,
+        address: &str,
+        rate: Option<u32>,
+    ) -> io::Result<SinkInfo> {
+        let info = self.sinks.add(format, address, rate)?;
+        self.hold_sinks_session().await?;
+        Ok(info)
+    }
+
+    pub async fn remove_sink(&self, format: SinkFormat, address: &str) -> io::Result<()> {
+        self.sinks.remove(format, address)?;
+        if self.sinks.is_empty() {
+            let session = self.sinks_session.lock().unwrap().take();
+            // Gone already if all sessions were closed
+            if let Some(session) = session
+                && let Err(e) = self.close_session(&session).await
+                && e.kind() != io::ErrorKind::NotFound
+            {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Open the sinks session if there are sinks and it isn't open, like after a restart
+    pub async fn hold_sinks_session(&self) -> io::Result<()> {
+        if self.sinks.is_empty() {
+            return Ok(());
+        }
+        let session = self.sinks_session.lock().unwrap().clone();
+        if session.is_some_and(|session| self.sessions.keepalive(&session).is_ok()) {
+            return Ok(());
+        }
+        let session = self.open_session("sinks", None).await?;
+        *self.sinks_session.lock().unwrap() = Some(session.session);
+        Ok(())
+    }
+
+    /// Run a calibration capture in its own session
+    pub async fn calibrate(
+        &self,
+        kind: CalibrationKind,
+        duration: Duration,
+    ) -> io::Result<Calibration> {
+        let session = self.open_session("calibration", None).await?;
+
+        let result = match kind {
+            CalibrationKind::Still => self.calibrator.calibrate_still(duration).await,
+            CalibrationKind::Mag => self.calibrator.calibrate_mag(duration).await,
+        };
+
+        // The capture result matters more than closing its session
+        if let Err(e) = self.close_session(&session.session).await {
+            error!("{} close calibration session failed: {}", self.id, e);
+        }
+        result
+    }
+
+    /// Current orientation becomes forward, the stream keeps running
+    pub fn tare(&self) -> io::Result<()> {
+        if !self.is_running() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not running, no orientation to tare", self.id),
+            ));
+        }
+        self.fusion.tare();
+        Ok(())
+    }
+
+    /// Reinitialise the filter from identity and clear tare
+    pub fn reset_orientation(&self) {
+        self.fusion.clear_tare();
+        self.fusion.reset(self.imu.sample_rate());
+        debug!("{} orientation reset", self.id);
+    }
+
+    pub fn health(&self) -> ImuHealth {
+        self.health.health(self.imu.sample_rate())
+    }
+
+    /// Reopen the device when it stays unhealthy and auto reopen is on, runs until dropped
+    pub async fn watch_health(&self) {
+        let mut interval = time::interval(HEALTH_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if !self.is_running() || !self.health.reopen_due() {
+                continue;
+            }
+
+            let _guard = self.session_lock.lock().await;
+            if !self.is_running() {
+                continue;
+            }
+            warn!("{} unhealthy, reopen device", self.id);
+            self.health.reopened();
+            if let Err(e) = self.stop().await {
+                error!("{} stop for reopen failed: {}", self.id, e);
+            }
+            if let Err(e) = self.start().await {
+                error!("{} start for reopen failed: {}", self.id, e);
+            }
+        }
+    }
+
+    pub fn config(&self) -> ImuConfigInfo {
+        self.imu.config()
+    }
+
+    /// Apply and persist sample rate and scales, a running update task is restarted
+    pub async fn set_config(&self, config: ImuConfig) -> io::Result<ImuConfigInfo> {
+        let guard = self.session_lock.lock().await;
+        let was_running = self.is_running();
+        if was_running {
+            self.stop().await?;
+        }
+
+        let result = self.imu.set_config(&config);
+
+        if was_running {
+            self.start().await?;
+        }
+        drop(guard);
+        result?;
+
+        // Only the given fields change in the saved config
+        let key = format!("{}/config", self.id);
+        let mut saved: ImuConfig = storage::load(&key).unwrap_or_default();
+        saved.sample_rate = config.sample_rate.or(saved.sample_rate);
+        saved.accel_scale = config.accel_scale.or(saved.accel_scale);
+        saved.gyro_scale = config.gyro_scale.or(saved.gyro_scale);
+        storage::save(&key, &saved)?;
+
+        let info = self.imu.config();
+        debug!("{} imu config: {:?}", self.id, info);
+        Ok(info)
+    }
+
+    pub fn mount(&self) -> (MountMatrix, MountSource) {
+        *self.mount.read().unwrap()
+    }
+
+    /// Set and persist mount matrix, not allowed when driver reports one
+    pub fn set_mount_matrix(&self, mount_matrix: MountMatrix) -> io::Result<()> {
+        let mut mount = self.mount.write().unwrap();
+        if mount.1 == MountSource::Iio {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} mount matrix comes from iio mount_matrix, change it in device tree",
+                    self.id
+                ),
+            ));
+        }
+
+        *mount = (mount_matrix, MountSource::Config);
+        storage::save(&format!("{}/mount_matrix", self.id), &mount_matrix)
+    }
+
     pub fn get_device_info(&self) -> String {
         let imu_socket_info = ImuSocketInfo {
             device_type: self.imu.name(),
             status: if self.is_running() { "running" } else { "idle" }.to_string(),
             sample_rate: self.imu.sample_rate(),
+            buffered: self.imu.is_buffered(),
+            sessions: self.sessions.len(),
             imu_data_port: self.imu_data_port,
+            imu_topic_port: self.imu_topic_port,
             description: format!(
-                "{} Subscribe to IMU data from <imu_data_port> using a ZMQ SUB socket. The data is published in Protobuf format, and its schema is available at /{}/schema.",
+                "{} Subscribe to IMU data from <imu_data_port> using a ZMQ SUB socket. The data is published in Protobuf format, and its schema is available at /{}/schema. <imu_topic_port> publishes [topic, payload] messages: raw and orientation (Protobuf, full rate), events (JSON like /{}/events), and \"<rate>hz/raw\" or \"<rate>hz/orientation\" streams added at /{}/streams/add.",
                 Emoji::SUBSCRIBE,
+                self.id,
+                self.id,
                 self.id
             ),
         };
@@ -275,6 +946,10 @@ impl ImuSocket {
         IMU_DATA_PROTO_SCHEMA.to_string()
     }
 
+    pub fn sample_rate(&self) -> f64 {
+        self.imu.sample_rate()
+    }
+
     pub fn is_running(&self) -> bool {
         self.is_running.load(Ordering::Acquire)
     }
@@ -0,0 +1,274 @@
+use log::info;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Topics on the topic socket, each message is [topic, payload]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImuTopic {
+    Raw,         // ImuDataProto with sensor values only
+    Orientation, // ImuDataProto with timestamp, quaternion and euler angles only
+    Events,      // motion events as JSON
+}
+
+impl ImuTopic {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImuTopic::Raw => "raw",
+            ImuTopic::Orientation => "orientation",
+            ImuTopic::Events => "events",
+        }
+    }
+}
+
+impl fmt::Display for ImuTopic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ImuTopic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(ImuTopic::Raw),
+            "orientation" => Ok(ImuTopic::Orientation),
+            "events" => Ok(ImuTopic::Events),
+            _ => Err(format!(
+                "invalid topic '{}', expected raw, orientation or events",
+                s
+            )),
+        }
+    }
+}
+
+/// A topic republished at a lower rate
+#[derive(Serialize, Debug, Clone)]
+pub struct DecimatedStream {
+    pub topic: ImuTopic,
+    pub rate: u32,
+    pub name: String, // "{rate}hz/{topic}", doesn't prefix-match the full rate topic
+    pub sessions: BTreeSet<String>, // sessions that added it, it ends with the last one
+    #[serde(skip)]
+    next_due: Option<u64>,
+}
+
+/// Decimated streams of an IMU
+/// 按订阅者需要的频率降采样发布 raw / orientation，用时间戳决定哪些采样发出去
+/// 每个流记着添加它的会话，会话移除、关闭或过期后不再持有，没有会话持有的流就删掉
+pub struct ImuStreams {
+    streams: Mutex<Vec<DecimatedStream>>,
+}
+
+impl ImuStreams {
+    pub fn new() -> Self {
+        Self {
+            streams: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn list(&self) -> Vec<DecimatedStream> {
+        self.streams.lock().unwrap().clone()
+    }
+
+    /// Add a stream below `sample_rate` held by `session`, returns its topic name,
+    /// adding it again from the same or another session is fine
+    pub fn add(
+        &self,
+        topic: ImuTopic,
+        rate: u32,
+        sample_rate: f64,
+        session: &str,
+    ) -> io::Result<String> {
+        if topic == ImuTopic::Events {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "events are not sampled, subscribe to the events topic",
+            ));
+        }
+        if rate == 0 || rate as f64 >= sample_rate {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "invalid rate {}, must be 1 to {} (below sample rate), subscribe to {} for full rate",
+                    rate,
+                    (sample_rate.ceil() as u32).saturating_sub(1),
+                    topic
+                ),
+            ));
+        }
+
+        let name = stream_name(topic, rate);
+        let mut streams = self.streams.lock().unwrap();
+        match streams.iter_mut().find(|stream| stream.name == name) {
+            Some(stream) => {
+                stream.sessions.insert(session.to_string());
+            }
+            None => streams.push(DecimatedStream {
+                topic,
+                rate,
+                name: name.clone(),
+                sessions: BTreeSet::from([session.to_string()]),
+                next_due: None,
+            }),
+        }
+        Ok(name)
+    }
+
+    /// Release `session`'s hold on a stream, the stream ends when no session holds it
+    pub fn remove(&self, topic: ImuTopic, rate: u32, session: &str) -> io::Result<()> {
+        let name = stream_name(topic, rate);
+        let mut streams = self.streams.lock().unwrap();
+        let Some(index) = streams.iter().position(|stream| stream.name == name) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no stream {}", name),
+            ));
+        };
+        if !streams[index].sessions.remove(session) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("stream {} isn't held by session {}", name, session),
+            ));
+        }
+        if streams[index].sessions.is_empty() {
+            streams.remove(index);
+        }
+        Ok(())
+    }
+
+    /// Drop holds of sessions that are gone and the streams left without one, returns how many streams ended
+    pub fn retain_sessions(&self, alive: impl Fn(&str) -> bool) -> usize {
+        let mut streams = self.streams.lock().unwrap();
+        let count = streams.len();
+        streams.retain_mut(|stream| {
+            stream.sessions.retain(|session| alive(session));
+            if stream.sessions.is_empty() {
+                info!("stream {} ended with its last session", stream.name);
+            }
+            !stream.sessions.is_empty()
+        });
+        count - streams.len()
+    }
+
+    /// Streams a sample at `timestamp` (microseconds) goes out on, as (topic, name)
+    pub fn due(&self, timestamp: u64) -> Vec<(ImuTopic, String)> {
+        let mut streams = self.streams.lock().unwrap();
+        streams
+            .iter_mut()
+            .filter_map(|stream| {
+                stream
+                    .due(timestamp)
+                    .then(|| (stream.topic, stream.name.clone()))
+            })
+            .collect()
+    }
+
+    /// Start over with the next sample, timestamps may jump between runs
+    pub fn reset(&self) {
+        for stream in self.streams.lock().unwrap().iter_mut() {
+            stream.next_due = None;
+        }
+    }
+}
+
+impl DecimatedStream {
+    fn due(&mut self, timestamp: u64) -> bool {
+        due_at_rate(&mut self.next_due, self.rate, timestamp)
+    }
+}
+
+/// Whether a sample at `timestamp` (microseconds) goes out at `rate`, on a grid kept in `next_due`
+pub(crate) fn due_at_rate(next_due: &mut Option<u64>, rate: u32, timestamp: u64) -> bool {
+    let period = 1_000_000 / rate as u64;
+    match *next_due {
+        Some(due) if timestamp < due => false,
+        // Keep the grid so the average rate holds, resync when far behind
+        Some(due) if timestamp - due < period => {
+            *next_due = Some(due + period);
+            true
+        }
+        _ => {
+            *next_due = Some(timestamp + period);
+            true
+        }
+    }
+}
+
+fn stream_name(topic: ImuTopic, rate: u32) -> String {
+    format!("{}hz/{}", rate, topic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_lives_while_a_session_holds_it() {
+        let streams = ImuStreams::new();
+        let name = streams.add(ImuTopic::Orientation, 10, 100.0, "a").unwrap();
+        assert_eq!(name, "10hz/orientation");
+        assert_eq!(
+            streams.add(ImuTopic::Orientation, 10, 100.0, "b").unwrap(),
+            name
+        );
+        assert_eq!(streams.list().len(), 1);
+
+        // Only holders can remove, and only their own hold
+        let err = streams.remove(ImuTopic::Orientation, 10, "c").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        streams.remove(ImuTopic::Orientation, 10, "a").unwrap();
+        assert_eq!(streams.list().len(), 1);
+        streams.remove(ImuTopic::Orientation, 10, "b").unwrap();
+        assert!(streams.list().is_empty());
+        let err = streams.remove(ImuTopic::Orientation, 10, "b").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn streams_end_with_their_sessions() {
+        let streams = ImuStreams::new();
+        streams.add(ImuTopic::Orientation, 10, 100.0, "a").unwrap();
+        streams.add(ImuTopic::Raw, 5, 100.0, "a").unwrap();
+        streams.add(ImuTopic::Raw, 5, 100.0, "b").unwrap();
+
+        assert_eq!(streams.retain_sessions(|session| session == "b"), 1);
+        let list = streams.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].name, "5hz/raw");
+        assert_eq!(list[0].sessions, BTreeSet::from(["b".to_string()]));
+
+        assert_eq!(streams.retain_sessions(|_| false), 1);
+        assert!(streams.list().is_empty());
+    }
+
+    #[test]
+    fn add_rejects_events_and_full_rate() {
+        let streams = ImuStreams::new();
+        for (topic, rate) in [
+            (ImuTopic::Events, 10),
+            (ImuTopic::Raw, 0),
+            (ImuTopic::Raw, 100),
+        ] {
+            let err = streams.add(topic, rate, 100.0, "a").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(streams.list().is_empty());
+    }
+
+    #[test]
+    fn due_keeps_the_rate_grid() {
+        let streams = ImuStreams::new();
+        streams.add(ImuTopic::Orientation, 10, 100.0, "a").unwrap();
+        // 100Hz samples, every tenth goes out
+        let due = (0..100u64)
+            .filter(|i| !streams.due(1_000_000 + i * 10_000).is_empty())
+            .count();
+        assert_eq!(due, 10);
+    }
+}
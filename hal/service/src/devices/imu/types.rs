@@ -1,7 +1,17 @@
+use crate::devices::imu::MountMatrix;
+use serde::{Deserialize, Serialize};
 use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone)]
+// Channel names in ImuData order, for per-channel stats
+pub const IMU_CHANNELS: [&str; 10] = [
+    "accel_x", "accel_y", "accel_z", "gyro_x", "gyro_y", "gyro_z", "mag_x", "mag_y", "mag_z",
+    "temp",
+];
+
+#[derive(Debug, Clone, Default)]
 pub struct ImuData {
+    pub timestamp: u64,  // microseconds since UNIX_EPOCH
     pub accel: [f32; 3], // normalized to g
     pub gyro: [f32; 3],  // radians/s
     pub mag: [f32; 3],   // normalized to gauss
@@ -10,10 +20,114 @@ pub struct ImuData {
     pub euler_angles: [f32; 3], // ZYX (Yaw-Pitch-Roll) in radians
 }
 
+/// Sample rate and full-scale settings to change, scales are the driver's value per LSB
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ImuConfig {
+    pub sample_rate: Option<f64>,
+    pub accel_scale: Option<f64>,
+    pub gyro_scale: Option<f64>,
+}
+
+/// Current sample rate and scales, with the values the driver accepts
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ImuConfigInfo {
+    pub sample_rate: f64,
+    pub sample_rate_available: Vec<f64>, // empty when the driver doesn't list them
+    pub accel_scale: Option<f64>,
+    pub accel_scale_available: Vec<f64>,
+    pub gyro_scale: Option<f64>,
+    pub gyro_scale_available: Vec<f64>,
+}
+
 pub trait Imu {
     fn name(&self) -> String;
     fn imu_data(&self) -> ImuData;
     fn init(&self) -> io::Result<()>;
     fn deinit(&self) -> io::Result<()>;
-    fn sample_rate(&self) -> u32;
+    /// Nominal sample rate in Hz, may be fractional like 12.5
+    fn sample_rate(&self) -> f64;
+
+    /// Whether samples come from a hardware buffer with `read_buffer` instead of polling `imu_data`,
+    /// only valid after `init`
+    fn is_buffered(&self) -> bool {
+        false
+    }
+
+    /// Mount matrix reported by the driver, None when unknown
+    fn mount_matrix(&self) -> Option<MountMatrix> {
+        None
+    }
+
+    /// Mount matrix used when neither the driver nor the config has one
+    fn default_mount_matrix(&self) -> MountMatrix {
+        MountMatrix::LEGACY
+    }
+
+    /// Failed reads per channel since `init`, channels named like `IMU_CHANNELS`
+    fn read_errors(&self) -> Vec<(&'static str, u64)> {
+        vec![]
+    }
+
+    /// Largest accel and gyro magnitude the sensor can report, in ImuData units, None when unknown
+    fn full_scale(&self) -> Option<[f32; 2]> {
+        None
+    }
+
+    /// Current sample rate and scales
+    fn config(&self) -> ImuConfigInfo {
+        ImuConfigInfo {
+            sample_rate: self.sample_rate(),
+            ..Default::default()
+        }
+    }
+
+    /// Apply the given fields, only while stopped, `sample_rate` follows the new rate
+    fn set_config(&self, _config: &ImuConfig) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has no configurable sample rate or scale", self.name()),
+        ))
+    }
+
+    /// Wait up to `timeout` for buffered samples, timestamped by the device
+    fn read_buffer(&self, _timeout: Duration) -> io::Result<Vec<ImuData>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "imu has no hardware buffer",
+        ))
+    }
+
+    /// Whether the driver has a wake-on-motion interrupt
+    fn has_wake_on_motion(&self) -> bool {
+        false
+    }
+
+    /// Arm wake-on-motion at `threshold` g of accel change, while stopped, returns the threshold set
+    fn enable_wake_on_motion(&self, _threshold: f32) -> io::Result<f32> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "imu has no wake-on-motion",
+        ))
+    }
+
+    fn disable_wake_on_motion(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for a wake-on-motion interrupt
+    fn wait_wake_on_motion(&self, _timeout: Duration) -> io::Result<bool> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "imu has no wake-on-motion",
+        ))
+    }
+}
+
+/// Current time in microseconds since UNIX_EPOCH, for polled samples
+pub fn timestamp_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
 }
@@ -0,0 +1,196 @@
+use crate::common::storage;
+use crate::devices::imu::ImuData;
+use log::{debug, warn};
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Mutex, RwLock};
+
+// Motion events are at least this far apart, microseconds
+const MOTION_COOLDOWN: u64 = 1_000_000;
+// Rest reference low-pass time constant, seconds
+const REFERENCE_TAU: f32 = 1.0;
+
+/// Wake-on-motion settings, persisted per IMU
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WakeSettings {
+    pub enabled: bool,      // low-power motion watch while no session runs the device
+    pub threshold: f32,     // g of accel change from rest
+    pub wake_screens: bool, // motion wakes screens and backlights turned off by their idle policy
+}
+
+impl Default for WakeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 0.1,
+            wake_screens: true,
+        }
+    }
+}
+
+impl WakeSettings {
+    pub fn validate(&self) -> io::Result<()> {
+        if !(self.threshold > 0.0 && self.threshold <= 2.0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "invalid threshold {}, must be above 0 up to 2 g",
+                    self.threshold
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What is watching for motion
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WakeMode {
+    Off,       // disabled, or low power not entered yet
+    Streaming, // a session runs the device, samples are checked at full rate
+    Hardware,  // driver's wake-on-motion interrupt, device in low-power cycle
+    Software,  // sysfs polled at a low rate
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WakeInfo {
+    #[serde(flatten)]
+    pub settings: WakeSettings,
+    pub mode: WakeMode,
+    pub hardware: bool, // driver has wake-on-motion
+    pub motions: u64,
+    pub last_motion: Option<u64>, // microseconds since UNIX_EPOCH
+}
+
+#[derive(Default)]
+struct WakeState {
+    low_power_mode: Option<WakeMode>,
+    reference: Option<Vector3<f32>>, // accel at rest, in driver units
+    last_timestamp: Option<u64>,
+    motions: u64,
+    last_motion: Option<u64>,
+}
+
+/// Wake on motion
+/// 没有会话用 IMU 时进入低功耗，用驱动的 wake-on-motion 中断或低频轮询检测运动，产生 motion 事件并唤醒空闲熄屏的屏幕
+pub struct WakeOnMotion {
+    id: String,
+    settings: RwLock<WakeSettings>,
+    state: Mutex<WakeState>,
+}
+
+impl WakeOnMotion {
+    pub fn new(id: &str) -> Self {
+        let settings: WakeSettings = storage::load(&format!("{}/wake", id))
+            .filter(|settings: &WakeSettings| match settings.validate() {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("{} stored wake settings ignored: {}", id, e);
+                    false
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            id: id.to_string(),
+            settings: RwLock::new(settings),
+            state: Mutex::new(WakeState::default()),
+        }
+    }
+
+    pub fn settings(&self) -> WakeSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: WakeSettings) -> io::Result<()> {
+        settings.validate()?;
+        *self.settings.write().unwrap() = settings.clone();
+        debug!("{} wake settings: {:?}", self.id, settings);
+        storage::save(&format!("{}/wake", self.id), &settings)
+    }
+
+    pub fn info(&self, running: bool, hardware: bool) -> WakeInfo {
+        let settings = self.settings();
+        let state = self.state.lock().unwrap();
+        let mode = match state.low_power_mode {
+            _ if !settings.enabled => WakeMode::Off,
+            _ if running => WakeMode::Streaming,
+            Some(mode) => mode,
+            None => WakeMode::Off,
+        };
+        WakeInfo {
+            settings,
+            mode,
+            hardware,
+            motions: state.motions,
+            last_motion: state.last_motion,
+        }
+    }
+
+    pub fn low_power_mode(&self) -> Option<WakeMode> {
+        self.state.lock().unwrap().low_power_mode
+    }
+
+    /// Low power entered with `mode`, or left with None, the rest reference starts over
+    pub fn set_low_power(&self, mode: Option<WakeMode>) {
+        let mut state = self.state.lock().unwrap();
+        state.low_power_mode = mode;
+        state.reference = None;
+        state.last_timestamp = None;
+    }
+
+    /// Check a sample against the threshold, true when it's a new motion event
+    pub fn update(&self, imu_data: &ImuData) -> bool {
+        let threshold = self.settings.read().unwrap().threshold;
+        let mut state = self.state.lock().unwrap();
+        let now = imu_data.timestamp;
+        let accel = Vector3::from(imu_data.accel);
+        let dt = state
+            .last_timestamp
+            .map(|last| now.saturating_sub(last) as f32 / 1e6)
+            .unwrap_or(0.0)
+            .min(1.0);
+        state.last_timestamp = Some(now);
+
+        let reference = match state.reference {
+            Some(reference) => reference,
+            None => {
+                state.reference = Some(accel);
+                return false;
+            }
+        };
+        // Drivers report either g or m/s², the threshold is relative to gravity
+        let g = reference.norm();
+        if g <= f32::EPSILON {
+            state.reference = Some(accel);
+            return false;
+        }
+        let moved = (accel - reference).norm() / g > threshold;
+        state.reference = Some(if moved {
+            accel
+        } else {
+            reference + (accel - reference) * (dt / (REFERENCE_TAU + dt))
+        });
+        moved && Self::record(&mut state, &self.id, now)
+    }
+
+    /// A hardware interrupt fired, true when it's a new motion event
+    pub fn interrupt(&self, timestamp: u64) -> bool {
+        Self::record(&mut self.state.lock().unwrap(), &self.id, timestamp)
+    }
+
+    fn record(state: &mut WakeState, id: &str, timestamp: u64) -> bool {
+        if state
+            .last_motion
+            .is_some_and(|last| timestamp.saturating_sub(last) < MOTION_COOLDOWN)
+        {
+            return false;
+        }
+        debug!("{} motion", id);
+        state.motions += 1;
+        state.last_motion = Some(timestamp);
+        true
+    }
+}
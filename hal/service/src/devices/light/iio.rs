@@ -0,0 +1,220 @@
+use crate::common::iio::list_iio_devices;
+use crate::devices::light::LightSensor;
+use log::debug;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// How the illuminance channel reports lux
+enum Channel {
+    Input(PathBuf), // in_illuminance_input, already lux
+    Raw {
+        path: PathBuf, // in_illuminance_raw, lux = (raw + offset) * scale
+        scale: f32,
+        offset: f32,
+    },
+}
+
+/// IIO ambient light sensor
+/// 读取 IIO 设备的 in_illuminance_* 通道，比如转接板上的 ltr559、tsl2591、veml6030
+pub struct IioLight {
+    name: String,
+    device_path: PathBuf,
+    channel: Channel,
+}
+
+impl IioLight {
+    /// Open an IIO device directory (`/sys/bus/iio/devices/iio:deviceN`)
+    pub fn new(device_path: &Path) -> io::Result<Self> {
+        let name = fs::read_to_string(device_path.join("name"))?
+            .trim()
+            .to_string();
+
+        let mut names: Vec<String> = fs::read_dir(device_path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+
+        // in_illuminance_input or in_illuminance0_input, color variants like _clear_ and _ir_ are skipped
+        let find = |suffix: &str| {
+            names.iter().find(|file| {
+                file.strip_prefix("in_illuminance")
+                    .and_then(|rest| rest.strip_suffix(suffix))
+                    .is_some_and(|index| index.chars().all(|c| c.is_ascii_digit()))
+            })
+        };
+
+        let channel = if let Some(input) = find("_input") {
+            Channel::Input(device_path.join(input))
+        } else if let Some(raw) = find("_raw") {
+            let prefix = raw.strip_suffix("_raw").unwrap_or(raw);
+            let attribute = |attribute: &str, default: f32| {
+                [
+                    format!("{}_{}", prefix, attribute),
+                    format!("in_illuminance_{}", attribute),
+                ]
+                .iter()
+                .find_map(|file| read_f32(&device_path.join(file)))
+                .unwrap_or(default)
+            };
+            Channel::Raw {
+                path: device_path.join(raw),
+                scale: attribute("scale", 1.0),
+                offset: attribute("offset", 0.0),
+            }
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no illuminance channel",
+            ));
+        };
+
+        debug!("open iio light {} at: {}", name, device_path.display());
+        Ok(Self {
+            name,
+            device_path: device_path.to_path_buf(),
+            channel,
+        })
+    }
+
+    /// Get all IIO devices under `iio_devices_path` with an illuminance channel, in device number order
+    pub fn get_all_devices(iio_devices_path: &Path) -> Vec<Self> {
+        list_iio_devices(iio_devices_path)
+            .into_iter()
+            .filter_map(|device_path| match Self::new(&device_path) {
+                Ok(device) => Some(device),
+                Err(e) => {
+                    debug!("skip iio device {}: {}", device_path.display(), e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+impl LightSensor for IioLight {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn device_type(&self) -> String {
+        self.device_path.display().to_string()
+    }
+
+    fn read_lux(&self) -> io::Result<f32> {
+        let (path, scale, offset) = match &self.channel {
+            Channel::Input(path) => (path, 1.0, 0.0),
+            Channel::Raw {
+                path,
+                scale,
+                offset,
+            } => (path, *scale, *offset),
+        };
+        let content = fs::read_to_string(path)?;
+        let value = content.trim().parse::<f32>().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("parse {} failed: {}", path.display(), e),
+            )
+        })?;
+        Ok(((value + offset) * scale).max(0.0))
+    }
+}
+
+fn read_f32(path: &Path) -> Option<f32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_device(root: &Path, dir_name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let device_path = root.join(dir_name);
+        fs::create_dir_all(&device_path).unwrap();
+        fs::write(device_path.join("name"), "ltr559\n").unwrap();
+        for (file, content) in files {
+            fs::write(device_path.join(file), content).unwrap();
+        }
+        device_path
+    }
+
+    #[test]
+    fn input_channel_is_lux() {
+        let root = tempfile::tempdir().unwrap();
+        let device_path = fake_device(
+            root.path(),
+            "iio:device0",
+            &[
+                ("in_illuminance_clear_input", "9999\n"),
+                ("in_illuminance0_input", "123.5\n"),
+                ("in_illuminance0_raw", "1\n"),
+            ],
+        );
+        let light = IioLight::new(&device_path).unwrap();
+        assert_eq!(light.name(), "ltr559");
+        assert_eq!(light.read_lux().unwrap(), 123.5);
+    }
+
+    #[test]
+    fn raw_channel_with_scale_and_offset() {
+        let root = tempfile::tempdir().unwrap();
+        let device_path = fake_device(
+            root.path(),
+            "iio:device0",
+            &[
+                ("in_illuminance_ir_raw", "9999\n"),
+                ("in_illuminance0_raw", "100\n"),
+                ("in_illuminance0_scale", "0.5\n"),
+                ("in_illuminance_offset", "-20\n"),
+            ],
+        );
+        let light = IioLight::new(&device_path).unwrap();
+        // (100 - 20) * 0.5
+        assert_eq!(light.read_lux().unwrap(), 40.0);
+
+        // Never negative
+        fs::write(device_path.join("in_illuminance0_raw"), "5\n").unwrap();
+        assert_eq!(light.read_lux().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn raw_channel_defaults() {
+        let root = tempfile::tempdir().unwrap();
+        let device_path = fake_device(
+            root.path(),
+            "iio:device0",
+            &[("in_illuminance_raw", "42\n")],
+        );
+        assert_eq!(
+            IioLight::new(&device_path).unwrap().read_lux().unwrap(),
+            42.0
+        );
+    }
+
+    #[test]
+    fn devices_without_illuminance_are_skipped() {
+        let root = tempfile::tempdir().unwrap();
+        fake_device(root.path(), "iio:device0", &[("in_accel_x_raw", "1\n")]);
+        fake_device(
+            root.path(),
+            "iio:device1",
+            &[("in_illuminance_clear_raw", "1\n")],
+        );
+        let light_path = fake_device(
+            root.path(),
+            "iio:device2",
+            &[("in_illuminance_input", "7\n")],
+        );
+
+        let e = IioLight::new(&root.path().join("iio:device0"))
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+
+        let lights = IioLight::get_all_devices(root.path());
+        assert_eq!(lights.len(), 1);
+        assert_eq!(lights[0].device_type(), light_path.display().to_string());
+    }
+}
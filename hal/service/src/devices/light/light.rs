@@ -0,0 +1,157 @@
+use crate::common::Emoji;
+use crate::common::iio::IIO_DEVICES_PATH;
+use crate::devices::light::{IioLight, LightSensor, MockLight};
+use crate::devices::{API_REGISTER, ApiRoute};
+use hyper::{Method, Response, StatusCode, header::CONTENT_TYPE};
+use indexmap::IndexMap;
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::{
+    sync::{Notify, RwLock},
+    task,
+};
+
+// 已注册的光线传感器，给其他服务（比如背光自动亮度）按 id 查找
+static LIGHTS: Lazy<RwLock<IndexMap<String, Arc<dyn LightSensor + Send + Sync>>>> =
+    Lazy::new(|| RwLock::new(IndexMap::new()));
+
+/// Find a registered light sensor by device id, e.g. `light0`
+pub async fn find_light_sensor(id: &str) -> Option<Arc<dyn LightSensor + Send + Sync>> {
+    LIGHTS.read().await.get(id).cloned()
+}
+
+// 批量克隆
+macro_rules! arc_clones {
+    ($arc_var:ident, $($name:ident),*) => {
+        $( let $name = Arc::clone(&$arc_var); )*
+    };
+}
+
+// 注册设备
+async fn register_device(id: &str, light: &Arc<dyn LightSensor + Send + Sync>) {
+    arc_clones!(light, light_clone1, light_clone2);
+
+    let error_response = |e: io::Error| {
+        Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(e.to_string())
+            .unwrap()
+    };
+
+    // Add device to device list
+    API_REGISTER.add_device(id.to_string()).await;
+    LIGHTS
+        .write()
+        .await
+        .insert(id.to_string(), Arc::clone(light));
+
+    // Get info
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/info", id),
+                method: Method::GET,
+                description: format!("{} Get device info.", Emoji::INFO),
+            },
+            Box::new(move |_request| {
+                let light = Arc::clone(&light_clone1);
+                Box::pin(async move {
+                    let info = json!({
+                        "name": light.name(),
+                        "device_type": light.device_type(),
+                        "lux": light.read_lux().ok(),
+                        "description": format!("{} Read ambient light in lux.", Emoji::LIGHT)
+                    });
+
+                    Response::builder()
+                        .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                        .body(serde_json::to_string_pretty(&info).unwrap_or("wtf?🤡".to_string()))
+                        .unwrap()
+                })
+            }),
+        )
+        .await
+    {
+        error!("failed to register light info api: {}", e);
+    }
+
+    // Read illuminance
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/read", id),
+                method: Method::GET,
+                description: format!("{} Read ambient light in lux.", Emoji::LIGHT),
+            },
+            Box::new(move |_request| {
+                let light = Arc::clone(&light_clone2);
+                Box::pin(async move {
+                    match light.read_lux() {
+                        Ok(lux) => Response::builder()
+                            .header(CONTENT_TYPE, "application/json; charset=utf-8")
+                            .body(json!({ "lux": lux }).to_string())
+                            .unwrap(),
+                        Err(e) => error_response(e),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        error!("failed to register light read api: {}", e);
+    }
+}
+
+/// Start light service to handle ambient light sensors
+/// # Arguments
+/// * `host` - The host for ZMQ socket to bind to (not used for light sensors, but kept for consistency)
+/// * `shutdown_notify` - A notify clone for shutdown signal
+/// * `mock_light` - Whether to create mock light sensor for api test
+/// # Returns
+/// A `task::JoinHandle` that can be used to wait for the light service to shutdown
+pub async fn start_light_service(
+    _host: &str,
+    shutdown_notify: Arc<Notify>,
+    mock_light: bool,
+) -> io::Result<task::JoinHandle<()>> {
+    let mut lights: Vec<Arc<dyn LightSensor + Send + Sync>> = Vec::new();
+
+    // Create IIO light sensors
+    for light in IioLight::get_all_devices(Path::new(IIO_DEVICES_PATH)) {
+        info!("initialized light sensor: {}", light.name());
+        lights.push(Arc::new(light));
+    }
+
+    // Create mock light sensor
+    if mock_light {
+        info!("create mock light sensor");
+        lights.push(Arc::new(MockLight::new("mock")));
+    }
+
+    if lights.is_empty() {
+        warn!("no light sensors found");
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no light sensors found",
+        ));
+    }
+
+    // Register devices
+    for (i, light) in lights.iter().enumerate() {
+        register_device(format!("light{}", i).as_str(), light).await;
+    }
+
+    info!("light service started with {} devices", lights.len());
+
+    // Start service task
+    Ok(task::spawn(async move {
+        // Wait for shutdown signal
+        shutdown_notify.notified().await;
+        info!("light service shutdown complete");
+    }))
+}
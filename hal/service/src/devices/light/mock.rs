@@ -0,0 +1,38 @@
+use crate::devices::light::LightSensor;
+use std::f32::consts::PI;
+use std::io;
+use std::time::Instant;
+
+// One dark to daylight and back cycle, seconds
+const MOCK_PERIOD: f32 = 60.0;
+
+pub struct MockLight {
+    name: String,
+    start: Instant,
+}
+
+impl MockLight {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl LightSensor for MockLight {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn device_type(&self) -> String {
+        "mock".to_string()
+    }
+
+    fn read_lux(&self) -> io::Result<f32> {
+        // Sweep 5 to 2000 lux on a log scale
+        let t = self.start.elapsed().as_secs_f32();
+        let phase = 0.5 - 0.5 * (2.0 * PI * t / MOCK_PERIOD).cos();
+        Ok(10f32.powf(0.7 + 2.6 * phase))
+    }
+}
@@ -0,0 +1,10 @@
+mod iio;
+#[allow(clippy::module_inception)]
+mod light;
+mod mock;
+mod types;
+
+pub use iio::IioLight;
+pub use light::{find_light_sensor, start_light_service};
+pub use mock::MockLight;
+pub use types::LightSensor;
@@ -0,0 +1,8 @@
+use std::io;
+
+/// Ambient light sensor trait
+pub trait LightSensor {
+    fn name(&self) -> String;
+    fn device_type(&self) -> String;
+    fn read_lux(&self) -> io::Result<f32>; // illuminance in lux
+}
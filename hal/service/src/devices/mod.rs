@@ -1,9 +1,11 @@
 mod backlight;
 mod imu;
+mod light;
 mod register;
 mod screen;
 
 pub use backlight::start_backlight_service;
-pub use imu::start_imu_service;
-pub use register::{API_REGISTER, ApiCallback, ApiRoute};
+pub use imu::{ReplayOptions, start_imu_service};
+pub use light::start_light_service;
+pub use register::{API_REGISTER, ApiRoute};
 pub use screen::start_screen_service;
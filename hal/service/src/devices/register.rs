@@ -98,7 +98,7 @@ impl ApiRegister {
                 Box::pin(async move {
                     Response::builder()
                         .header(CONTENT_TYPE, "application/plain-text; charset=utf-8")
-                        .body(format!("{}", env!("CARGO_PKG_VERSION")))
+                        .body(env!("CARGO_PKG_VERSION").to_string())
                         .unwrap()
                 })
             }),
@@ -0,0 +1,133 @@
+use embedded_graphics::{pixelcolor::Rgb888, prelude::*, primitives::Rectangle};
+use std::convert::Infallible;
+use std::io;
+
+/// Canvas
+/// 服务端绘制用的 RGB888 画布，画完再转换成屏幕的像素格式推送
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<Rgb888>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Rgb888::BLACK; (width * height) as usize],
+        }
+    }
+
+    /// Copy a block of pixels to (x, y), pixels outside the canvas are clipped
+    pub fn blit(&mut self, x: i32, y: i32, width: u32, pixels: &[Rgb888]) {
+        if width == 0 {
+            return;
+        }
+        for (i, row) in pixels.chunks(width as usize).enumerate() {
+            let py = y + i as i32;
+            if py < 0 || py >= self.height as i32 {
+                continue;
+            }
+            for (j, pixel) in row.iter().enumerate() {
+                let px = x + j as i32;
+                if px < 0 || px >= self.width as i32 {
+                    continue;
+                }
+                self.pixels[(py as u32 * self.width + px as u32) as usize] = *pixel;
+            }
+        }
+    }
+
+    /// Convert to frame buffer bytes in the same pixel format kava uses:
+    /// 16 bpp rgb565 (little endian), 24 bpp rgb24, 32 bpp rgba
+    pub fn to_frame_buffer(&self, bpp: u32) -> io::Result<Vec<u8>> {
+        let mut frame_buffer = Vec::with_capacity(self.pixels.len() * bpp as usize / 8);
+        match bpp {
+            16 => {
+                for pixel in &self.pixels {
+                    let rgb565 = ((pixel.r() as u16 >> 3) << 11)
+                        | ((pixel.g() as u16 >> 2) << 5)
+                        | (pixel.b() as u16 >> 3);
+                    frame_buffer.extend_from_slice(&rgb565.to_le_bytes());
+                }
+            }
+            24 => {
+                for pixel in &self.pixels {
+                    frame_buffer.extend_from_slice(&[pixel.r(), pixel.g(), pixel.b()]);
+                }
+            }
+            32 => {
+                for pixel in &self.pixels {
+                    frame_buffer.extend_from_slice(&[pixel.r(), pixel.g(), pixel.b(), 0xff]);
+                }
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("unsupported bits per pixel: {}", bpp),
+                ));
+            }
+        }
+        Ok(frame_buffer)
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x < 0
+                || point.y < 0
+                || point.x >= self.width as i32
+                || point.y >= self.height as i32
+            {
+                continue;
+            }
+            self.pixels[(point.y as u32 * self.width + point.x as u32) as usize] = color;
+        }
+        Ok(())
+    }
+
+    // The defaults visit every point of the area, only walk the part on the canvas
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        let Some(bottom_right) = area.bottom_right() else {
+            return Ok(());
+        };
+        for y in area.top_left.y..=bottom_right.y {
+            let row = (y as u32 * self.width) as usize;
+            self.pixels[row + area.top_left.x as usize..=row + bottom_right.x as usize].fill(color);
+        }
+        Ok(())
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let drawable = area.intersection(&self.bounding_box());
+        let Some(bottom_right) = drawable.bottom_right() else {
+            return Ok(());
+        };
+        // Colors come in row order for the whole area, stop after the last visible row
+        let pixels = area
+            .points()
+            .zip(colors)
+            .take_while(|(point, _)| point.y <= bottom_right.y)
+            .filter(|(point, _)| drawable.contains(*point))
+            .map(|(point, color)| Pixel(point, color));
+        self.draw_iter(pixels)
+    }
+}
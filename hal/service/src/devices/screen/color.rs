@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Color correction settings, persisted per screen
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ColorSettings {
+    pub gamma: [f32; 3], // r, g, b exponent on normalized value, 1.0 means no correction
+    pub swap_rb: bool,   // RGB <-> BGR
+    pub swap_bytes: bool, // rgb565 byte order, only for 16 bpp
+    pub invert: bool,
+}
+
+impl Default for ColorSettings {
+    fn default() -> Self {
+        Self {
+            gamma: [1.0; 3],
+            swap_rb: false,
+            swap_bytes: false,
+            invert: false,
+        }
+    }
+}
+
+/// Color correction
+/// 推到屏幕之前做 gamma、RGB/BGR、字节序和反色校正，查找表提前算好
+pub struct ColorCorrection {
+    settings: ColorSettings,
+    lut: Lut,
+}
+
+enum Lut {
+    Identity,
+    Rgb565(Vec<u16>),                // every rgb565 value to corrected value
+    Rgb(Box<[[u8; 256]; 3]>, usize), // per channel luts and bytes per pixel, for rgb24 and rgba
+}
+
+impl ColorCorrection {
+    pub fn new(settings: ColorSettings, bpp: u32) -> Self {
+        let lut = if settings == ColorSettings::default() {
+            Lut::Identity
+        } else {
+            let channel_luts = Self::channel_luts(&settings);
+            match bpp {
+                16 => Lut::Rgb565(
+                    (0..=u16::MAX)
+                        .map(|value| Self::correct_rgb565(value, &channel_luts, &settings))
+                        .collect(),
+                ),
+                _ => Lut::Rgb(Box::new(channel_luts), (bpp / 8).max(3) as usize),
+            }
+        };
+
+        Self { settings, lut }
+    }
+
+    pub fn settings(&self) -> &ColorSettings {
+        &self.settings
+    }
+
+    /// Apply correction on a frame buffer, no copy if nothing to correct
+    pub fn apply<'a>(&self, frame_buffer: &'a [u8]) -> Cow<'a, [u8]> {
+        match &self.lut {
+            Lut::Identity => Cow::Borrowed(frame_buffer),
+            Lut::Rgb565(lut) => Cow::Owned(
+                frame_buffer
+                    .chunks_exact(2)
+                    .flat_map(|pixel| {
+                        lut[u16::from_le_bytes([pixel[0], pixel[1]]) as usize].to_le_bytes()
+                    })
+                    .collect(),
+            ),
+            Lut::Rgb(channel_luts, bytes_per_pixel) => {
+                // Alpha passes through
+                let mut corrected = frame_buffer.to_vec();
+                for pixel in corrected.chunks_exact_mut(*bytes_per_pixel) {
+                    let r = channel_luts[0][pixel[0] as usize];
+                    let g = channel_luts[1][pixel[1] as usize];
+                    let b = channel_luts[2][pixel[2] as usize];
+                    let (r, b) = if self.settings.swap_rb {
+                        (b, r)
+                    } else {
+                        (r, b)
+                    };
+                    pixel[..3].copy_from_slice(&[r, g, b]);
+                }
+                Cow::Owned(corrected)
+            }
+        }
+    }
+
+    fn channel_luts(settings: &ColorSettings) -> [[u8; 256]; 3] {
+        let mut luts = [[0u8; 256]; 3];
+        for (lut, gamma) in luts.iter_mut().zip(settings.gamma) {
+            for (i, value) in lut.iter_mut().enumerate() {
+                let corrected = ((i as f32 / 255.0).powf(gamma) * 255.0).round() as u8;
+                *value = if settings.invert {
+                    255 - corrected
+                } else {
+                    corrected
+                };
+            }
+        }
+        luts
+    }
+
+    fn correct_rgb565(value: u16, channel_luts: &[[u8; 256]; 3], settings: &ColorSettings) -> u16 {
+        // Expand to 8 bit, correct, then pack back
+        let r5 = (value >> 11) & 0x1f;
+        let g6 = (value >> 5) & 0x3f;
+        let b5 = value & 0x1f;
+        let r = channel_luts[0][((r5 << 3) | (r5 >> 2)) as usize] as u16;
+        let g = channel_luts[1][((g6 << 2) | (g6 >> 4)) as usize] as u16;
+        let b = channel_luts[2][((b5 << 3) | (b5 >> 2)) as usize] as u16;
+        let (r, b) = if settings.swap_rb { (b, r) } else { (r, b) };
+
+        let corrected = ((r >> 3) << 11) | ((g >> 2) << 5) | (b >> 3);
+        if settings.swap_bytes {
+            corrected.swap_bytes()
+        } else {
+            corrected
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::devices::screen::Canvas;
+use embedded_graphics::pixelcolor::Rgb888;
+use image::{
+    AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits, RgbaImage,
+    codecs::gif::GifDecoder, imageops::FilterType,
+};
+use log::warn;
+use std::io::{self, Cursor};
+use std::str::FromStr;
+use std::time::Duration;
+
+// Largest source image side, a full size frame is decoded before fitting
+const MAX_IMAGE_SIDE: u32 = 4096;
+// Frames after this are dropped, every fitted frame stays in memory while playing
+const MAX_ANIMATION_FRAMES: usize = 600;
+
+/// Resize mode, same as kava's
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ResizeMode {
+    Stretch,   // 拉伸
+    Letterbox, // 等比缩放，居中显示
+    #[default]
+    Fill, // 等比缩放，填满屏幕（可能裁剪）
+}
+
+/// Decoded image fitted to screen size
+pub enum DecodedImage {
+    Still(Canvas),
+    Animation(Vec<(Canvas, Duration)>),
+}
+
+impl FromStr for ResizeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stretch" => Ok(ResizeMode::Stretch),
+            "letterbox" => Ok(ResizeMode::Letterbox),
+            "fill" => Ok(ResizeMode::Fill),
+            _ => Err(format!(
+                "invalid resize mode '{}', expected stretch, letterbox or fill",
+                s
+            )),
+        }
+    }
+}
+
+/// Decode a PNG/JPEG/WebP/GIF image and fit it to `size`, animated GIF keeps all frames
+pub fn decode_image(data: &[u8], size: (u32, u32), mode: ResizeMode) -> io::Result<DecodedImage> {
+    let invalid_data = |e: image::ImageError| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("decode image failed: {}", e),
+        )
+    };
+
+    let format = image::guess_format(data).map_err(invalid_data)?;
+    if format == ImageFormat::Gif {
+        let decoder = GifDecoder::new(Cursor::new(data)).map_err(invalid_data)?;
+        let (width, height) = decoder.dimensions();
+        if width > MAX_IMAGE_SIDE || height > MAX_IMAGE_SIDE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "image size {}x{} is too large, must be up to {}x{}",
+                    width, height, MAX_IMAGE_SIDE, MAX_IMAGE_SIDE
+                ),
+            ));
+        }
+
+        // Fit each frame as it's decoded, only one full size frame is around at a time
+        let mut frames = Vec::new();
+        for frame in decoder.into_frames() {
+            if frames.len() == MAX_ANIMATION_FRAMES {
+                warn!(
+                    "animation has more than {} frames, the rest are dropped",
+                    MAX_ANIMATION_FRAMES
+                );
+                break;
+            }
+            let frame = frame.map_err(invalid_data)?;
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            // Browsers play delays of 10ms or less at 100ms, so do we
+            let delay_ms = match numer / denom.max(1) {
+                0..=10 => 100,
+                delay_ms => delay_ms,
+            };
+            let image = DynamicImage::ImageRgba8(frame.into_buffer());
+            frames.push((
+                fit(&image, size, mode),
+                Duration::from_millis(delay_ms as u64),
+            ));
+        }
+
+        return match frames.len() {
+            0 => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "decode image failed: no frames",
+            )),
+            1 => Ok(DecodedImage::Still(frames.remove(0).0)),
+            _ => Ok(DecodedImage::Animation(frames)),
+        };
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_SIDE);
+    limits.max_image_height = Some(MAX_IMAGE_SIDE);
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+    let image = reader.decode().map_err(invalid_data)?;
+    Ok(DecodedImage::Still(fit(&image, size, mode)))
+}
+
+fn fit(image: &DynamicImage, size: (u32, u32), mode: ResizeMode) -> Canvas {
+    let (width, height) = size;
+    let resized = match mode {
+        ResizeMode::Stretch => image.resize_exact(width, height, FilterType::Triangle),
+        ResizeMode::Letterbox => image.resize(width, height, FilterType::Triangle),
+        ResizeMode::Fill => image.resize_to_fill(width, height, FilterType::Triangle),
+    };
+    let resized = resized.to_rgba8();
+
+    // Center on a black canvas, only letterbox leaves borders
+    let mut canvas = Canvas::new(width, height);
+    let x = (width as i32 - resized.width() as i32) / 2;
+    let y = (height as i32 - resized.height() as i32) / 2;
+    canvas.blit(x, y, resized.width(), &to_pixels(&resized));
+    canvas
+}
+
+/// Blend alpha over black
+fn to_pixels(image: &RgbaImage) -> Vec<Rgb888> {
+    image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            let blend = |c: u8| (c as u16 * a as u16 / 255) as u8;
+            Rgb888::new(blend(r), blend(g), blend(b))
+        })
+        .collect()
+}
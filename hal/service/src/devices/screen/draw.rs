@@ -0,0 +1,287 @@
+use crate::devices::screen::Canvas;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use embedded_graphics::{
+    mono_font::{
+        MonoFont, MonoTextStyleBuilder,
+        ascii::{FONT_6X10, FONT_8X13, FONT_10X20},
+    },
+    pixelcolor::Rgb888,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::{Baseline, Text},
+};
+use serde::Deserialize;
+use std::io;
+
+/// Draw operation
+/// `POST /screenN/draw` 的 JSON 绘制指令，按顺序画在屏幕的画布上
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum DrawOp {
+    Fill {
+        color: Color,
+    },
+    Rect {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: Color,
+        #[serde(default)]
+        fill: bool,
+        #[serde(default = "default_stroke_width")]
+        stroke_width: u32,
+    },
+    Line {
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        color: Color,
+        #[serde(default = "default_stroke_width")]
+        stroke_width: u32,
+    },
+    Text {
+        x: i32,
+        y: i32,
+        text: String,
+        color: Color,
+        #[serde(default)]
+        font: Font,
+        background: Option<Color>,
+    },
+    Image {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        data: String, // base64 encoded rgb24 pixels
+    },
+}
+
+/// Color in `#rrggbb` format
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(try_from = "String")]
+pub struct Color(pub Rgb888);
+
+/// Embedded bitmap fonts, ascii only
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Font {
+    Small, // 6x10
+    #[default]
+    Medium, // 8x13
+    Large, // 10x20
+}
+
+// Ops reaching further than this many screen sizes are rejected, drawing them only burns time
+const MAX_DRAW_EXTENT: i64 = 4;
+// Longest text op, glyphs are drawn one by one even off screen
+const MAX_TEXT_LEN: usize = 4096;
+
+fn default_stroke_width() -> u32 {
+    1
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hex = value.trim_start_matches('#');
+        let rgb = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)
+            .ok_or(format!("invalid color '{}', expected #rrggbb", value))?;
+        Ok(Color(Rgb888::new(
+            (rgb >> 16) as u8,
+            (rgb >> 8) as u8,
+            rgb as u8,
+        )))
+    }
+}
+
+impl Font {
+    fn mono_font(&self) -> &'static MonoFont<'static> {
+        match self {
+            Font::Small => &FONT_6X10,
+            Font::Medium => &FONT_8X13,
+            Font::Large => &FONT_10X20,
+        }
+    }
+}
+
+impl DrawOp {
+    /// Reject ops far beyond a screen of `size`, so a single request can't keep the canvas busy
+    pub fn validate(&self, screen_size: (u32, u32)) -> io::Result<()> {
+        let limit = MAX_DRAW_EXTENT * screen_size.0.max(screen_size.1).max(1) as i64;
+        let check = |name: &str, value: i64| {
+            if value.abs() > limit {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} {} is out of range, must be within ±{}",
+                        name, value, limit
+                    ),
+                ));
+            }
+            Ok(())
+        };
+        match self {
+            DrawOp::Fill { .. } => {}
+            DrawOp::Rect {
+                x,
+                y,
+                width,
+                height,
+                stroke_width,
+                ..
+            } => {
+                check("x", *x as i64)?;
+                check("y", *y as i64)?;
+                check("width", *width as i64)?;
+                check("height", *height as i64)?;
+                check("stroke_width", *stroke_width as i64)?;
+            }
+            DrawOp::Line {
+                x0,
+                y0,
+                x1,
+                y1,
+                stroke_width,
+                ..
+            } => {
+                check("x0", *x0 as i64)?;
+                check("y0", *y0 as i64)?;
+                check("x1", *x1 as i64)?;
+                check("y1", *y1 as i64)?;
+                check("stroke_width", *stroke_width as i64)?;
+            }
+            DrawOp::Text { x, y, text, .. } => {
+                check("x", *x as i64)?;
+                check("y", *y as i64)?;
+                if text.len() > MAX_TEXT_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "text is {} bytes, must be up to {}",
+                            text.len(),
+                            MAX_TEXT_LEN
+                        ),
+                    ));
+                }
+            }
+            DrawOp::Image {
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => {
+                check("x", *x as i64)?;
+                check("y", *y as i64)?;
+                check("width", *width as i64)?;
+                check("height", *height as i64)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, canvas: &mut Canvas) -> io::Result<()> {
+        match self {
+            DrawOp::Fill { color } => {
+                let _ = canvas.clear(color.0);
+            }
+            DrawOp::Rect {
+                x,
+                y,
+                width,
+                height,
+                color,
+                fill,
+                stroke_width,
+            } => {
+                let style = if *fill {
+                    PrimitiveStyle::with_fill(color.0)
+                } else {
+                    PrimitiveStyleBuilder::new()
+                        .stroke_color(color.0)
+                        .stroke_width(*stroke_width)
+                        .build()
+                };
+                let _ = Rectangle::new(Point::new(*x, *y), Size::new(*width, *height))
+                    .into_styled(style)
+                    .draw(canvas);
+            }
+            DrawOp::Line {
+                x0,
+                y0,
+                x1,
+                y1,
+                color,
+                stroke_width,
+            } => {
+                let _ = Line::new(Point::new(*x0, *y0), Point::new(*x1, *y1))
+                    .into_styled(PrimitiveStyle::with_stroke(color.0, *stroke_width))
+                    .draw(canvas);
+            }
+            DrawOp::Text {
+                x,
+                y,
+                text,
+                color,
+                font,
+                background,
+            } => {
+                let mut style = MonoTextStyleBuilder::new()
+                    .font(font.mono_font())
+                    .text_color(color.0);
+                if let Some(background) = background {
+                    style = style.background_color(background.0);
+                }
+                let _ = Text::with_baseline(text, Point::new(*x, *y), style.build(), Baseline::Top)
+                    .draw(canvas);
+            }
+            DrawOp::Image {
+                x,
+                y,
+                width,
+                height,
+                data,
+            } => {
+                let data = BASE64.decode(data).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("decode image data failed: {}", e),
+                    )
+                })?;
+
+                let expected_len = (*width as usize)
+                    .checked_mul(*height as usize)
+                    .and_then(|len| len.checked_mul(3))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("image size {}x{} is too large", width, height),
+                        )
+                    })?;
+                if data.len() != expected_len {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "expected {} bytes of rgb24 image data, got {}",
+                            expected_len,
+                            data.len()
+                        ),
+                    ));
+                }
+
+                let pixels: Vec<Rgb888> = data
+                    .chunks_exact(3)
+                    .map(|rgb| Rgb888::new(rgb[0], rgb[1], rgb[2]))
+                    .collect();
+                canvas.blit(*x, *y, *width, &pixels);
+            }
+        }
+        Ok(())
+    }
+}
@@ -2,8 +2,14 @@ use crate::devices::screen::Screen;
 use log::{debug, info, warn};
 use std::fs;
 use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
 use std::path::{Path, PathBuf};
 
+// FBIOBLANK from linux/fb.h, drm fbdev emulation maps it to DPMS
+nix::ioctl_write_int_bad!(fbioblank, 0x4611);
+const FB_BLANK_UNBLANK: i32 = 0;
+const FB_BLANK_POWERDOWN: i32 = 4;
+
 /// Frame buffer screen
 /// 副屏的 fb 实现，可以将 buffer 写入 /dev/fbx
 #[derive(Debug)]
@@ -80,7 +86,7 @@ impl FrameBufferScreen {
             // Get device path
             let Some(device_path) = fs::read_link(entry_path.join("device"))
                 .ok()
-                .map(|p| fs::canonicalize(&p).unwrap_or_else(|_| p))
+                .map(|p| fs::canonicalize(&p).unwrap_or(p))
             else {
                 warn!(
                     "failed to read device path of frame buffer: {}",
@@ -152,4 +158,22 @@ impl Screen for FrameBufferScreen {
 
         Ok(())
     }
+
+    fn set_power(&self, on: bool) -> io::Result<()> {
+        debug!(
+            "set power {} to {}",
+            if on { "on" } else { "off" },
+            self.path.display()
+        );
+
+        let file = fs::OpenOptions::new().write(true).open(&self.path)?;
+        let blank = if on {
+            FB_BLANK_UNBLANK
+        } else {
+            FB_BLANK_POWERDOWN
+        };
+        unsafe { fbioblank(file.as_raw_fd(), blank) }?;
+
+        Ok(())
+    }
 }
@@ -52,4 +52,9 @@ impl Screen for MockScreen {
 
         Ok(())
     }
+
+    fn set_power(&self, on: bool) -> io::Result<()> {
+        debug!("set mock screen power {}", if on { "on" } else { "off" });
+        Ok(())
+    }
 }
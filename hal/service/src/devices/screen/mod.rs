@@ -1,11 +1,26 @@
+mod canvas;
+mod color;
+mod decode;
+mod draw;
 mod fb;
 mod mock;
+mod power;
+#[allow(clippy::module_inception)]
 mod screen;
+mod shm;
 mod socket;
+mod splash;
 mod types;
 
+use canvas::Canvas;
+use color::{ColorCorrection, ColorSettings};
+use decode::{DecodedImage, ResizeMode, decode_image};
+use draw::DrawOp;
 use fb::FrameBufferScreen;
 use mock::MockScreen;
-pub use screen::start_screen_service;
+use power::ScreenPower;
+pub use screen::{start_screen_service, wake_screens};
+use shm::{ShmClient, ShmTransport};
 use socket::ScreenSocket;
+use splash::{SplashMode, SplashSettings, error_card, splash};
 use types::Screen;
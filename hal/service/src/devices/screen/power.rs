@@ -0,0 +1,222 @@
+use crate::devices::backlight::Backlight;
+use crate::devices::screen::Screen;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::{sync::Notify, time};
+
+/// Screen power control
+/// 管理屏幕的开关和空闲熄屏：一段时间没有新帧就熄屏并关掉关联的背光，下一帧到来时自动唤醒
+pub struct ScreenPower {
+    id: String,
+    screen: Arc<dyn Screen + Send + Sync>,
+    state: Mutex<PowerState>,
+    notify: Notify,
+}
+
+struct PowerState {
+    power_on: bool, // set by power api
+    idle: bool,     // set by idle policy
+    last_activity: Instant,
+    idle_timeout: Option<Duration>,
+    backlight: Option<IdleBacklight>,
+}
+
+struct IdleBacklight {
+    id: String,
+    backlight: Arc<dyn Backlight + Send + Sync>,
+    saved_brightness: Option<f32>, // brightness before blank, restored on wake
+}
+
+#[derive(Serialize, Debug)]
+pub struct PowerInfo {
+    power: bool,
+    idle: bool,
+    idle_timeout: u64, // seconds, 0 means disabled
+    backlight: Option<String>,
+}
+
+impl PowerState {
+    fn blanked(&self) -> bool {
+        !self.power_on || self.idle
+    }
+}
+
+impl ScreenPower {
+    pub fn new(id: &str, screen: Arc<dyn Screen + Send + Sync>) -> Self {
+        Self {
+            id: id.to_string(),
+            screen,
+            state: Mutex::new(PowerState {
+                power_on: true,
+                idle: false,
+                last_activity: Instant::now(),
+                idle_timeout: None,
+                backlight: None,
+            }),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Turn screen on or off manually, frames won't wake a screen turned off here
+    pub fn set_power(&self, on: bool) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let was_blanked = state.blanked();
+        state.power_on = on;
+        // Either way the idle blank is over, on wakes the screen and restarts the idle timer
+        state.idle = false;
+        if on {
+            state.last_activity = Instant::now();
+        }
+        self.apply(&mut state, was_blanked)?;
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// Record a new frame, wakes the screen if it was blanked by idle policy
+    pub fn on_frame(&self) -> io::Result<()> {
+        self.on_activity("frame")
+    }
+
+    /// Record activity like a frame or motion, restarts the idle timer and wakes an idle screen
+    pub fn on_activity(&self, source: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.last_activity = Instant::now();
+        if state.idle {
+            info!("{} wake up from idle on {}", self.id, source);
+            let was_blanked = state.blanked();
+            state.idle = false;
+            self.apply(&mut state, was_blanked)?;
+            self.notify.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Set idle policy, `None` timeout disables it
+    pub fn set_idle_policy(
+        &self,
+        idle_timeout: Option<Duration>,
+        backlight: Option<(String, Arc<dyn Backlight + Send + Sync>)>,
+    ) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        // Wake up first so the old backlight gets its brightness back
+        let was_blanked = state.blanked();
+        state.idle = false;
+        self.apply(&mut state, was_blanked)?;
+
+        state.idle_timeout = idle_timeout;
+        state.backlight = backlight.map(|(id, backlight)| IdleBacklight {
+            id,
+            backlight,
+            saved_brightness: None,
+        });
+        state.last_activity = Instant::now();
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    pub fn info(&self) -> PowerInfo {
+        let state = self.state.lock().unwrap();
+        PowerInfo {
+            power: state.power_on,
+            idle: state.idle,
+            idle_timeout: state.idle_timeout.map(|t| t.as_secs()).unwrap_or(0),
+            backlight: state.backlight.as_ref().map(|b| b.id.clone()),
+        }
+    }
+
+    /// Blank the screen when no frame arrives within idle timeout, runs until cancelled
+    pub async fn watch_idle(&self) {
+        loop {
+            let deadline = {
+                let state = self.state.lock().unwrap();
+                match state.idle_timeout {
+                    Some(timeout) if !state.blanked() => Some(state.last_activity + timeout),
+                    _ => None,
+                }
+            };
+
+            match deadline {
+                Some(deadline) => {
+                    tokio::select! {
+                        _ = time::sleep_until(deadline.into()) => self.check_idle(),
+                        _ = self.notify.notified() => {}
+                    }
+                }
+                None => self.notify.notified().await,
+            }
+        }
+    }
+
+    fn check_idle(&self) {
+        let mut state = self.state.lock().unwrap();
+        let Some(timeout) = state.idle_timeout else {
+            return;
+        };
+        if state.blanked() || state.last_activity.elapsed() < timeout {
+            return;
+        }
+
+        info!("{} idle for {}s, blank screen", self.id, timeout.as_secs());
+        state.idle = true;
+        if let Err(e) = self.apply(&mut state, false) {
+            warn!("{} blank screen failed: {}", self.id, e);
+        }
+    }
+
+    /// Apply blank state to screen and backlight if it changed
+    fn apply(&self, state: &mut PowerState, was_blanked: bool) -> io::Result<()> {
+        let blanked = state.blanked();
+        if blanked == was_blanked {
+            return Ok(());
+        }
+
+        debug!("{} apply blank: {}", self.id, blanked);
+
+        if let Some(idle_backlight) = state.backlight.as_mut() {
+            if blanked {
+                idle_backlight.saved_brightness = idle_backlight.backlight.get_brightness().ok();
+                idle_backlight.backlight.set_brightness(0.0)?;
+            } else if let Some(brightness) = idle_backlight.saved_brightness.take() {
+                idle_backlight.backlight.set_brightness(brightness)?;
+            }
+        }
+
+        self.screen.set_power(!blanked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::backlight::MockBacklight;
+    use crate::devices::screen::MockScreen;
+
+    #[test]
+    fn power_on_wakes_idle_screen() {
+        let power = ScreenPower::new("screen0", Arc::new(MockScreen::new(320, 240, 16)));
+        let backlight: Arc<dyn Backlight + Send + Sync> = Arc::new(MockBacklight::new("mock", 100));
+        backlight.set_brightness(0.6).unwrap();
+        power
+            .set_idle_policy(
+                Some(Duration::from_secs(60)),
+                Some(("backlight0".to_string(), Arc::clone(&backlight))),
+            )
+            .unwrap();
+
+        // Idle timeout passed
+        power.state.lock().unwrap().last_activity -= Duration::from_secs(61);
+        power.check_idle();
+        let info = power.info();
+        assert!(info.power && info.idle);
+        assert_eq!(backlight.get_brightness().unwrap(), 0.0);
+
+        power.set_power(true).unwrap();
+        let info = power.info();
+        assert!(info.power && !info.idle);
+        assert_eq!(backlight.get_brightness().unwrap(), 0.6);
+    }
+}
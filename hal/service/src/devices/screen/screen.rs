@@ -1,13 +1,522 @@
-use crate::common::Emoji;
+use crate::common::{Emoji, query_param, read_body};
 use crate::devices::{
     API_REGISTER, ApiRoute,
-    screen::{FrameBufferScreen, MockScreen, Screen, ScreenSocket},
+    backlight::find_backlight,
+    screen::{
+        DrawOp, FrameBufferScreen, MockScreen, ResizeMode, Screen, ScreenSocket, SplashMode,
+        decode_image,
+    },
 };
-use hyper::{Method, Response, header::CONTENT_TYPE};
+use hyper::{Method, Response, StatusCode, header::CONTENT_TYPE};
+use indexmap::IndexMap;
 use log::{error, info, warn};
+use once_cell::sync::Lazy;
 use std::io;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::{sync::Notify, task};
+use std::time::Duration;
+use tokio::{
+    sync::{Notify, RwLock},
+    task,
+};
+
+// 已注册的屏幕，给其他服务（比如 IMU 运动唤醒）用
+static SCREENS: Lazy<RwLock<IndexMap<String, Arc<ScreenSocket>>>> =
+    Lazy::new(|| RwLock::new(IndexMap::new()));
+
+/// Count activity like `source` on every screen, screens blanked by their idle policy wake up
+pub async fn wake_screens(source: &str) {
+    for screen_socket in SCREENS.read().await.values() {
+        if let Err(e) = screen_socket.power.on_activity(source) {
+            warn!("{} wake up screen failed: {}", screen_socket.id, e);
+        }
+    }
+}
+
+// 批量克隆
+macro_rules! arc_clones {
+    ($arc_var:ident, $($name:ident),*) => {
+        $( let $name = Arc::clone(&$arc_var); )*
+    };
+}
+
+// 注册设备
+async fn register_device(screen_socket: &Arc<ScreenSocket>) {
+    arc_clones!(
+        screen_socket,
+        screen_socket_clone1,
+        screen_socket_clone2,
+        screen_socket_clone3,
+        screen_socket_clone4,
+        screen_socket_clone5,
+        screen_socket_clone6,
+        screen_socket_clone7,
+        screen_socket_clone8,
+        screen_socket_clone9,
+        screen_socket_clone10,
+        screen_socket_clone11,
+        screen_socket_clone12
+    );
+
+    let json_response = |body: String| -> Response<String> {
+        Response::builder()
+            .header(CONTENT_TYPE, "application/json; charset=utf-8")
+            .body(body)
+            .unwrap()
+    };
+    let success_response = || -> Response<String> {
+        Response::builder()
+            .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body("ok👍".to_string())
+            .unwrap()
+    };
+    let error_response = |status: StatusCode, msg: String| {
+        Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(msg)
+            .unwrap()
+    };
+
+    // Add device to device list
+    API_REGISTER.add_device(screen_socket.id.clone()).await;
+    SCREENS
+        .write()
+        .await
+        .insert(screen_socket.id.clone(), Arc::clone(screen_socket));
+
+    // Get info
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/info", screen_socket.id),
+                method: Method::GET,
+                description: format!("{} Get device info", Emoji::INFO),
+            },
+            Box::new(move |_request| {
+                let screen_socket = Arc::clone(&screen_socket_clone1);
+                Box::pin(async move { json_response(screen_socket.get_device_info()) })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Get power state
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/power", screen_socket.id),
+                method: Method::GET,
+                description: format!("{} Get power state and idle policy.", Emoji::INFO),
+            },
+            Box::new(move |_request| {
+                let screen_socket = Arc::clone(&screen_socket_clone2);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&screen_socket.power.info())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Power on
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/power/on", screen_socket.id),
+                method: Method::GET,
+                description: format!("{} Turn screen on.", Emoji::START),
+            },
+            Box::new(move |_request| {
+                let screen_socket = Arc::clone(&screen_socket_clone3);
+                Box::pin(async move {
+                    match screen_socket.power.set_power(true) {
+                        Ok(()) => success_response(),
+                        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Power off
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/power/off", screen_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Turn screen off, it stays off until turned on again.",
+                    Emoji::STOP
+                ),
+            },
+            Box::new(move |_request| {
+                let screen_socket = Arc::clone(&screen_socket_clone4);
+                Box::pin(async move {
+                    match screen_socket.power.set_power(false) {
+                        Ok(()) => success_response(),
+                        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Set idle policy
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/idle/set", screen_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Blank screen and turn its backlight off after <timeout> seconds without frames, wake on next frame. Use query parameter: /{}/idle/set?timeout=60&backlight=backlight0, timeout=0 to disable",
+                    Emoji::STOP,
+                    screen_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let screen_socket = Arc::clone(&screen_socket_clone5);
+                Box::pin(async move {
+                    let timeout = match query_param(request.uri(), "timeout")
+                        .map(|timeout| timeout.parse::<u64>())
+                    {
+                        Some(Ok(timeout)) => timeout,
+                        Some(Err(_)) => {
+                            return error_response(
+                                StatusCode::BAD_REQUEST,
+                                "invalid timeout value, must be seconds".to_string(),
+                            );
+                        }
+                        None => {
+                            return error_response(
+                                StatusCode::BAD_REQUEST,
+                                "missing timeout parameter".to_string(),
+                            );
+                        }
+                    };
+
+                    let backlight = match query_param(request.uri(), "backlight") {
+                        Some(id) => match find_backlight(&id).await {
+                            Some(backlight) => Some((id, backlight)),
+                            None => {
+                                return error_response(
+                                    StatusCode::NOT_FOUND,
+                                    format!("backlight {} not found", id),
+                                );
+                            }
+                        },
+                        None => None,
+                    };
+
+                    let timeout = (timeout > 0).then(|| Duration::from_secs(timeout));
+                    match screen_socket.power.set_idle_policy(timeout, backlight) {
+                        Ok(()) => success_response(),
+                        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Draw
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/draw", screen_socket.id),
+                method: Method::POST,
+                description: format!(
+                    "{} Draw with a JSON array of ops: fill, rect, line, text, image. e.g. [{{\"op\": \"fill\", \"color\": \"#000000\"}}, {{\"op\": \"text\", \"x\": 0, \"y\": 0, \"text\": \"hi\", \"color\": \"#ffffff\"}}]",
+                    Emoji::FORMAT
+                ),
+            },
+            Box::new(move |request| {
+                let screen_socket = Arc::clone(&screen_socket_clone6);
+                Box::pin(async move {
+                    let body = match read_body(request).await {
+                        Ok(body) => body,
+                        Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+                    };
+                    let ops: Vec<DrawOp> = match serde_json::from_slice(&body) {
+                        Ok(ops) => ops,
+                        Err(e) => {
+                            return error_response(
+                                StatusCode::BAD_REQUEST,
+                                format!("invalid draw ops: {}", e),
+                            );
+                        }
+                    };
+
+                    match screen_socket.draw(&ops) {
+                        Ok(()) => success_response(),
+                        Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                            error_response(StatusCode::BAD_REQUEST, e.to_string())
+                        }
+                        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Show image
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/image", screen_socket.id),
+                method: Method::POST,
+                description: format!(
+                    "{} Show a PNG/JPEG/WebP/GIF image from request body, animated GIF keeps playing until next push. Use query parameter: /{}/image?mode=fill, mode can be stretch, letterbox or fill",
+                    Emoji::PUBLISH,
+                    screen_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let screen_socket = Arc::clone(&screen_socket_clone7);
+                Box::pin(async move {
+                    let mode = match query_param(request.uri(), "mode")
+                        .map(|mode| mode.parse::<ResizeMode>())
+                        .unwrap_or(Ok(ResizeMode::default()))
+                    {
+                        Ok(mode) => mode,
+                        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+                    };
+                    let body = match read_body(request).await {
+                        Ok(body) => body,
+                        Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+                    };
+
+                    // Decoding is cpu heavy, keep it off the async workers
+                    let size = screen_socket.size();
+                    let image = match task::spawn_blocking(move || decode_image(&body, size, mode))
+                        .await
+                    {
+                        Ok(Ok(image)) => image,
+                        Ok(Err(e)) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+                        Err(e) => {
+                            return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+                        }
+                    };
+
+                    match screen_socket.show_image(image) {
+                        Ok(()) => success_response(),
+                        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Get color correction
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/color", screen_socket.id),
+                method: Method::GET,
+                description: format!("{} Get color correction settings.", Emoji::INFO),
+            },
+            Box::new(move |_request| {
+                let screen_socket = Arc::clone(&screen_socket_clone8);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&screen_socket.color_settings())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Set color correction
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/color/set", screen_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Set color correction, only given parameters change and settings are persisted. Use query parameter: /{}/color/set?gamma=2.2&swap_rb=true&swap_bytes=false&invert=false, gamma can also be per channel like gamma=2.2,2.0,2.1",
+                    Emoji::STORAGE,
+                    screen_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let screen_socket = Arc::clone(&screen_socket_clone9);
+                Box::pin(async move {
+                    let mut settings = screen_socket.color_settings();
+
+                    if let Some(gamma) = query_param(request.uri(), "gamma") {
+                        let gamma: Vec<f32> = gamma
+                            .split(',')
+                            .filter_map(|g| g.parse().ok())
+                            .filter(|g: &f32| *g > 0.0)
+                            .collect();
+                        settings.gamma = match gamma[..] {
+                            [g] => [g; 3],
+                            [r, g, b] => [r, g, b],
+                            _ => {
+                                return error_response(
+                                    StatusCode::BAD_REQUEST,
+                                    "invalid gamma value, must be one or three positive numbers"
+                                        .to_string(),
+                                );
+                            }
+                        };
+                    }
+
+                    for (key, value) in [
+                        ("swap_rb", &mut settings.swap_rb),
+                        ("swap_bytes", &mut settings.swap_bytes),
+                        ("invert", &mut settings.invert),
+                    ] {
+                        if let Some(param) = query_param(request.uri(), key) {
+                            match param.parse::<bool>() {
+                                Ok(param) => *value = param,
+                                Err(_) => {
+                                    return error_response(
+                                        StatusCode::BAD_REQUEST,
+                                        format!("invalid {} value, must be true or false", key),
+                                    );
+                                }
+                            }
+                        }
+                    }
+
+                    match screen_socket.set_color_settings(settings) {
+                        Ok(()) => success_response(),
+                        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Get splash settings
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/splash", screen_socket.id),
+                method: Method::GET,
+                description: format!("{} Get splash settings.", Emoji::INFO),
+            },
+            Box::new(move |_request| {
+                let screen_socket = Arc::clone(&screen_socket_clone10);
+                Box::pin(async move {
+                    json_response(
+                        serde_json::to_string_pretty(&screen_socket.splash_settings())
+                            .unwrap_or("wtf?🤡".to_string()),
+                    )
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Set splash settings
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/splash/set", screen_socket.id),
+                method: Method::GET,
+                description: format!(
+                    "{} Set splash shown on startup and <stream_timeout> seconds after the last stream ends, only given parameters change and settings are persisted. Use query parameter: /{}/splash/set?mode=image&image=/path/to/splash.gif&stream_timeout=3, mode can be none, info or image",
+                    Emoji::STORAGE,
+                    screen_socket.id
+                ),
+            },
+            Box::new(move |request| {
+                let screen_socket = Arc::clone(&screen_socket_clone11);
+                Box::pin(async move {
+                    let mut settings = screen_socket.splash_settings();
+
+                    if let Some(mode) = query_param(request.uri(), "mode") {
+                        settings.mode = match mode.parse::<SplashMode>() {
+                            Ok(mode) => mode,
+                            Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+                        };
+                    }
+                    if let Some(image) = query_param(request.uri(), "image") {
+                        settings.image = Some(PathBuf::from(image));
+                    }
+                    if let Some(stream_timeout) = query_param(request.uri(), "stream_timeout") {
+                        settings.stream_timeout = match stream_timeout.parse::<u64>() {
+                            Ok(stream_timeout) => stream_timeout,
+                            Err(_) => {
+                                return error_response(
+                                    StatusCode::BAD_REQUEST,
+                                    "invalid stream_timeout value, must be seconds".to_string(),
+                                );
+                            }
+                        };
+                    }
+                    if settings.mode == SplashMode::Image && settings.image.is_none() {
+                        return error_response(
+                            StatusCode::BAD_REQUEST,
+                            "image mode needs an image parameter".to_string(),
+                        );
+                    }
+
+                    match screen_socket.set_splash_settings(settings) {
+                        Ok(()) => success_response(),
+                        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                    }
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+
+    // Show splash
+    if let Err(e) = API_REGISTER
+        .add_api(
+            ApiRoute {
+                path: format!("/{}/splash/show", screen_socket.id),
+                method: Method::GET,
+                description: format!("{} Show splash now.", Emoji::PUBLISH),
+            },
+            Box::new(move |_request| {
+                let screen_socket = Arc::clone(&screen_socket_clone12);
+                Box::pin(async move {
+                    screen_socket.show_splash().await;
+                    success_response()
+                })
+            }),
+        )
+        .await
+    {
+        warn!("add api failed: {}", e);
+    }
+}
 
 /// Start screen service to handle cover screen devices
 /// # Arguments
@@ -39,39 +548,13 @@ pub async fn start_screen_service(
     }
 
     // Create screen sockets
-    let mut screen_sockets: Vec<ScreenSocket> = Vec::new();
+    let mut screen_sockets: Vec<Arc<ScreenSocket>> = Vec::new();
     for (i, screen) in screens.into_iter().enumerate() {
-        let screen_socket = ScreenSocket::new(screen, format!("screen{}", i), host).await?;
-        let screen_info = screen_socket.get_device_info();
-
-        // Add device to device list
-        API_REGISTER.add_device(screen_socket.id.clone()).await;
-
-        // Register get info api
-        match API_REGISTER
-            .add_api(
-                ApiRoute {
-                    path: format!("/{}/info", screen_socket.id),
-                    method: Method::GET,
-                    description: format!("{} Get device info", Emoji::INFO),
-                },
-                Box::new(move |_request| {
-                    let screen_info = screen_info.clone();
-                    Box::pin(async move {
-                        Response::builder()
-                            .header(CONTENT_TYPE, "application/json; charset=utf-8")
-                            .body(screen_info)
-                            .unwrap()
-                    })
-                }),
-            )
-            .await
-        {
-            Ok(_) => {}
-            Err(e) => {
-                warn!("add api failed: {}", e);
-            }
-        }
+        let screen_socket =
+            Arc::new(ScreenSocket::new(screen, format!("screen{}", i), host).await?);
+
+        register_device(&screen_socket).await;
+        screen_socket.show_splash().await;
 
         screen_sockets.push(screen_socket);
     }
@@ -80,19 +563,16 @@ pub async fn start_screen_service(
     let handle = task::spawn(async move {
         let mut workers = Vec::new();
 
-        for mut screen_socket in screen_sockets {
+        for screen_socket in screen_sockets {
             let notify = shutdown_notify.clone();
 
             let worker = task::spawn(async move {
                 tokio::select! {
-                    _ = async {
-                        loop {
-                            screen_socket.listen().await;
-                        }
-                    } => {}
+                    _ = screen_socket.serve() => {}
 
                     _ = notify.notified() => {
                         info!("{} shutdown...", screen_socket.id);
+                        screen_socket.show_error_card("HAL stopped");
                     }
                 }
             });
@@ -0,0 +1,136 @@
+use log::debug;
+use memmap2::MmapRaw;
+use nix::fcntl::{FcntlArg, SealFlag, fcntl};
+use nix::sys::memfd::{MFdFlags, memfd_create};
+use nix::sys::socket::{ControlMessage, MsgFlags, sendmsg};
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, IoSlice};
+use std::os::fd::AsRawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixListener as StdUnixListener};
+use std::ptr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, Interest};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Shared memory frame transport
+/// 给本机客户端用的零拷贝传输：每个客户端连上 unix socket 后分到自己的一块 memfd，
+/// 客户端直接往共享内存里画，画完发一个字节通知服务端，服务端拷出一帧后回一个字节，客户端才能接着画下一帧
+///
+/// Ownership of a client's buffer alternates over its socket: the client owns it until it sends
+/// "frame ready", the service owns it until it replies the status byte. The service only reads
+/// through raw pointers into its own copy, so a client writing out of turn tears its own frame
+/// but never aliases memory the service holds a reference to.
+pub struct ShmTransport {
+    socket_name: String,
+    listener: UnixListener,
+    frame_size: usize,
+}
+
+/// A connected shared memory client with its own buffer
+pub struct ShmClient {
+    stream: UnixStream,
+    // Keeps the mapping alive, sealed against resizing
+    _memfd: File,
+    shared: MmapRaw,
+    frame: Vec<u8>, // copy of the shared buffer taken on "frame ready"
+}
+
+impl ShmTransport {
+    /// Listen on abstract unix socket `@rebecca-hal/<id>` for clients of `frame_size` byte frames
+    pub fn new(id: &str, frame_size: usize) -> io::Result<Self> {
+        let socket_name = format!("rebecca-hal/{}", id);
+
+        // Bind abstract unix socket, no file on disk to clean up
+        let addr = SocketAddr::from_abstract_name(socket_name.as_bytes())?;
+        let listener = StdUnixListener::bind_addr(&addr)?;
+        listener.set_nonblocking(true)?;
+        let listener = UnixListener::from_std(listener)?;
+
+        debug!(
+            "shm transport listening on @{}, frame size {} bytes",
+            socket_name, frame_size
+        );
+
+        Ok(Self {
+            socket_name,
+            listener,
+            frame_size,
+        })
+    }
+
+    /// Abstract socket name, prefixed with `@` like `ss` shows it
+    pub fn socket_name(&self) -> String {
+        format!("@{}", self.socket_name)
+    }
+
+    /// Accept a new client and hand over its own memfd with a JSON header
+    pub async fn accept(&self) -> io::Result<ShmClient> {
+        let (stream, _) = self.listener.accept().await?;
+
+        // Create memfd, seal its size so the client can't shrink it under our mapping
+        let memfd = File::from(memfd_create(
+            self.socket_name.as_str(),
+            MFdFlags::MFD_CLOEXEC | MFdFlags::MFD_ALLOW_SEALING,
+        )?);
+        memfd.set_len(self.frame_size as u64)?;
+        fcntl(
+            &memfd,
+            FcntlArg::F_ADD_SEALS(
+                SealFlag::F_SEAL_SHRINK | SealFlag::F_SEAL_GROW | SealFlag::F_SEAL_SEAL,
+            ),
+        )?;
+        let shared = MmapRaw::map_raw(&memfd)?;
+
+        let header = format!("{}\n", json!({ "frame_size": self.frame_size }));
+        let fds = [memfd.as_raw_fd()];
+        stream
+            .async_io(Interest::WRITABLE, || {
+                sendmsg::<()>(
+                    stream.as_raw_fd(),
+                    &[IoSlice::new(header.as_bytes())],
+                    &[ControlMessage::ScmRights(&fds)],
+                    MsgFlags::empty(),
+                    None,
+                )
+                .map_err(io::Error::from)
+            })
+            .await?;
+
+        debug!("shm client connected to @{}", self.socket_name);
+
+        Ok(ShmClient {
+            stream,
+            _memfd: memfd,
+            shared,
+            frame: vec![0; self.frame_size],
+        })
+    }
+}
+
+impl ShmClient {
+    /// Wait for a "frame ready" byte, returns `false` when the client disconnects
+    pub async fn wait_frame(&mut self) -> io::Result<bool> {
+        let mut signal = [0u8; 1];
+        Ok(self.stream.read(&mut signal).await? == 1)
+    }
+
+    /// Copy the shared buffer out, only between `wait_frame` and `ack` while the client waits
+    pub fn frame(&mut self) -> &[u8] {
+        // Safety: the mapping is sealed at `frame.len()` bytes and lives as long as self, it is
+        // read through a raw pointer only, never borrowed, since the client process may write it
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.shared.as_ptr(),
+                self.frame.as_mut_ptr(),
+                self.frame.len(),
+            );
+        }
+        &self.frame
+    }
+
+    /// Reply a status byte, 0 for ok and 1 for failed, after which the client may reuse the buffer
+    pub async fn ack(&mut self, ok: bool) -> io::Result<()> {
+        self.stream.write_all(&[if ok { 0 } else { 1 }]).await
+    }
+}
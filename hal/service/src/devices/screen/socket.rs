@@ -1,20 +1,55 @@
-use crate::common::Emoji;
-use crate::devices::screen::Screen;
-use log::error;
+use crate::common::{Emoji, storage};
+use crate::devices::screen::{
+    Canvas, ColorCorrection, ColorSettings, DecodedImage, DrawOp, Screen, ScreenPower, ShmClient,
+    ShmTransport, SplashSettings, error_card, splash,
+};
+use log::{debug, error, warn};
 use regex::Regex;
 use serde::Serialize;
 use serde_json::json;
 use std::io;
-use std::sync::Arc;
-use zeromq::{Socket, SocketRecv, SocketSend};
+use std::sync::{
+    Arc, Mutex, RwLock,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+use std::time::{Duration, Instant};
+use tokio::{
+    sync::Notify,
+    task::{self, JoinSet},
+    time,
+};
+use zeromq::{Socket, SocketRecv, SocketSend, ZmqMessage};
+
+// Most ops one draw request may carry
+const MAX_DRAW_OPS: usize = 1024;
 
 /// Screen socket
 /// 监听一个 screen zmq rep socket 把接收数据推送到屏幕
+/// 另外还有一个 zmq pull socket 用于异步推流，只保留最新的一帧
+/// 以及一个给本机客户端用的共享内存传输
 pub struct ScreenSocket {
     pub id: String,
     screen: Arc<dyn Screen + Send + Sync>,
     frame_buffer_port: u16,
-    frame_buffer_socket: zeromq::RepSocket,
+    frame_buffer_socket: tokio::sync::Mutex<zeromq::RepSocket>,
+    frame_stream_port: u16,
+    frame_stream_socket: tokio::sync::Mutex<zeromq::PullSocket>,
+    // Latest frame received from stream socket, waiting to be rendered
+    pending_frame: Mutex<Option<ZmqMessage>>,
+    pending_frame_notify: Notify,
+    dropped_frames: AtomicU64,
+    shm_transport: Option<ShmTransport>,
+    pub power: ScreenPower,
+    // Canvas for server side drawing, kept between draw requests
+    canvas: Mutex<Canvas>,
+    // Animation playing task, stopped by any other push
+    animation_task: Mutex<Option<task::JoinHandle<()>>>,
+    color: RwLock<ColorCorrection>,
+    splash: RwLock<SplashSettings>,
+    // Last frame time of stream socket or shm clients, None when no stream is going on
+    last_stream_frame: Mutex<Option<Instant>>,
+    stream_notify: Notify,
+    shm_clients: AtomicUsize,
 }
 
 #[derive(Serialize, Debug)]
@@ -22,6 +57,9 @@ struct ScreenSocketInfo {
     screen_size: (u32, u32),
     bits_per_pixel: u32,
     frame_buffer_port: u16,
+    frame_stream_port: u16,
+    dropped_frames: u64,
+    shm_socket: Option<String>,
     device_type: String,
     description: String,
 }
@@ -37,35 +75,224 @@ impl ScreenSocket {
         let ep = frame_buffer_socket
             .bind(format!("tcp://{}:0", host).as_str())
             .await
-            .unwrap()
+            .map_err(|e| io::Error::other(format!("zmq bind failed: {}", e)))?
             .to_string();
+        let frame_buffer_port = Self::parse_port(&ep);
 
-        // Get port
-        let frame_buffer_port = Regex::new(r":(\d+)$")
-            .ok()
-            .and_then(|re| re.captures(&ep))
-            .and_then(|caps| caps.get(1))
-            .and_then(|port_str| port_str.as_str().parse().ok());
-        let frame_buffer_port = frame_buffer_port.unwrap_or_else(|| {
-            error!("parse port from '{}' failed", ep);
-            0
-        });
+        // Create frame stream zmq socket
+        let mut frame_stream_socket = zeromq::PullSocket::new();
+        let ep = frame_stream_socket
+            .bind(format!("tcp://{}:0", host).as_str())
+            .await
+            .map_err(|e| io::Error::other(format!("zmq bind failed: {}", e)))?
+            .to_string();
+        let frame_stream_port = Self::parse_port(&ep);
+
+        // Create shared memory transport
+        let (width, height) = screen.size();
+        let frame_size = (width * height * screen.bpp() / 8) as usize;
+        let shm_transport = match ShmTransport::new(&id, frame_size) {
+            Ok(shm_transport) => Some(shm_transport),
+            Err(e) => {
+                warn!("{} create shm transport failed: {}", id, e);
+                None
+            }
+        };
+
+        let screen: Arc<dyn Screen + Send + Sync> = Arc::from(screen);
+        let power = ScreenPower::new(&id, Arc::clone(&screen));
+        let canvas = Canvas::new(width, height);
+        let color_settings: ColorSettings =
+            storage::load(&format!("{}/color", id)).unwrap_or_default();
+        let color = ColorCorrection::new(color_settings, screen.bpp());
+        let splash: SplashSettings = storage::load(&format!("{}/splash", id)).unwrap_or_default();
 
         Ok(Self {
             id,
-            screen: Arc::from(screen),
+            screen,
             frame_buffer_port,
-            frame_buffer_socket,
+            frame_buffer_socket: tokio::sync::Mutex::new(frame_buffer_socket),
+            frame_stream_port,
+            frame_stream_socket: tokio::sync::Mutex::new(frame_stream_socket),
+            pending_frame: Mutex::new(None),
+            pending_frame_notify: Notify::new(),
+            dropped_frames: AtomicU64::new(0),
+            shm_transport,
+            power,
+            canvas: Mutex::new(canvas),
+            animation_task: Mutex::new(None),
+            color: RwLock::new(color),
+            splash: RwLock::new(splash),
+            last_stream_frame: Mutex::new(None),
+            stream_notify: Notify::new(),
+            shm_clients: AtomicUsize::new(0),
         })
     }
 
-    pub async fn listen(&mut self) {
-        match self.frame_buffer_socket.recv().await {
+    fn parse_port(ep: &str) -> u16 {
+        Regex::new(r":(\d+)$")
+            .ok()
+            .and_then(|re| re.captures(ep))
+            .and_then(|caps| caps.get(1))
+            .and_then(|port_str| port_str.as_str().parse().ok())
+            .unwrap_or_else(|| {
+                error!("parse port from '{}' failed", ep);
+                0
+            })
+    }
+
+    /// Serve frame buffer, frame stream, shm transport, idle policy and splash until cancelled
+    pub async fn serve(self: &Arc<Self>) {
+        tokio::join!(
+            async {
+                loop {
+                    self.listen().await;
+                }
+            },
+            async {
+                loop {
+                    self.receive_stream().await;
+                }
+            },
+            async {
+                loop {
+                    self.render_stream().await;
+                }
+            },
+            self.serve_shm(),
+            self.power.watch_idle(),
+            self.watch_stream_end(),
+        );
+    }
+
+    /// Push a frame that stays on screen, every one-shot transport goes through here
+    fn push_frame(&self, frame_buffer: &[u8]) -> io::Result<()> {
+        self.stop_animation();
+        *self.last_stream_frame.lock().unwrap() = None;
+        self.render_frame(frame_buffer)
+    }
+
+    /// Push a frame of a stream, splash shows up again when the stream ends
+    fn push_stream_frame(&self, frame_buffer: &[u8]) -> io::Result<()> {
+        self.stop_animation();
+        if self
+            .last_stream_frame
+            .lock()
+            .unwrap()
+            .replace(Instant::now())
+            .is_none()
+        {
+            self.stream_notify.notify_one();
+        }
+        self.render_frame(frame_buffer)
+    }
+
+    fn render_frame(&self, frame_buffer: &[u8]) -> io::Result<()> {
+        if let Err(e) = self.power.on_frame() {
+            warn!("{} wake up screen failed: {}", self.id, e);
+        }
+        let color = self.color.read().unwrap();
+        self.screen.push_frame_buffer(&color.apply(frame_buffer))
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.screen.size()
+    }
+
+    pub fn color_settings(&self) -> ColorSettings {
+        self.color.read().unwrap().settings().clone()
+    }
+
+    /// Apply and persist color correction settings
+    pub fn set_color_settings(&self, settings: ColorSettings) -> io::Result<()> {
+        *self.color.write().unwrap() = ColorCorrection::new(settings.clone(), self.screen.bpp());
+        storage::save(&format!("{}/color", self.id), &settings)
+    }
+
+    /// Draw ops on canvas in order and push the result to screen
+    pub fn draw(&self, ops: &[DrawOp]) -> io::Result<()> {
+        if ops.len() > MAX_DRAW_OPS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} draw ops, must be up to {}", ops.len(), MAX_DRAW_OPS),
+            ));
+        }
+        // Check everything first so a bad op doesn't leave the canvas half drawn
+        let screen_size = self.screen.size();
+        for op in ops {
+            op.validate(screen_size)?;
+        }
+
+        let mut canvas = self.canvas.lock().unwrap();
+        for op in ops {
+            op.apply(&mut canvas)?;
+        }
+        let frame_buffer = canvas.to_frame_buffer(self.screen.bpp())?;
+        self.push_frame(&frame_buffer)
+    }
+
+    /// Show a decoded image, animation keeps playing in a task until another push arrives
+    pub fn show_image(self: &Arc<Self>, image: DecodedImage) -> io::Result<()> {
+        let bpp = self.screen.bpp();
+        match image {
+            DecodedImage::Still(canvas) => {
+                self.push_frame(&canvas.to_frame_buffer(bpp)?)?;
+                // Later draw ops go on top of this image
+                *self.canvas.lock().unwrap() = canvas;
+            }
+            DecodedImage::Animation(frames) => {
+                let frames = frames
+                    .into_iter()
+                    .map(|(canvas, delay)| Ok((canvas.to_frame_buffer(bpp)?, delay)))
+                    .collect::<io::Result<Vec<_>>>()?;
+
+                // Hold the lock from stopping the old animation to storing the new one,
+                // so concurrent requests can't leave an orphan task playing
+                let mut animation_task = self.animation_task.lock().unwrap();
+                if let Some(previous) = animation_task.take() {
+                    debug!("{} stop animation", self.id);
+                    previous.abort();
+                }
+                *self.last_stream_frame.lock().unwrap() = None;
+
+                let screen_socket = Arc::clone(self);
+                let handle = task::spawn(async move {
+                    debug!(
+                        "{} play animation of {} frames",
+                        screen_socket.id,
+                        frames.len()
+                    );
+                    loop {
+                        for (frame_buffer, delay) in &frames {
+                            if let Err(e) = screen_socket.render_frame(frame_buffer) {
+                                error!("{} push animation frame failed: {}", screen_socket.id, e);
+                                return;
+                            }
+                            time::sleep(*delay).await;
+                        }
+                    }
+                });
+                *animation_task = Some(handle);
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_animation(&self) {
+        if let Some(handle) = self.animation_task.lock().unwrap().take() {
+            debug!("{} stop animation", self.id);
+            handle.abort();
+        }
+    }
+
+    pub async fn listen(&self) {
+        let mut frame_buffer_socket = self.frame_buffer_socket.lock().await;
+        match frame_buffer_socket.recv().await {
             Ok(msg) => {
                 let response: String;
 
                 if let Some(data) = msg.get(0) {
-                    match self.screen.push_frame_buffer(data) {
+                    match self.push_frame(data) {
                         Ok(()) => {
                             response = json!({"status": 0, "msg": "ok👌"}).to_string();
                         }
@@ -78,7 +305,7 @@ impl ScreenSocket {
                     response = json!({"status": 1, "msg": "get msg failed"}).to_string();
                 };
 
-                self.frame_buffer_socket
+                frame_buffer_socket
                     .send(response.into())
                     .await
                     .unwrap_or_else(|e| {
@@ -91,14 +318,194 @@ impl ScreenSocket {
         }
     }
 
+    /// Receive a frame from stream socket, replacing any frame not rendered yet
+    async fn receive_stream(&self) {
+        let msg = match self.frame_stream_socket.lock().await.recv().await {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!("ZMQ recv error: {:?}", e);
+                return;
+            }
+        };
+
+        let replaced = self.pending_frame.lock().unwrap().replace(msg);
+        if replaced.is_some() {
+            let dropped = self.dropped_frames.fetch_add(1, Ordering::Relaxed) + 1;
+            debug!("{} dropped stale frame, total: {}", self.id, dropped);
+        }
+        self.pending_frame_notify.notify_one();
+    }
+
+    /// Render the latest frame received from stream socket
+    async fn render_stream(&self) {
+        self.pending_frame_notify.notified().await;
+
+        let Some(msg) = self.pending_frame.lock().unwrap().take() else {
+            return;
+        };
+        let Some(data) = msg.get(0) else {
+            error!("ZMQ recv error: {:#?}", msg);
+            return;
+        };
+
+        if let Err(e) = self.push_stream_frame(data) {
+            error!("{} push stream frame failed: {}", self.id, e);
+        }
+    }
+
+    /// Accept shm clients, each client is served in its own task
+    async fn serve_shm(self: &Arc<Self>) {
+        let Some(shm_transport) = &self.shm_transport else {
+            return std::future::pending().await;
+        };
+
+        let mut clients = JoinSet::new();
+        loop {
+            tokio::select! {
+                result = shm_transport.accept() => match result {
+                    Ok(client) => {
+                        let screen_socket = Arc::clone(self);
+                        clients.spawn(screen_socket.handle_shm_client(client));
+                    }
+                    Err(e) => error!("{} accept shm client failed: {}", self.id, e),
+                },
+                Some(_) = clients.join_next() => {}
+            }
+        }
+    }
+
+    async fn handle_shm_client(self: Arc<Self>, mut client: ShmClient) {
+        self.shm_clients.fetch_add(1, Ordering::AcqRel);
+
+        loop {
+            match client.wait_frame().await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    error!("{} shm client recv error: {}", self.id, e);
+                    break;
+                }
+            }
+
+            let result = self.push_stream_frame(client.frame());
+            if let Err(e) = &result {
+                error!("{} push shm frame failed: {}", self.id, e);
+            }
+            if let Err(e) = client.ack(result.is_ok()).await {
+                error!("{} shm client send error: {}", self.id, e);
+                break;
+            }
+        }
+
+        debug!("{} shm client disconnected", self.id);
+
+        // Last shm client gone, end the stream now instead of waiting for stream timeout
+        if self.shm_clients.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let stream_timeout = Duration::from_secs(self.splash.read().unwrap().stream_timeout);
+            let mut last_stream_frame = self.last_stream_frame.lock().unwrap();
+            if last_stream_frame.is_some() {
+                *last_stream_frame = Instant::now().checked_sub(stream_timeout);
+                self.stream_notify.notify_one();
+            }
+        }
+    }
+
+    /// Show splash when no stream frame arrives within stream timeout, runs until cancelled
+    async fn watch_stream_end(self: &Arc<Self>) {
+        loop {
+            match self.stream_deadline() {
+                Some(deadline) => {
+                    tokio::select! {
+                        _ = time::sleep_until(deadline.into()) => {}
+                        _ = self.stream_notify.notified() => continue,
+                    }
+
+                    // Frames may have arrived while sleeping
+                    let ended = {
+                        let mut last_stream_frame = self.last_stream_frame.lock().unwrap();
+                        let ended = self
+                            .stream_deadline_of(*last_stream_frame)
+                            .is_some_and(|deadline| Instant::now() >= deadline);
+                        if ended {
+                            *last_stream_frame = None;
+                        }
+                        ended
+                    };
+                    if ended {
+                        debug!("{} stream ended", self.id);
+                        self.show_splash().await;
+                    }
+                }
+                None => self.stream_notify.notified().await,
+            }
+        }
+    }
+
+    fn stream_deadline(&self) -> Option<Instant> {
+        let last_stream_frame = *self.last_stream_frame.lock().unwrap();
+        self.stream_deadline_of(last_stream_frame)
+    }
+
+    fn stream_deadline_of(&self, last_stream_frame: Option<Instant>) -> Option<Instant> {
+        let stream_timeout = Duration::from_secs(self.splash.read().unwrap().stream_timeout);
+        last_stream_frame.map(|last_stream_frame| last_stream_frame + stream_timeout)
+    }
+
+    pub fn splash_settings(&self) -> SplashSettings {
+        self.splash.read().unwrap().clone()
+    }
+
+    /// Apply and persist splash settings
+    pub fn set_splash_settings(&self, settings: SplashSettings) -> io::Result<()> {
+        *self.splash.write().unwrap() = settings.clone();
+        self.stream_notify.notify_one();
+        storage::save(&format!("{}/splash", self.id), &settings)
+    }
+
+    /// Show splash content, does nothing in none mode
+    pub async fn show_splash(self: &Arc<Self>) {
+        let settings = self.splash_settings();
+        let id = self.id.clone();
+        let size = self.screen.size();
+        // Image mode decodes a file, keep it off the async workers
+        let image = match task::spawn_blocking(move || splash(&settings, &id, size)).await {
+            Ok(Some(image)) => image,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("{} build splash failed: {}", self.id, e);
+                return;
+            }
+        };
+        if let Err(e) = self.show_image(image) {
+            warn!("{} show splash failed: {}", self.id, e);
+        }
+    }
+
+    /// Show an error card, e.g. when HAL shuts down
+    pub fn show_error_card(&self, message: &str) {
+        let canvas = error_card(&self.id, self.screen.size(), message);
+        if let Err(e) = canvas
+            .to_frame_buffer(self.screen.bpp())
+            .and_then(|frame_buffer| self.push_frame(&frame_buffer))
+        {
+            warn!("{} show error card failed: {}", self.id, e);
+        }
+    }
+
     pub fn get_device_info(&self) -> String {
         let screen_socket_info = ScreenSocketInfo {
             screen_size: self.screen.size(),
             bits_per_pixel: self.screen.bpp(),
             frame_buffer_port: self.frame_buffer_port,
+            frame_stream_port: self.frame_stream_port,
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+            shm_socket: self
+                .shm_transport
+                .as_ref()
+                .map(|shm_transport| shm_transport.socket_name()),
             device_type: self.screen.device_type(),
             description: format!(
-                "{} Render a frame by sending a raw buffer to <frame_buffer_port> using a ZMQ REP socket.",
+                "{} Render a frame by sending a raw buffer to <frame_buffer_port> using a ZMQ REP socket, or stream frames to <frame_stream_port> using a ZMQ PUSH socket (latest frame wins, no reply). Local clients can also connect to <shm_socket> to get a shared memory frame buffer.",
                 Emoji::PUBLISH
             ),
         };
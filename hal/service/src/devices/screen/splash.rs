@@ -0,0 +1,168 @@
+use crate::devices::screen::{Canvas, DecodedImage, ResizeMode, decode_image};
+use embedded_graphics::{
+    mono_font::{
+        MonoTextStyle,
+        ascii::{FONT_6X10, FONT_8X13, FONT_10X20},
+    },
+    pixelcolor::Rgb888,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+use log::warn;
+use nix::{ifaddrs::getifaddrs, net::if_::InterfaceFlags, unistd::gethostname};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Splash settings, persisted per screen
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SplashSettings {
+    pub mode: SplashMode,
+    pub image: Option<PathBuf>, // used by image mode
+    pub stream_timeout: u64,    // seconds without stream frames before showing splash again
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SplashMode {
+    None,  // leave the screen as it is
+    Info,  // generated info card
+    Image, // image file, animated GIF keeps playing
+}
+
+impl FromStr for SplashMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(SplashMode::None),
+            "info" => Ok(SplashMode::Info),
+            "image" => Ok(SplashMode::Image),
+            _ => Err(format!(
+                "invalid splash mode '{}', expected none, info or image",
+                s
+            )),
+        }
+    }
+}
+
+impl Default for SplashSettings {
+    fn default() -> Self {
+        Self {
+            mode: SplashMode::Info,
+            image: None,
+            stream_timeout: 3,
+        }
+    }
+}
+
+/// Build splash content shown on startup and when the last stream ends
+pub fn splash(settings: &SplashSettings, id: &str, size: (u32, u32)) -> Option<DecodedImage> {
+    match settings.mode {
+        SplashMode::None => None,
+        SplashMode::Info => Some(DecodedImage::Still(info_card(id, size))),
+        SplashMode::Image => {
+            let image = settings
+                .image
+                .as_ref()
+                .ok_or("no image path set".to_string())
+                .and_then(|path| fs::read(path).map_err(|e| e.to_string()))
+                .and_then(|data| {
+                    decode_image(&data, size, ResizeMode::Fill).map_err(|e| e.to_string())
+                });
+            match image {
+                Ok(image) => Some(image),
+                Err(e) => {
+                    warn!("{} load splash image failed: {}, use info card", id, e);
+                    Some(DecodedImage::Still(info_card(id, size)))
+                }
+            }
+        }
+    }
+}
+
+/// Info card with hostname, ip and HAL version
+pub fn info_card(id: &str, size: (u32, u32)) -> Canvas {
+    let hostname = gethostname()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or("unknown".to_string());
+    let ips = local_ips();
+
+    let mut lines = vec![format!("host  {}", hostname)];
+    if ips.is_empty() {
+        lines.push("ip    offline".to_string());
+    }
+    for ip in ips {
+        lines.push(format!("ip    {}", ip));
+    }
+    lines.push(format!("hal   v{}", env!("CARGO_PKG_VERSION")));
+    lines.push(format!("{}  {}x{}", id, size.0, size.1));
+
+    card(size, Rgb888::new(0x22, 0x33, 0x88), "Rebecca", &lines)
+}
+
+/// Error card shown when HAL shuts down
+pub fn error_card(id: &str, size: (u32, u32), message: &str) -> Canvas {
+    card(
+        size,
+        Rgb888::new(0xaa, 0x22, 0x22),
+        "Rebecca HAL",
+        &[message.to_string(), id.to_string()],
+    )
+}
+
+fn card(size: (u32, u32), accent: Rgb888, title: &str, lines: &[String]) -> Canvas {
+    let (width, height) = size;
+    let mut canvas = Canvas::new(width, height);
+
+    // Small screens get small fonts
+    let (title_font, body_font) = if width >= 240 {
+        (&FONT_10X20, &FONT_8X13)
+    } else {
+        (&FONT_8X13, &FONT_6X10)
+    };
+    let title_height = title_font.character_size.height + 8;
+    let line_height = body_font.character_size.height + 4;
+
+    let _ = Rectangle::new(Point::zero(), Size::new(width, title_height))
+        .into_styled(PrimitiveStyle::with_fill(accent))
+        .draw(&mut canvas);
+    let _ = Text::with_baseline(
+        title,
+        Point::new(6, 4),
+        MonoTextStyle::new(title_font, Rgb888::WHITE),
+        Baseline::Top,
+    )
+    .draw(&mut canvas);
+
+    for (i, line) in lines.iter().enumerate() {
+        let _ = Text::with_baseline(
+            line,
+            Point::new(6, (title_height + 6 + line_height * i as u32) as i32),
+            MonoTextStyle::new(body_font, Rgb888::WHITE),
+            Baseline::Top,
+        )
+        .draw(&mut canvas);
+    }
+
+    canvas
+}
+
+fn local_ips() -> Vec<String> {
+    let Ok(addrs) = getifaddrs() else {
+        return vec![];
+    };
+    addrs
+        .filter(|addr| {
+            addr.flags.contains(InterfaceFlags::IFF_UP)
+                && !addr.flags.contains(InterfaceFlags::IFF_LOOPBACK)
+        })
+        .filter_map(|addr| {
+            let ip = addr.address?.as_sockaddr_in()?.ip();
+            Some(format!("{} ({})", ip, addr.interface_name))
+        })
+        .collect()
+}
@@ -6,4 +6,5 @@ pub trait Screen {
     fn size(&self) -> (u32, u32);
     fn device_type(&self) -> String;
     fn push_frame_buffer(&self, frame_buffer: &[u8]) -> io::Result<()>;
+    fn set_power(&self, on: bool) -> io::Result<()>; // false to blank the panel
 }
@@ -3,9 +3,14 @@ mod devices;
 mod server;
 
 use clap::Parser;
-use devices::{start_backlight_service, start_imu_service, start_screen_service};
+use common::storage;
+use devices::{
+    ReplayOptions, start_backlight_service, start_imu_service, start_light_service,
+    start_screen_service,
+};
 use env_logger::Env;
 use log::{error, info};
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::{signal, sync::Notify, task::JoinHandle};
 
@@ -20,6 +25,10 @@ struct Args {
     #[arg(short, long, default_value_t = 12580)]
     port: u16,
 
+    /// Directory to persist device settings [default: ~/.config/rebecca-hal]
+    #[arg(long)]
+    config_dir: Option<PathBuf>,
+
     /// Verbose mode
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
@@ -32,9 +41,25 @@ struct Args {
     #[arg(long, default_value_t = false)]
     mock_imu: bool,
 
+    /// Replay an IMU recording (from /imuN/record/start) as an IMU device
+    #[arg(long, value_name = "FILE")]
+    replay_imu: Option<PathBuf>,
+
+    /// Replay speed, 2.0 replays twice as fast
+    #[arg(long, default_value_t = 1.0)]
+    replay_speed: f64,
+
+    /// Start the replay over when it reaches the end
+    #[arg(long, default_value_t = false)]
+    replay_loop: bool,
+
     /// Create mock backlight for api test
     #[arg(long, default_value_t = false)]
     mock_backlight: bool,
+
+    /// Create mock ambient light sensor for api test
+    #[arg(long, default_value_t = false)]
+    mock_light: bool,
 }
 
 #[tokio::main]
@@ -49,6 +74,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }))
     .init();
 
+    // Init storage for persisted device settings
+    storage::init_storage(args.config_dir.unwrap_or_else(storage::default_storage_dir));
+
     // Create shutdown notify and tasks
     let shutdown_notify = Arc::new(Notify::new());
     let mut tasks: Vec<JoinHandle<()>> = Vec::new();
@@ -66,11 +94,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Start IMU service
-    match start_imu_service(args.host.as_str(), shutdown_notify.clone(), args.mock_imu).await {
+    let replay_imu = args.replay_imu.map(|path| ReplayOptions {
+        path,
+        speed: args.replay_speed,
+        looping: args.replay_loop,
+    });
+    match start_imu_service(
+        args.host.as_str(),
+        shutdown_notify.clone(),
+        args.mock_imu,
+        replay_imu,
+    )
+    .await
+    {
         Ok(imu_handle) => tasks.push(imu_handle),
         Err(e) => error!("failed to start imu service: {}", e),
     }
 
+    // Start light service
+    match start_light_service(args.host.as_str(), shutdown_notify.clone(), args.mock_light).await {
+        Ok(light_handle) => tasks.push(light_handle),
+        Err(e) => error!("failed to start light service: {}", e),
+    }
+
     // Start backlight service
     match start_backlight_service(
         args.host.as_str(),
@@ -2,6 +2,7 @@
 
 # Rebecca HAL 卸载脚本
 # 此脚本将停止并移除 rebecca-hal-service 服务，并卸载二进制文件
+# 设备设置（校准、安装方向、背光曲线等）和录制默认保留，加 --purge 才一起删除
 
 set -e
 
@@ -14,6 +15,18 @@ NC='\033[0m' # No Color
 SERVICE_NAME="rebecca-hal-service"
 BINARY_NAME="rebecca-hal-service"
 SERVICE_FILE="/etc/systemd/system/${SERVICE_NAME}.service"
+CONFIG_DIR="$HOME/.config/rebecca-hal"
+
+PURGE=false
+for arg in "$@"; do
+    case "$arg" in
+        --purge) PURGE=true ;;
+        *)
+            echo -e "${RED}未知参数: $arg，用法: $0 [--purge]${NC}"
+            exit 1
+            ;;
+    esac
+done
 
 echo -e "${GREEN}开始卸载 Rebecca HAL...${NC}"
 
@@ -51,7 +64,17 @@ else
     echo "二进制文件不存在: $CARGO_BIN_PATH"
 fi
 
-echo -e "${YELLOW}5. 清理检查...${NC}"
+echo -e "${YELLOW}5. 处理设备设置...${NC}"
+if [ ! -d "$CONFIG_DIR" ]; then
+    echo "设备设置不存在: $CONFIG_DIR"
+elif [ "$PURGE" = true ]; then
+    rm -rf "$CONFIG_DIR"
+    echo "已删除设备设置和录制: $CONFIG_DIR"
+else
+    echo "保留设备设置和录制: $CONFIG_DIR（删除请加 --purge）"
+fi
+
+echo -e "${YELLOW}6. 清理检查...${NC}"
 # 检查是否还有残留的进程
 if pgrep -f $BINARY_NAME > /dev/null; then
     echo -e "${YELLOW}发现残留进程，正在终止...${NC}"
@@ -69,6 +92,11 @@ echo "已完成以下操作:"
 echo "- 停止并禁用了 $SERVICE_NAME 服务"
 echo "- 删除了服务配置文件"
 echo "- 卸载了二进制文件"
+if [ "$PURGE" = true ]; then
+    echo "- 删除了设备设置和录制"
+else
+    echo "- 保留了设备设置和录制"
+fi
 echo "- 清理了相关进程"
 echo ""
 echo -e "${GREEN}Rebecca HAL 已完全卸载${NC}"